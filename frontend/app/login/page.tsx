@@ -16,17 +16,11 @@ function LoginContent() {
   const [isLoading, setIsLoading] = useState(false);
 
   useEffect(() => {
-    // Check if we have a token from backend redirect (after OAuth callback)
-    const token = searchParams.get('token');
-    const email = searchParams.get('email');
+    // Check if we have a one-time login code from backend redirect (after OAuth callback)
+    const loginCode = searchParams.get('login_code');
 
-    if (token && email) {
-      // Token received from backend redirect
-      setIsLoading(true);
-      localStorage.setItem('jwt_token', token);
-      localStorage.setItem('user_email', decodeURIComponent(email));
-      // Clear URL params and redirect
-      router.replace('/');
+    if (loginCode) {
+      handleLoginCode(loginCode);
       return;
     }
 
@@ -55,6 +49,22 @@ function LoginContent() {
     }
   };
 
+  const handleLoginCode = async (loginCode: string) => {
+    try {
+      setIsLoading(true);
+      // Exchange the code for a session (also sets the HttpOnly session cookie)
+      const { access_token, email } = await api.exchangeLoginCode(loginCode);
+      localStorage.setItem('jwt_token', access_token);
+      localStorage.setItem('user_email', email);
+      // Clear URL params and redirect
+      router.replace('/');
+    } catch (error) {
+      console.error('Failed to exchange login code:', error);
+      alert('Failed to complete authentication');
+      setIsLoading(false);
+    }
+  };
+
   const handleCallback = async (code: string, state: string) => {
     try {
       setIsLoading(true);
@@ -85,7 +95,7 @@ function LoginContent() {
           {isLoading ? (
             <div className="text-center">
               <div className="mb-4 text-sm text-muted-foreground">
-                {searchParams.get('token') ? 'Completing login...' : 'Connecting to Google...'}
+                {searchParams.get('login_code') ? 'Completing login...' : 'Connecting to Google...'}
               </div>
             </div>
           ) : (
@@ -5,11 +5,13 @@ import { Card, CardContent, CardDescription, CardHeader, CardTitle } from '@/com
 import { Button } from '@/components/ui/button';
 import { useRouter } from 'next/navigation';
 import { LogOut } from 'lucide-react';
+import { api } from '@/lib/api';
 
 export default function SettingsPage() {
   const router = useRouter();
 
-  const handleLogout = () => {
+  const handleLogout = async () => {
+    await api.logout().catch(() => undefined);
     localStorage.removeItem('jwt_token');
     localStorage.removeItem('user_email');
     router.push('/login');
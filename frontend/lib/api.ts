@@ -1,5 +1,7 @@
 const API_BASE_URL = process.env.NEXT_PUBLIC_API_URL!;
 
+export type EmailCategory = "support" | "sales" | "billing" | "personal" | "newsletter" | "spam";
+
 export interface Email {
   id: number;
   gmail_id: string;
@@ -10,20 +12,166 @@ export interface Email {
   subject: string | null;
   snippet: string | null;
   has_body: boolean;
+  labels: string[] | null;
+  category: EmailCategory | null;
+  urgency: number | null;
+  needs_reply: boolean | null;
   fetched_at: string;
+  replied_at: string | null;
+  replied_draft_id: number | null;
 }
 
 export interface FetchUnreadResponse {
   fetched: boolean;
+  auto_drafts: number[];
 }
 
 export interface GenerateDraftResponse {
   draft_id: number;
   content: string;
+  example_draft_ids: number[];
+  sources: Omit<DraftSource, "rank">[];
+  style_applied: boolean;
+  generation: DraftGeneration;
+  warnings: DraftWarning[];
+  attempts: number;
+}
+
+export interface DraftWarning {
+  kind: "placeholder" | "unsupported_promise" | "email_header" | "language";
+  severity: "high" | "medium";
+  message: string;
+  excerpt: string | null;
+}
+
+export interface DraftGeneration {
+  prompt_id: number | null;
+  prompt_name: string | null;
+  prompt_version: number | null;
+  model: string;
+  temperature: number | null;
+  prompt_tokens: number | null;
+  completion_tokens: number | null;
+}
+
+export interface PromptVersion {
+  id: number;
+  workspace_id: number | null;
+  name: "reply";
+  version: number;
+  description: string | null;
+  system: string;
+  body: string;
+  model: string;
+  temperature: number;
+  max_tokens: number;
+  created_by: number | null;
+  created_at: string;
+}
+
+export type PromptVersionInput = Pick<PromptVersion, "name" | "system" | "body"> &
+  Partial<Pick<PromptVersion, "description" | "model" | "temperature" | "max_tokens">> & { activate?: boolean };
+
+export interface DraftSource {
+  rank: number;
+  chunk_id: number | null;
+  document_id: number | null;
+  document_title: string;
+  heading: string | null;
+  content: string;
+  score: number;
+}
+
+export type KnowledgeFormat = "markdown" | "text" | "pdf";
+
+export interface KnowledgeDocumentSummary {
+  id: number;
+  title: string;
+  format: KnowledgeFormat;
+  chars: number;
+  chunk_count: number;
+  created_by: number | null;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface KnowledgeDocument {
+  id: number;
+  workspace_id: number;
+  title: string;
+  format: KnowledgeFormat;
+  content: string;
+  content_hash: string;
+  created_by: number | null;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface KnowledgeDocumentInput {
+  title: string;
+  format?: KnowledgeFormat;
+  content: string;
+}
+
+export interface KnowledgeChunk {
+  chunk_id: number;
+  document_id: number;
+  document_title: string;
+  heading: string | null;
+  content: string;
+  score: number;
+}
+
+export interface TemplateField {
+  name: string;
+  label?: string | null;
+  default?: string | null;
+}
+
+export interface ReplyTemplate {
+  id: number;
+  account_id: number;
+  folder: string | null;
+  name: string;
+  body: string;
+  fields: TemplateField[];
+  created_by: number | null;
+  created_at: string;
+  updated_at: string;
+  variables: string[];
+}
+
+export type ReplyTemplateInput = Pick<ReplyTemplate, "name" | "body"> & Partial<Pick<ReplyTemplate, "folder" | "fields">>;
+
+export interface RenderedTemplate {
+  content: string;
+  missing: string[];
+}
+
+export type Formality = "formal" | "neutral" | "casual";
+
+export interface StyleProfile {
+  user_id: number;
+  greeting: string | null;
+  sign_off: string | null;
+  typical_words: number | null;
+  formality: Formality | null;
+  common_phrases: string[];
+  notes: string | null;
+  enabled: boolean;
+  sample_count: number;
+  source_account_id: number | null;
+  analyzed_at: string | null;
+  updated_at: string;
 }
 
+export type StyleProfileInput = Partial<
+  Pick<StyleProfile, "greeting" | "sign_off" | "typical_words" | "formality" | "common_phrases" | "notes" | "enabled">
+>;
+
 export interface SendDraftResponse {
   sent_gmail_id: string;
+  post_send: { applied: boolean; actions?: TriageActions; error?: string };
 }
 
 
@@ -31,7 +179,33 @@ export interface SendDraftResponse {
 export interface EmailDetail extends Email {
   body_text: string | null;
   body_html: string | null;
-  labels: string[] | null;
+}
+
+export type WorkspaceRole = "owner" | "editor" | "approver" | "viewer";
+
+export interface MailboxAccount {
+  id: number;
+  email: string;
+  is_primary: boolean;
+  is_owner: boolean;
+  workspace_id: number | null;
+  role: WorkspaceRole;
+  needs_reauth: boolean;
+  created_at: string | null;
+}
+
+export interface Workspace {
+  id: number;
+  name: string;
+  role: WorkspaceRole;
+  created_at: string;
+}
+
+export interface WorkspaceMember {
+  user_id: number;
+  email: string;
+  role: WorkspaceRole;
+  created_at: string;
 }
 
 export interface Draft {
@@ -40,7 +214,194 @@ export interface Draft {
   content: string | null;
   tone: string | null;
   status: string | null;
+  revision: number;
+  comment_count?: number;
+  open_threads?: number;
+  created_at: string;
+}
+
+export interface DraftComment {
+  id: number;
+  draft_id: number;
+  parent_id: number | null;
+  author_id: number | null;
+  author_email: string | null;
+  body: string;
+  anchor_start: number | null;
+  anchor_end: number | null;
+  anchor_revision: number | null;
+  anchor_text: string | null;
+  resolved_at: string | null;
+  resolved_by: number | null;
+  mentions: string[];
   created_at: string;
+  updated_at: string;
+  replies?: DraftComment[];
+}
+
+export interface ReviewQueueItem {
+  id: number;
+  email_id: number;
+  sender: string | null;
+  subject: string | null;
+  status: string;
+  revision: number;
+  assigned_to: number | null;
+  assignee_email: string | null;
+  claimed_by: number | null;
+  claimed_by_email: string | null;
+  created_at: string;
+  age_minutes: number;
+  sla_minutes: number;
+  overdue: boolean;
+}
+
+export interface RuleConditions {
+  senders?: string[];
+  domains?: string[];
+  subject_regex?: string | null;
+  labels?: string[];
+  keywords?: string[];
+}
+
+export interface TriageActions {
+  add_labels?: string[];
+  remove_labels?: string[];
+  archive?: boolean;
+  read?: boolean | null;
+  star?: boolean | null;
+  trash?: boolean;
+}
+
+export interface PostSendSettings {
+  mark_read: boolean;
+  label: string | null;
+  archive: boolean;
+}
+
+export interface Signature {
+  plain: string | null;
+  html: string | null;
+  enabled: boolean;
+  source: "manual" | "gmail";
+  updated_at: string;
+}
+
+export interface DraftRule {
+  id: number;
+  account_id: number;
+  name: string;
+  enabled: boolean;
+  priority: number;
+  conditions: RuleConditions;
+  actions: TriageActions;
+  generate_draft: boolean;
+  tone: string;
+  instructions: string | null;
+  template_id: number | null;
+  created_by: number | null;
+  created_at: string;
+  updated_at: string;
+}
+
+export type DraftRuleInput = Pick<DraftRule, "name" | "conditions"> &
+  Partial<Pick<DraftRule, "enabled" | "priority" | "actions" | "generate_draft" | "tone" | "instructions" | "template_id">>;
+
+export interface RuleDryRunResponse {
+  evaluated: number;
+  rules: {
+    rule_id: number | null;
+    name: string | null;
+    enabled: boolean;
+    matches: { email_id: number; sender: string | null; subject: string | null; fetched_at: string | null; has_draft: boolean }[];
+  }[];
+}
+
+export interface ConversationSummary {
+  scope: "email" | "thread";
+  id: string;
+  message_count: number;
+  summary: string;
+  key_asks: string[];
+  deadlines: string[];
+  open_questions: string[];
+  cached: boolean;
+  generated_at: string;
+}
+
+export interface SimilarReply {
+  email_id: number;
+  subject: string | null;
+  sender: string | null;
+  draft_id: number;
+  reply: string;
+  score: number;
+}
+
+export type SemanticSearchResult =
+  | {
+      type: "email";
+      id: number;
+      score: number;
+      thread_id: string | null;
+      sender: string | null;
+      subject: string | null;
+      snippet: string | null;
+      fetched_at: string;
+    }
+  | {
+      type: "draft";
+      id: number;
+      score: number;
+      email_id: number | null;
+      status: string;
+      subject: string | null;
+      snippet: string | null;
+      created_at: string;
+    };
+
+export interface ReindexEmbeddingsResponse {
+  model: string;
+  indexed_emails: number;
+  indexed_drafts: number;
+  pruned: number;
+  remaining: number;
+}
+
+export interface SearchResults {
+  emails: {
+    id: number;
+    gmail_id: string;
+    thread_id: string | null;
+    sender: string | null;
+    subject: string | null;
+    snippet: string | null;
+    headline: string | null;
+    labels: string[] | null;
+    category: EmailCategory | null;
+    has_draft: boolean;
+    fetched_at: string;
+    rank: number;
+  }[];
+  drafts: {
+    id: number;
+    email_id: number | null;
+    status: string;
+    revision: number;
+    sender: string | null;
+    subject: string | null;
+    headline: string | null;
+    created_at: string;
+    rank: number;
+  }[];
+}
+
+export interface ApproveDraftResponse {
+  approved: boolean;
+  status: string;
+  revision: number;
+  approvals: number;
+  required_approvals: number;
 }
 
 class ApiClient {
@@ -49,7 +410,21 @@ class ApiClient {
     return localStorage.getItem("jwt_token");
   }
 
-  private async request<T>(endpoint: string, options: RequestInit = {}): Promise<T> {
+  private async refreshSession(): Promise<boolean> {
+    // The refresh token lives in an HttpOnly cookie scoped to /auth
+    const response = await fetch(`${API_BASE_URL}/auth/refresh`, {
+      method: "POST",
+      credentials: "include",
+    }).catch(() => null);
+
+    if (!response || !response.ok) return false;
+
+    const { access_token } = await response.json();
+    localStorage.setItem("jwt_token", access_token);
+    return true;
+  }
+
+  private async request<T>(endpoint: string, options: RequestInit = {}, retried = false): Promise<T> {
     const token = this.getToken();
 
     // FIXED: use Record<string, string>
@@ -62,12 +437,19 @@ class ApiClient {
       headers["Authorization"] = `Bearer ${token}`;
     }
 
+    // Which connected mailbox to operate on (defaults to the login mailbox)
+    const mailboxAccount = typeof window !== "undefined" ? localStorage.getItem("mailbox_account_id") : null;
+    if (mailboxAccount) {
+      headers["X-Mailbox-Account"] = mailboxAccount;
+    }
+
     const url = `${API_BASE_URL}${endpoint}`;
 
     try {
       const response = await fetch(url, {
         ...options,
         headers,
+        credentials: "include",
       });
 
       if (!response.ok) {
@@ -76,6 +458,9 @@ class ApiClient {
         console.error(`API Error [${response.status}]:`, errorText);
 
         if (response.status === 401) {
+          if (!retried && (await this.refreshSession())) {
+            return this.request<T>(endpoint, options, true);
+          }
           localStorage.removeItem("jwt_token");
           if (typeof window !== "undefined") {
             window.location.href = "/login";
@@ -103,15 +488,162 @@ class ApiClient {
     return this.request<{ auth_url: string; state: string }>("/auth/google/start");
   }
 
+  async exchangeLoginCode(code: string) {
+    return this.request<{ access_token: string; email: string }>("/auth/session", {
+      method: "POST",
+      body: JSON.stringify({ code }),
+    });
+  }
+
+  async logout() {
+    return this.request("/auth/logout", { method: "POST" });
+  }
+
+  async logoutEverywhere() {
+    return this.request("/auth/logout-all", { method: "POST" });
+  }
+
   async googleCallback(code: string, state: string) {
     return this.request<{ jwt: string; email: string }>(
       `/auth/google/callback?code=${code}&state=${state}`
     );
   }
 
+  // Mailbox accounts
+  async listAccounts(): Promise<MailboxAccount[]> {
+    return this.request("/accounts");
+  }
+
+  async connectAccount(loginHint?: string) {
+    return this.request<{ auth_url: string; state: string }>("/accounts/connect", {
+      method: "POST",
+      body: JSON.stringify({ login_hint: loginHint }),
+    });
+  }
+
+  async disconnectAccount(id: number) {
+    return this.request(`/accounts/${id}`, { method: "DELETE" });
+  }
+
+  // Workspaces
+  async getPostSendSettings(accountId: number): Promise<PostSendSettings> {
+    return this.request(`/accounts/${accountId}/post-send`);
+  }
+
+  async setPostSendSettings(accountId: number, settings: PostSendSettings): Promise<PostSendSettings> {
+    return this.request<PostSendSettings>(`/accounts/${accountId}/post-send`, {
+      method: "PUT",
+      body: JSON.stringify(settings),
+    });
+  }
+
+  async getSignature(accountId: number): Promise<Signature> {
+    return this.request(`/accounts/${accountId}/signature`);
+  }
+
+  async setSignature(accountId: number, signature: { plain?: string | null; html?: string | null; enabled?: boolean }): Promise<Signature> {
+    return this.request<Signature>(`/accounts/${accountId}/signature`, {
+      method: "PUT",
+      body: JSON.stringify(signature),
+    });
+  }
+
+  async importSignature(accountId: number): Promise<Signature> {
+    return this.request<Signature>(`/accounts/${accountId}/signature/import`, { method: "POST" });
+  }
+
+  async listWorkspaces(): Promise<Workspace[]> {
+    return this.request("/workspaces");
+  }
+
+  async createWorkspace(name: string) {
+    return this.request<{ id: number; name: string; role: WorkspaceRole }>("/workspaces", {
+      method: "POST",
+      body: JSON.stringify({ name }),
+    });
+  }
+
+  async listWorkspaceMembers(workspaceId: number): Promise<WorkspaceMember[]> {
+    return this.request(`/workspaces/${workspaceId}/members`);
+  }
+
+  async inviteToWorkspace(workspaceId: number, email: string, role: WorkspaceRole) {
+    return this.request<{ id: number; token: string }>(`/workspaces/${workspaceId}/invitations`, {
+      method: "POST",
+      body: JSON.stringify({ email, role }),
+    });
+  }
+
+  async acceptInvitation(token: string) {
+    return this.request<{ workspace_id: number; role: WorkspaceRole }>("/invitations/accept", {
+      method: "POST",
+      body: JSON.stringify({ token }),
+    });
+  }
+
+  async shareMailbox(workspaceId: number, accountId: number) {
+    return this.request(`/workspaces/${workspaceId}/mailboxes`, {
+      method: "POST",
+      body: JSON.stringify({ account_id: accountId }),
+    });
+  }
+
+  // Knowledge base
+  async listKnowledgeDocuments(workspaceId: number): Promise<KnowledgeDocumentSummary[]> {
+    return this.request(`/workspaces/${workspaceId}/knowledge`);
+  }
+
+  async getKnowledgeDocument(workspaceId: number, id: number): Promise<KnowledgeDocument> {
+    return this.request(`/workspaces/${workspaceId}/knowledge/${id}`);
+  }
+
+  async createKnowledgeDocument(workspaceId: number, doc: KnowledgeDocumentInput) {
+    return this.request<{ id: number; title: string; format: KnowledgeFormat; chunk_count: number; created_at: string }>(
+      `/workspaces/${workspaceId}/knowledge`,
+      { method: "POST", body: JSON.stringify(doc) }
+    );
+  }
+
+  async updateKnowledgeDocument(workspaceId: number, id: number, doc: KnowledgeDocumentInput) {
+    return this.request<{ id: number; title: string; format: KnowledgeFormat; reindexed: boolean; updated_at: string }>(
+      `/workspaces/${workspaceId}/knowledge/${id}`,
+      { method: "PUT", body: JSON.stringify(doc) }
+    );
+  }
+
+  async deleteKnowledgeDocument(workspaceId: number, id: number) {
+    return this.request<{ deleted: boolean }>(`/workspaces/${workspaceId}/knowledge/${id}`, { method: "DELETE" });
+  }
+
+  async searchKnowledge(workspaceId: number, q: string, limit?: number): Promise<{ chunks: KnowledgeChunk[] }> {
+    const query = new URLSearchParams({ q });
+    if (limit) query.set("limit", String(limit));
+    return this.request(`/workspaces/${workspaceId}/knowledge/search?${query}`);
+  }
+
+  async reindexKnowledge(workspaceId: number) {
+    return this.request<{ model: string; documents: number; chunks: number }>(
+      `/workspaces/${workspaceId}/knowledge/reindex`,
+      { method: "POST" }
+    );
+  }
+
   // Emails
-  async listEmails(): Promise<Email[]> {
-    return this.request("/emails");
+  async listEmails(
+    params: { category?: EmailCategory; needs_reply?: boolean; min_urgency?: number; sort?: "fetched_at" | "urgency" } = {}
+  ): Promise<Email[]> {
+    const query = new URLSearchParams(
+      Object.entries(params)
+        .filter(([, v]) => v !== undefined)
+        .map(([k, v]) => [k, String(v)])
+    ).toString();
+    return this.request(`/emails${query ? `?${query}` : ""}`);
+  }
+
+  async classifyEmail(id: number) {
+    return this.request<{ category: EmailCategory; urgency: number; needs_reply: boolean }>(`/emails/${id}/classify`, {
+      method: "POST",
+    });
   }
 
   async getEmail(id: number): Promise<EmailDetail> {
@@ -122,6 +654,60 @@ class ApiClient {
   return this.request<FetchUnreadResponse>("/internal/fetch-unread", { method: "POST" });
 }
 
+  async triageEmail(id: number, actions: TriageActions) {
+    return this.request<{ emails: { id: number; labels: string[] | null }[] }>(`/emails/${id}/actions`, {
+      method: "POST",
+      body: JSON.stringify(actions),
+    });
+  }
+
+  async triageEmails(emailIds: number[], actions: TriageActions) {
+    return this.request<{ emails: { id: number; labels: string[] | null }[] }>("/emails/actions", {
+      method: "POST",
+      body: JSON.stringify({ email_ids: emailIds, ...actions }),
+    });
+  }
+
+  async search(q: string, params: { in?: "all" | "emails" | "drafts"; limit?: number } = {}): Promise<SearchResults> {
+    const query = new URLSearchParams({ q });
+    if (params.in) query.set("in", params.in);
+    if (params.limit) query.set("limit", String(params.limit));
+    return this.request<SearchResults>(`/search?${query}`);
+  }
+
+  async semanticSearch(
+    q: string,
+    params: { in?: "all" | "emails" | "drafts"; limit?: number } = {}
+  ): Promise<{ results: SemanticSearchResult[] }> {
+    const query = new URLSearchParams({ q });
+    if (params.in) query.set("in", params.in);
+    if (params.limit) query.set("limit", String(params.limit));
+    return this.request(`/search/semantic?${query}`);
+  }
+
+  async similarReplies(emailId: number, limit = 3): Promise<{ replies: SimilarReply[] }> {
+    return this.request(`/emails/${emailId}/similar-replies?limit=${limit}`);
+  }
+
+  async reindexEmbeddings(limit?: number): Promise<ReindexEmbeddingsResponse> {
+    return this.request<ReindexEmbeddingsResponse>(`/embeddings/reindex${limit ? `?limit=${limit}` : ""}`, {
+      method: "POST",
+    });
+  }
+
+  async summarizeThread(threadId: string, refresh = false): Promise<ConversationSummary> {
+    return this.request<ConversationSummary>(
+      `/threads/${encodeURIComponent(threadId)}/summary${refresh ? "?refresh=true" : ""}`,
+      { method: "POST" }
+    );
+  }
+
+  async summarizeEmail(id: number, refresh = false): Promise<ConversationSummary> {
+    return this.request<ConversationSummary>(`/emails/${id}/summary${refresh ? "?refresh=true" : ""}`, {
+      method: "POST",
+    });
+  }
+
   async fetchEmail(gmailId: string) {
     return this.request(`/internal/fetch/${gmailId}`, { method: "POST" });
   }
@@ -131,14 +717,24 @@ class ApiClient {
     return this.request("/drafts");
   }
 
- async generateDraft(emailId: number, tone: string): Promise<GenerateDraftResponse> {
+ async generateDraft(
+  emailId: number,
+  tone: string,
+  options: { useExamples?: boolean; useKnowledge?: boolean; useStyle?: boolean } = {}
+): Promise<GenerateDraftResponse> {
   return this.request<GenerateDraftResponse>("/drafts/generate", {
     method: "POST",
-    body: JSON.stringify({ email_id: emailId, tone }),
+    body: JSON.stringify({
+      email_id: emailId,
+      tone,
+      use_examples: options.useExamples,
+      use_knowledge: options.useKnowledge,
+      use_style: options.useStyle,
+    }),
   });
 }
 
-  async getDraft(id: number): Promise<Draft> {
+  async getDraft(id: number): Promise<Draft & { sources: DraftSource[]; generation: DraftGeneration | null; warnings: DraftWarning[] }> {
     return this.request(`/drafts/${id}`);
   }
 
@@ -149,9 +745,168 @@ class ApiClient {
     });
   }
 
-  async approveDraft(id: number) {
-    return this.request(`/drafts/${id}/approve`, {
+  async approveDraft(id: number, overrideGuardrails = false): Promise<ApproveDraftResponse> {
+    return this.request<ApproveDraftResponse>(`/drafts/${id}/approve`, {
+      method: "POST",
+      body: JSON.stringify({ override_guardrails: overrideGuardrails }),
+    });
+  }
+
+  async assignDraft(draftId: number, userId: number | null) {
+    return this.request(`/drafts/${draftId}/assign`, {
+      method: "POST",
+      body: JSON.stringify({ user_id: userId }),
+    });
+  }
+
+  async claimDraft(draftId: number) {
+    return this.request(`/drafts/${draftId}/claim`, { method: "POST" });
+  }
+
+  async unclaimDraft(draftId: number) {
+    return this.request(`/drafts/${draftId}/unclaim`, { method: "POST" });
+  }
+
+  async reviewQueue(params: { assignee?: string; status?: string } = {}) {
+    const query = new URLSearchParams(
+      Object.entries(params).filter(([, v]) => v !== undefined) as [string, string][]
+    ).toString();
+    return this.request<ReviewQueueItem[]>(`/review-queue${query ? `?${query}` : ""}`);
+  }
+
+  async listComments(draftId: number): Promise<DraftComment[]> {
+    return this.request(`/drafts/${draftId}/comments`);
+  }
+
+  async addComment(
+    draftId: number,
+    body: string,
+    options: { parentId?: number; anchor?: { start: number; end: number } } = {}
+  ): Promise<DraftComment> {
+    return this.request<DraftComment>(`/drafts/${draftId}/comments`, {
+      method: "POST",
+      body: JSON.stringify({ body, parent_id: options.parentId, anchor: options.anchor }),
+    });
+  }
+
+  async setCommentResolved(draftId: number, commentId: number, resolved: boolean): Promise<DraftComment> {
+    return this.request<DraftComment>(
+      `/drafts/${draftId}/comments/${commentId}/${resolved ? "resolve" : "unresolve"}`,
+      { method: "POST" }
+    );
+  }
+
+  // Draft rules
+  async listRules(): Promise<DraftRule[]> {
+    return this.request("/rules");
+  }
+
+  async createRule(rule: DraftRuleInput): Promise<DraftRule> {
+    return this.request<DraftRule>("/rules", { method: "POST", body: JSON.stringify(rule) });
+  }
+
+  async updateRule(id: number, rule: DraftRuleInput): Promise<DraftRule> {
+    return this.request<DraftRule>(`/rules/${id}`, { method: "PUT", body: JSON.stringify(rule) });
+  }
+
+  async deleteRule(id: number) {
+    return this.request(`/rules/${id}`, { method: "DELETE" });
+  }
+
+  async dryRunRules(options: { conditions?: RuleConditions; limit?: number } = {}): Promise<RuleDryRunResponse> {
+    return this.request<RuleDryRunResponse>("/rules/dry-run", { method: "POST", body: JSON.stringify(options) });
+  }
+
+  // Prompt templates
+  async listPrompts(workspaceId: number): Promise<(PromptVersion & { builtin: boolean; active: boolean })[]> {
+    return this.request(`/workspaces/${workspaceId}/prompts`);
+  }
+
+  async getPrompt(workspaceId: number, promptId: number): Promise<PromptVersion> {
+    return this.request(`/workspaces/${workspaceId}/prompts/${promptId}`);
+  }
+
+  async createPrompt(workspaceId: number, prompt: PromptVersionInput): Promise<PromptVersion> {
+    return this.request<PromptVersion>(`/workspaces/${workspaceId}/prompts`, {
+      method: "POST",
+      body: JSON.stringify(prompt),
+    });
+  }
+
+  async selectPrompt(workspaceId: number, name: PromptVersion["name"], promptId: number | null): Promise<PromptVersion> {
+    return this.request<PromptVersion>(`/workspaces/${workspaceId}/prompts/active`, {
+      method: "PUT",
+      body: JSON.stringify({ name, prompt_id: promptId }),
+    });
+  }
+
+  // Writing style profile
+  async getStyleProfile(): Promise<StyleProfile> {
+    return this.request("/style-profile");
+  }
+
+  async analyzeStyleProfile(limit?: number): Promise<StyleProfile> {
+    return this.request<StyleProfile>("/style-profile/analyze", { method: "POST", body: JSON.stringify({ limit }) });
+  }
+
+  async updateStyleProfile(profile: StyleProfileInput): Promise<StyleProfile> {
+    return this.request<StyleProfile>("/style-profile", { method: "PUT", body: JSON.stringify(profile) });
+  }
+
+  async deleteStyleProfile() {
+    return this.request<{ deleted: boolean }>("/style-profile", { method: "DELETE" });
+  }
+
+  // Reply templates
+  async listTemplates(params: { folder?: string; q?: string } = {}): Promise<ReplyTemplate[]> {
+    const query = new URLSearchParams(
+      Object.entries(params).filter(([, v]) => v !== undefined) as [string, string][]
+    ).toString();
+    return this.request(`/templates${query ? `?${query}` : ""}`);
+  }
+
+  async listTemplateFolders(): Promise<{ folder: string | null; count: number }[]> {
+    return this.request("/templates/folders");
+  }
+
+  async createTemplate(template: ReplyTemplateInput): Promise<ReplyTemplate> {
+    return this.request<ReplyTemplate>("/templates", { method: "POST", body: JSON.stringify(template) });
+  }
+
+  async updateTemplate(id: number, template: ReplyTemplateInput): Promise<ReplyTemplate> {
+    return this.request<ReplyTemplate>(`/templates/${id}`, { method: "PUT", body: JSON.stringify(template) });
+  }
+
+  async deleteTemplate(id: number) {
+    return this.request<{ deleted: boolean }>(`/templates/${id}`, { method: "DELETE" });
+  }
+
+  async renderTemplate(id: number, options: { emailId?: number; fields?: Record<string, string> } = {}): Promise<RenderedTemplate> {
+    return this.request<RenderedTemplate>(`/templates/${id}/render`, {
+      method: "POST",
+      body: JSON.stringify({ email_id: options.emailId, fields: options.fields }),
+    });
+  }
+
+  async draftFromTemplate(
+    templateId: number,
+    emailId: number,
+    options: { fields?: Record<string, string>; adapt?: boolean; tone?: string } = {}
+  ) {
+    return this.request<{ draft_id: number; content: string; template_id: number; adapted: boolean; warnings: DraftWarning[] }>("/drafts/from-template", {
+      method: "POST",
+      body: JSON.stringify({ template_id: templateId, email_id: emailId, ...options }),
+    });
+  }
+
+  async insertTemplate(
+    draftId: number,
+    templateId: number,
+    options: { fields?: Record<string, string>; mode?: "append" | "prepend" | "replace" } = {}
+  ) {
+    return this.request<{ updated: boolean; revision: number; content: string; warnings: DraftWarning[] }>(`/drafts/${draftId}/insert-template`, {
       method: "POST",
+      body: JSON.stringify({ template_id: templateId, ...options }),
     });
   }
 
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE login_codes (
+    code_hash TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP DEFAULT NOW()
+);
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE sessions (
+    id TEXT PRIMARY KEY,
+    user_email TEXT NOT NULL,
+    refresh_token_hash TEXT NOT NULL UNIQUE,
+    previous_token_hash TEXT,
+    user_agent TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP
+);
+
+CREATE INDEX sessions_user_email_idx ON sessions (user_email);
+CREATE INDEX sessions_previous_token_hash_idx ON sessions (previous_token_hash);
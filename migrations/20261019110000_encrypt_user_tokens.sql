@@ -0,0 +1,6 @@
+-- Add migration script here
+-- refresh_token holds base64(nonce || ciphertext) once encrypted; rows with a NULL
+-- key_id are legacy plaintext and get encrypted by `drafly rotate-token-keys`
+ALTER TABLE user_tokens
+ADD COLUMN encrypted_dek TEXT,
+ADD COLUMN key_id TEXT;
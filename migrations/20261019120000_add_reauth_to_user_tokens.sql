@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE user_tokens
+ADD COLUMN needs_reauth BOOLEAN NOT NULL DEFAULT FALSE,
+ADD COLUMN reauth_reason TEXT,
+ADD COLUMN granted_scopes TEXT;
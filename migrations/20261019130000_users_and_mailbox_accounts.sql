@@ -0,0 +1,75 @@
+-- Add migration script here
+-- A drafly login (users) is now separate from the Gmail mailboxes it manages
+CREATE TABLE users (
+    id SERIAL PRIMARY KEY,
+    primary_email TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE user_tokens RENAME TO mailbox_accounts;
+ALTER TABLE mailbox_accounts RENAME CONSTRAINT user_tokens_email_unique TO mailbox_accounts_email_unique;
+
+DELETE FROM mailbox_accounts WHERE email IS NULL;
+
+INSERT INTO users (primary_email)
+SELECT email FROM mailbox_accounts
+ON CONFLICT (primary_email) DO NOTHING;
+
+ALTER TABLE mailbox_accounts
+ADD COLUMN user_id INTEGER REFERENCES users(id) ON DELETE CASCADE;
+
+UPDATE mailbox_accounts a SET user_id = u.id
+FROM users u WHERE u.primary_email = a.email;
+
+ALTER TABLE mailbox_accounts
+ALTER COLUMN user_id SET NOT NULL,
+ALTER COLUMN email SET NOT NULL;
+
+CREATE INDEX mailbox_accounts_user_id_idx ON mailbox_accounts (user_id);
+
+-- emails and drafts are scoped by mailbox; disconnecting a mailbox removes its data
+ALTER TABLE emails
+ADD COLUMN account_id INTEGER REFERENCES mailbox_accounts(id) ON DELETE CASCADE;
+
+UPDATE emails e SET account_id = a.id
+FROM mailbox_accounts a WHERE a.email = e.user_email;
+
+CREATE INDEX emails_account_id_idx ON emails (account_id);
+
+ALTER TABLE drafts DROP CONSTRAINT drafts_email_id_fkey;
+ALTER TABLE drafts
+ADD CONSTRAINT drafts_email_id_fkey FOREIGN KEY (email_id) REFERENCES emails(id) ON DELETE CASCADE,
+ADD COLUMN account_id INTEGER REFERENCES mailbox_accounts(id) ON DELETE CASCADE;
+
+UPDATE drafts d SET account_id = e.account_id
+FROM emails e WHERE e.id = d.email_id;
+
+CREATE INDEX drafts_account_id_idx ON drafts (account_id);
+
+-- sessions and login codes now belong to a drafly user instead of a Google email
+DELETE FROM login_codes;
+ALTER TABLE login_codes
+DROP COLUMN email,
+ADD COLUMN user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE;
+
+ALTER TABLE sessions
+ADD COLUMN user_id INTEGER REFERENCES users(id) ON DELETE CASCADE;
+
+UPDATE sessions s SET user_id = u.id
+FROM users u WHERE u.primary_email = s.user_email;
+
+DELETE FROM sessions WHERE user_id IS NULL;
+DROP INDEX sessions_user_email_idx;
+ALTER TABLE sessions
+ALTER COLUMN user_id SET NOT NULL,
+DROP COLUMN user_email;
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
+
+-- OAuth state is now verified on callback; link_user_id marks "connect another mailbox" flows
+CREATE TABLE oauth_states (
+    state TEXT PRIMARY KEY,
+    link_user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP DEFAULT NOW()
+);
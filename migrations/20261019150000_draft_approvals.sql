@@ -0,0 +1,34 @@
+-- Add migration script here
+-- every content change bumps the revision; approvals are bound to one revision
+ALTER TABLE drafts
+ADD COLUMN revision INTEGER NOT NULL DEFAULT 1,
+ADD COLUMN updated_by INTEGER REFERENCES users(id) ON DELETE SET NULL;
+
+-- defaults keep the previous behaviour: one approval, self-approval allowed
+CREATE TABLE approval_policies (
+    account_id INTEGER PRIMARY KEY REFERENCES mailbox_accounts(id) ON DELETE CASCADE,
+    allow_self_approval BOOLEAN NOT NULL DEFAULT TRUE,
+    required_approvals INTEGER NOT NULL DEFAULT 1 CHECK (required_approvals >= 1),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- stricter requirements for replies to specific senders or domains
+CREATE TABLE approval_policy_rules (
+    id SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES mailbox_accounts(id) ON DELETE CASCADE,
+    match_type TEXT NOT NULL CHECK (match_type IN ('sender', 'domain')),
+    pattern TEXT NOT NULL,
+    required_approvals INTEGER NOT NULL CHECK (required_approvals >= 1)
+);
+
+CREATE INDEX approval_policy_rules_account_id_idx ON approval_policy_rules (account_id);
+
+CREATE TABLE draft_approvals (
+    id SERIAL PRIMARY KEY,
+    draft_id INTEGER NOT NULL REFERENCES drafts(id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL,
+    content_hash TEXT NOT NULL,
+    approved_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (draft_id, revision, approved_by)
+);
@@ -0,0 +1,29 @@
+-- Add migration script here
+CREATE TABLE draft_comments (
+    id SERIAL PRIMARY KEY,
+    draft_id INTEGER NOT NULL REFERENCES drafts(id) ON DELETE CASCADE,
+    -- replies point at the top-level comment of their thread
+    parent_id INTEGER REFERENCES draft_comments(id) ON DELETE CASCADE,
+    author_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    body TEXT NOT NULL,
+    -- optional character range of the draft content the thread is about
+    anchor_start INTEGER,
+    anchor_end INTEGER,
+    anchor_revision INTEGER,
+    anchor_text TEXT,
+    resolved_at TIMESTAMP,
+    resolved_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX draft_comments_draft_id_idx ON draft_comments (draft_id);
+
+CREATE TABLE comment_mentions (
+    comment_id INTEGER NOT NULL REFERENCES draft_comments(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (comment_id, user_id)
+);
+
+CREATE INDEX comment_mentions_user_id_idx ON comment_mentions (user_id);
@@ -0,0 +1,9 @@
+-- Add migration script here
+ALTER TABLE drafts
+ADD COLUMN assigned_to INTEGER REFERENCES users(id) ON DELETE SET NULL,
+ADD COLUMN assigned_at TIMESTAMP,
+-- a claim marks who is reviewing the draft right now; it lapses after a while
+ADD COLUMN claimed_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+ADD COLUMN claimed_at TIMESTAMP;
+
+CREATE INDEX drafts_assigned_to_idx ON drafts (assigned_to);
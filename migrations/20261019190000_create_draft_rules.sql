@@ -0,0 +1,20 @@
+-- Add migration script here
+CREATE TABLE draft_rules (
+    id SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES mailbox_accounts(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- lower runs first; the first matching rule drafts the reply
+    priority INTEGER NOT NULL DEFAULT 100,
+    conditions JSONB NOT NULL DEFAULT '{}',
+    tone TEXT NOT NULL DEFAULT 'friendly',
+    instructions TEXT,
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX draft_rules_account_id_idx ON draft_rules (account_id, priority);
+
+ALTER TABLE drafts
+ADD COLUMN rule_id INTEGER REFERENCES draft_rules(id) ON DELETE SET NULL;
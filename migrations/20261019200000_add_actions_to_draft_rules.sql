@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE draft_rules
+ADD COLUMN actions JSONB NOT NULL DEFAULT '{}',
+ADD COLUMN generate_draft BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE post_send_settings (
+    account_id INTEGER PRIMARY KEY REFERENCES mailbox_accounts(id) ON DELETE CASCADE,
+    mark_read BOOLEAN NOT NULL DEFAULT TRUE,
+    -- NULL applies no label
+    label TEXT DEFAULT 'Drafly/Replied',
+    archive BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE emails
+ADD COLUMN replied_at TIMESTAMP,
+ADD COLUMN replied_draft_id INTEGER REFERENCES drafts(id) ON DELETE SET NULL;
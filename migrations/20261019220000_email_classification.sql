@@ -0,0 +1,9 @@
+-- Add migration script here
+ALTER TABLE emails
+ADD COLUMN category TEXT CHECK (category IN ('support', 'sales', 'billing', 'personal', 'newsletter', 'spam')),
+ADD COLUMN urgency INTEGER CHECK (urgency BETWEEN 0 AND 100),
+ADD COLUMN needs_reply BOOLEAN,
+ADD COLUMN classified_at TIMESTAMP;
+
+CREATE INDEX emails_account_category_idx ON emails (account_id, category);
+CREATE INDEX emails_account_urgency_idx ON emails (account_id, urgency DESC NULLS LAST);
@@ -0,0 +1,17 @@
+-- Add migration script here
+CREATE TABLE summaries (
+    id SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES mailbox_accounts(id) ON DELETE CASCADE,
+    -- 'email' (target is emails.id) or 'thread' (target is the Gmail thread id)
+    scope TEXT NOT NULL CHECK (scope IN ('email', 'thread')),
+    target TEXT NOT NULL,
+    -- hash of the messages summarized; a different hash means the summary is stale
+    source_hash TEXT NOT NULL,
+    message_count INTEGER NOT NULL,
+    summary TEXT NOT NULL,
+    key_asks JSONB NOT NULL DEFAULT '[]',
+    deadlines JSONB NOT NULL DEFAULT '[]',
+    open_questions JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (account_id, scope, target)
+);
@@ -0,0 +1,16 @@
+-- Add migration script here
+ALTER TABLE emails
+ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(subject, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(sender, '')), 'B') ||
+    setweight(to_tsvector('english', coalesce(body_text, '')), 'C')
+) STORED;
+
+CREATE INDEX emails_search_vector_idx ON emails USING GIN (search_vector);
+
+ALTER TABLE drafts
+ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    to_tsvector('english', coalesce(content, ''))
+) STORED;
+
+CREATE INDEX drafts_search_vector_idx ON drafts USING GIN (search_vector);
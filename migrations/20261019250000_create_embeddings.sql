@@ -0,0 +1,19 @@
+-- Add migration script here
+-- Vectors are plain REAL[] and compared in the app, so no extension is needed.
+CREATE TABLE embeddings (
+    id SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES mailbox_accounts(id) ON DELETE CASCADE,
+    -- 'email' (source_id is emails.id) or 'draft' (source_id is drafts.id)
+    source_type TEXT NOT NULL CHECK (source_type IN ('email', 'draft')),
+    source_id INTEGER NOT NULL,
+    -- provider and model, e.g. 'local-hash-384' or 'openai/text-embedding-3-small'
+    model TEXT NOT NULL,
+    dims INTEGER NOT NULL,
+    vector REAL[] NOT NULL,
+    -- hash of the embedded text; unchanged content is not embedded again
+    content_hash TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (source_type, source_id, model)
+);
+
+CREATE INDEX idx_embeddings_account_model ON embeddings (account_id, model, source_type);
@@ -0,0 +1,48 @@
+-- Add migration script here
+CREATE TABLE knowledge_documents (
+    id SERIAL PRIMARY KEY,
+    workspace_id INTEGER NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    -- how content was written: 'markdown', 'text', or 'pdf' (the PDF's extracted text)
+    format TEXT NOT NULL CHECK (format IN ('markdown', 'text', 'pdf')),
+    content TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX knowledge_documents_workspace_id_idx ON knowledge_documents (workspace_id);
+
+-- documents are split into chunks that are retrieved and cited on their own
+CREATE TABLE knowledge_chunks (
+    id SERIAL PRIMARY KEY,
+    document_id INTEGER NOT NULL REFERENCES knowledge_documents(id) ON DELETE CASCADE,
+    workspace_id INTEGER NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    -- nearest Markdown heading path, e.g. 'Pricing > Discounts'
+    heading TEXT,
+    content TEXT NOT NULL,
+    -- embedding model of vector, as in embeddings.model
+    model TEXT NOT NULL,
+    vector REAL[] NOT NULL,
+    UNIQUE (document_id, position)
+);
+
+CREATE INDEX knowledge_chunks_workspace_model_idx ON knowledge_chunks (workspace_id, model);
+
+-- chunks put in the prompt of a generated draft, copied so reviewers can still see them
+-- after the document changes or is deleted
+CREATE TABLE draft_sources (
+    id SERIAL PRIMARY KEY,
+    draft_id INTEGER NOT NULL REFERENCES drafts(id) ON DELETE CASCADE,
+    -- 1-based number the chunk had in the prompt
+    rank INTEGER NOT NULL,
+    chunk_id INTEGER REFERENCES knowledge_chunks(id) ON DELETE SET NULL,
+    document_id INTEGER REFERENCES knowledge_documents(id) ON DELETE SET NULL,
+    document_title TEXT NOT NULL,
+    heading TEXT,
+    content TEXT NOT NULL,
+    score REAL NOT NULL,
+    UNIQUE (draft_id, rank)
+);
@@ -0,0 +1,22 @@
+-- Add migration script here
+CREATE TABLE reply_templates (
+    id SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES mailbox_accounts(id) ON DELETE CASCADE,
+    -- slash-separated path such as 'Billing/Refunds'; NULL for the top level
+    folder TEXT,
+    name TEXT NOT NULL,
+    -- text with {{variable}} placeholders
+    body TEXT NOT NULL,
+    -- custom fields the body uses besides the built-in variables: [{name, label, default}]
+    fields JSONB NOT NULL DEFAULT '[]',
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX reply_templates_folder_name_key
+ON reply_templates (account_id, COALESCE(folder, ''), lower(name));
+
+-- template a draft was written from
+ALTER TABLE drafts
+ADD COLUMN template_id INTEGER REFERENCES reply_templates(id) ON DELETE SET NULL;
@@ -0,0 +1,16 @@
+-- Add migration script here
+CREATE TABLE style_profiles (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    greeting TEXT,
+    sign_off TEXT,
+    typical_words INTEGER,
+    formality TEXT CHECK (formality IN ('formal', 'neutral', 'casual')),
+    common_phrases TEXT[] NOT NULL DEFAULT '{}',
+    -- written by the user; kept when the profile is learned again
+    notes TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    sample_count INTEGER NOT NULL DEFAULT 0,
+    source_account_id INTEGER REFERENCES mailbox_accounts(id) ON DELETE SET NULL,
+    analyzed_at TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
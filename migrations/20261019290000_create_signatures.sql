@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE signatures (
+    account_id INTEGER PRIMARY KEY REFERENCES mailbox_accounts(id) ON DELETE CASCADE,
+    plain TEXT,
+    html TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- 'manual' or 'gmail' when imported from the Gmail sendAs settings
+    source TEXT NOT NULL DEFAULT 'manual' CHECK (source IN ('manual', 'gmail')),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,63 @@
+-- Add migration script here
+CREATE TABLE prompt_templates (
+    id SERIAL PRIMARY KEY,
+    -- NULL for built-in prompts, available to every mailbox
+    workspace_id INTEGER REFERENCES workspaces(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- numbered per name across built-in and workspace prompts; versions are never edited
+    version INTEGER NOT NULL,
+    description TEXT,
+    system TEXT NOT NULL,
+    body TEXT NOT NULL,
+    model TEXT NOT NULL,
+    temperature DOUBLE PRECISION NOT NULL,
+    max_tokens INTEGER NOT NULL,
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT prompt_templates_name_version_key UNIQUE (name, version)
+);
+
+-- a workspace without a selection uses the newest built-in version
+CREATE TABLE workspace_prompts (
+    workspace_id INTEGER NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    prompt_id INTEGER NOT NULL REFERENCES prompt_templates(id) ON DELETE CASCADE,
+    selected_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    selected_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (workspace_id, name)
+);
+
+ALTER TABLE drafts
+ADD COLUMN prompt_id INTEGER REFERENCES prompt_templates(id) ON DELETE SET NULL,
+ADD COLUMN model TEXT,
+ADD COLUMN temperature DOUBLE PRECISION,
+ADD COLUMN prompt_tokens INTEGER,
+ADD COLUMN completion_tokens INTEGER;
+
+INSERT INTO prompt_templates (workspace_id, name, version, description, system, body, model, temperature, max_tokens)
+VALUES (
+    NULL,
+    'reply',
+    1,
+    'Default reply prompt',
+    $prompt${{assistant}} Write complete, ready-to-send email replies without any placeholders or variables.$prompt$,
+    $prompt$You are writing a professional email reply. Write a complete, ready-to-send email reply in a {{tone}} tone.
+
+IMPORTANT INSTRUCTIONS:
+- Write a complete email reply - do NOT use placeholders like [Name], [topic], [Your Name], etc.
+- Use the actual sender's name or email address from the context
+- Reference the original email subject naturally
+{{rules}}
+- Write as if you are directly replying to the sender
+- Do not include email headers (To, From, Subject) - just the reply body text
+
+Original Email:
+From: {{sender}}
+Subject: {{subject}}
+Body: {{email_body}}
+
+{{instructions}}{{style}}{{knowledge}}{{examples}}Write your complete email reply (body text only, no placeholders):$prompt$,
+    'llama-3.3-70b-versatile',
+    0.7,
+    500
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Guardrail findings on the current content of a draft, refreshed whenever the content changes
+ALTER TABLE drafts
+ADD COLUMN warnings JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- the same message can reach several connected mailboxes
+ALTER TABLE emails
+DROP CONSTRAINT emails_gmail_id_key,
+ADD CONSTRAINT emails_account_gmail_id_key UNIQUE (account_id, gmail_id);
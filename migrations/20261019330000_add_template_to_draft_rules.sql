@@ -0,0 +1,4 @@
+-- Add migration script here
+-- rules can draft from a reply template instead of generating from scratch
+ALTER TABLE draft_rules
+ADD COLUMN template_id INTEGER REFERENCES reply_templates(id) ON DELETE SET NULL;
@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+use dotenv::dotenv;
+use drafly::db;
+use drafly::eval::{self, report, Provider};
+
+const USAGE: &str = "usage: drafly-eval --fixtures FILE.jsonl --config A.json [--config B.json] \
+[--provider groq|mock] [--judge] [--json REPORT.json]
+
+Replies to every fixture with each config (two configs are compared), checks the replies and
+prints a Markdown report. --provider replaces the providers of the configs, --judge has a
+Groq model grade every reply, --json also writes the full report with every reply.";
+
+struct Args {
+    fixtures: PathBuf,
+    configs: Vec<PathBuf>,
+    provider: Option<Provider>,
+    judge: bool,
+    json: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut fixtures = None;
+    let mut configs = Vec::new();
+    let mut provider = None;
+    let mut judge = false;
+    let mut json = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--fixtures" => fixtures = Some(PathBuf::from(value()?)),
+            "--config" => configs.push(PathBuf::from(value()?)),
+            "--provider" => provider = Some(Provider::parse(&value()?)?),
+            "--json" => json = Some(PathBuf::from(value()?)),
+            "--judge" => judge = true,
+            "-h" | "--help" => return Err(String::new()),
+            other => return Err(format!("unknown argument {}", other)),
+        }
+    }
+
+    let fixtures = fixtures.ok_or("--fixtures is required")?;
+    if configs.is_empty() || configs.len() > 2 {
+        return Err("give one --config, or two to compare".to_string());
+    }
+    Ok(Args { fixtures, configs, provider, judge, json })
+}
+
+async fn run(args: Args) -> Result<bool, String> {
+    let fixtures = eval::load_fixtures(&args.fixtures)?;
+
+    let configs = args
+        .configs
+        .iter()
+        .map(|path| eval::load_config(path).map(|config| (path, config)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // only configs naming a registry prompt need the database
+    if configs.iter().any(|(_, config)| config.uses_registry()) {
+        std::env::var("DATABASE_URL").map_err(|_| "configs with a prompt_id need DATABASE_URL".to_string())?;
+        db::init().await.map_err(|e| format!("DB init failed: {:?}", e))?;
+    }
+
+    let mut runs = Vec::new();
+    for (path, config) in configs {
+        let variant = eval::variant(config, path, args.provider).await?;
+        log::info!("Running {} fixture(s) with {}", fixtures.len(), variant.label);
+        let outcomes = eval::run(&variant, &fixtures, args.judge).await;
+        runs.push(report::summarize(&variant, outcomes));
+    }
+
+    let report = report::Report::new(runs);
+    println!("{}", report.markdown());
+
+    if let Some(path) = &args.json {
+        let json = serde_json::to_string_pretty(&report).map_err(|e| format!("JSON error: {:?}", e))?;
+        std::fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    // a run with provider errors didn't evaluate anything
+    Ok(report.runs.iter().all(|r| r.summary.errors == 0))
+}
+
+#[actix_web::main]
+async fn main() {
+    dotenv().ok();
+    env_logger::init();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}\n", e);
+            }
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match run(args).await {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("drafly-eval: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
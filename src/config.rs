@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::env;
 
 pub fn google_client_id() -> String {
@@ -15,3 +16,79 @@ pub fn google_redirect_uri() -> String {
 pub fn jwt_secret() -> String {
     env::var("JWT_SECRET").expect("JWT_SECRET missing")
 }
+
+pub fn frontend_url() -> String {
+    env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:3000".to_string())
+}
+
+/// Whether the session cookie is marked `Secure` (disable only for plain-http local dev)
+pub fn session_cookie_secure() -> bool {
+    env::var("SESSION_COOKIE_SECURE")
+        .map(|v| v != "false" && v != "0")
+        .unwrap_or(true)
+}
+
+/// SameSite policy for the session cookie: `Lax` (default), `Strict` or `None`
+pub fn session_cookie_same_site() -> String {
+    env::var("SESSION_COOKIE_SAMESITE").unwrap_or_else(|_| "Lax".to_string())
+}
+
+/// Master keys for refresh-token encryption, as `id:base64key` pairs separated by commas.
+/// Old keys stay listed until `drafly rotate-token-keys` has re-encrypted every row.
+pub fn token_encryption_keys() -> HashMap<String, String> {
+    env::var("TOKEN_ENCRYPTION_KEYS")
+        .expect("TOKEN_ENCRYPTION_KEYS missing")
+        .split(',')
+        .filter_map(|pair| {
+            let (id, key) = pair.trim().split_once(':')?;
+            Some((id.to_string(), key.to_string()))
+        })
+        .collect()
+}
+
+/// Id of the master key used for new encryptions
+pub fn token_encryption_key_id() -> String {
+    env::var("TOKEN_ENCRYPTION_KEY_ID").expect("TOKEN_ENCRYPTION_KEY_ID missing")
+}
+
+/// Minutes a draft may wait for review before it counts as overdue in the review queue
+pub fn review_sla_minutes() -> i64 {
+    env::var("REVIEW_SLA_MINUTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(240)
+}
+
+/// Embedding provider for semantic search and similar replies: `local` (default) or `openai`
+pub fn embedding_provider() -> String {
+    env::var("EMBEDDING_PROVIDER").unwrap_or_else(|_| "local".to_string()).to_lowercase()
+}
+
+/// Model used when `EMBEDDING_PROVIDER=openai`
+pub fn embedding_model() -> String {
+    env::var("EMBEDDING_MODEL").unwrap_or_else(|_| "text-embedding-3-small".to_string())
+}
+
+/// Past replies added to a generation prompt as examples of how the mailbox answers
+pub fn similar_reply_examples() -> usize {
+    env::var("SIMILAR_REPLY_EXAMPLES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3)
+}
+
+/// Knowledge base excerpts added to a generation prompt
+pub fn knowledge_chunks() -> usize {
+    env::var("KNOWLEDGE_CHUNKS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(4)
+}
+
+/// Extra attempts at a generated reply whose guardrail check found high-severity problems
+pub fn guardrail_retries() -> u32 {
+    env::var("GUARDRAIL_RETRIES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1)
+}
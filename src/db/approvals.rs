@@ -0,0 +1,191 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::db::get_pool;
+
+/// Approval requirements of a mailbox
+#[derive(Serialize, Deserialize)]
+pub struct Policy {
+    pub allow_self_approval: bool,
+    pub required_approvals: i32,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+/// Raises the number of approvals for replies to a sender address or domain
+#[derive(Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// `sender` (exact address) or `domain` (domain and its subdomains)
+    pub match_type: String,
+    pub pattern: String,
+    pub required_approvals: i32,
+}
+
+#[derive(Serialize)]
+pub struct Approval {
+    pub revision: i32,
+    pub content_hash: String,
+    pub approved_by: Option<i32>,
+    pub approver_email: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// SHA-256 hex digest of draft content, so an approval is bound to the exact text approved
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// Bare lowercase address from a `From` header such as `Jane <jane@example.com>`
+pub fn sender_address(sender: &str) -> String {
+    let address = match (sender.rfind('<'), sender.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &sender[start + 1..end],
+        _ => sender,
+    };
+    address.trim().to_lowercase()
+}
+
+impl Policy {
+    /// Number of distinct approvers needed before a reply to `sender` can be sent
+    pub fn required_for_sender(&self, sender: &str) -> i32 {
+        let address = sender_address(sender);
+        let domain = address.rsplit_once('@').map(|(_, d)| d).unwrap_or("");
+
+        self.rules
+            .iter()
+            .filter(|r| {
+                let pattern = r.pattern.trim().trim_start_matches('@').to_lowercase();
+                match r.match_type.as_str() {
+                    "sender" => address == pattern,
+                    "domain" => domain == pattern || domain.ends_with(&format!(".{}", pattern)),
+                    _ => false,
+                }
+            })
+            .map(|r| r.required_approvals)
+            .fold(self.required_approvals, i32::max)
+    }
+}
+
+pub async fn get_policy(account_id: i32) -> Result<Policy, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT allow_self_approval, required_approvals FROM approval_policies WHERE account_id = $1",
+        account_id
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    let rules = sqlx::query_as!(
+        PolicyRule,
+        "SELECT match_type, pattern, required_approvals FROM approval_policy_rules
+         WHERE account_id = $1 ORDER BY id",
+        account_id
+    )
+    .fetch_all(get_pool())
+    .await?;
+
+    Ok(match row {
+        Some(r) => Policy {
+            allow_self_approval: r.allow_self_approval,
+            required_approvals: r.required_approvals,
+            rules,
+        },
+        None => Policy {
+            allow_self_approval: true,
+            required_approvals: 1,
+            rules,
+        },
+    })
+}
+
+/// Replaces the mailbox's policy and rules
+pub async fn set_policy(account_id: i32, policy: &Policy) -> Result<(), sqlx::Error> {
+    let mut tx = get_pool().begin().await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO approval_policies (account_id, allow_self_approval, required_approvals)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (account_id)
+        DO UPDATE SET allow_self_approval = EXCLUDED.allow_self_approval,
+                      required_approvals = EXCLUDED.required_approvals,
+                      updated_at = NOW()
+        "#,
+        account_id,
+        policy.allow_self_approval,
+        policy.required_approvals
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM approval_policy_rules WHERE account_id = $1", account_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for rule in &policy.rules {
+        sqlx::query!(
+            "INSERT INTO approval_policy_rules (account_id, match_type, pattern, required_approvals)
+             VALUES ($1, $2, $3, $4)",
+            account_id,
+            rule.match_type,
+            rule.pattern.trim().to_lowercase(),
+            rule.required_approvals
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Records `user_id`'s approval of a draft revision; approving the same revision twice is a no-op
+pub async fn record(draft_id: i32, revision: i32, content_hash: &str, user_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO draft_approvals (draft_id, revision, content_hash, approved_by)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (draft_id, revision, approved_by) DO NOTHING
+        "#,
+        draft_id,
+        revision,
+        content_hash,
+        user_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
+
+/// Distinct approvers of exactly this revision and content
+pub async fn count_for_revision(draft_id: i32, revision: i32, content_hash: &str) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(DISTINCT approved_by) AS "count!"
+        FROM draft_approvals
+        WHERE draft_id = $1 AND revision = $2 AND content_hash = $3
+        "#,
+        draft_id,
+        revision,
+        content_hash
+    )
+    .fetch_one(get_pool())
+    .await?;
+
+    Ok(row.count)
+}
+
+pub async fn list(draft_id: i32) -> Result<Vec<Approval>, sqlx::Error> {
+    sqlx::query_as!(
+        Approval,
+        r#"
+        SELECT a.revision, a.content_hash, a.approved_by, u.primary_email AS "approver_email?", a.created_at
+        FROM draft_approvals a
+        LEFT JOIN users u ON u.id = a.approved_by
+        WHERE a.draft_id = $1
+        ORDER BY a.created_at
+        "#,
+        draft_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
@@ -0,0 +1,197 @@
+use actix_web::HttpRequest;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use crate::db::get_pool;
+use crate::middleware::AuthenticatedUser;
+
+/// An event about to be appended to the audit log
+pub struct NewEvent {
+    actor_user_id: Option<i32>,
+    actor_email: Option<String>,
+    account_id: Option<i32>,
+    action: &'static str,
+    target_type: &'static str,
+    target_id: Option<String>,
+    before_hash: Option<String>,
+    after_hash: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    metadata: serde_json::Value,
+}
+
+impl NewEvent {
+    /// An action performed by an authenticated user
+    pub fn by(user: &AuthenticatedUser, action: &'static str) -> NewEvent {
+        NewEvent {
+            actor_user_id: Some(user.user_id),
+            actor_email: Some(user.email.clone()),
+            ip: user.ip.clone(),
+            user_agent: user.user_agent.clone(),
+            ..NewEvent::new(action)
+        }
+    }
+
+    /// An action of a user who isn't authenticated by a JWT yet (sign-in, OAuth callback)
+    pub fn by_user_id(user_id: i32, req: &HttpRequest, action: &'static str) -> NewEvent {
+        NewEvent {
+            actor_user_id: Some(user_id),
+            ip: req.connection_info().realip_remote_addr().map(|a| a.to_string()),
+            user_agent: req
+                .headers()
+                .get("User-Agent")
+                .and_then(|h| h.to_str().ok())
+                .map(|ua| ua.to_string()),
+            ..NewEvent::new(action)
+        }
+    }
+
+    fn new(action: &'static str) -> NewEvent {
+        NewEvent {
+            actor_user_id: None,
+            actor_email: None,
+            account_id: None,
+            action,
+            target_type: "user",
+            target_id: None,
+            before_hash: None,
+            after_hash: None,
+            ip: None,
+            user_agent: None,
+            metadata: serde_json::json!({}),
+        }
+    }
+
+    pub fn mailbox(mut self, account_id: i32) -> NewEvent {
+        self.account_id = Some(account_id);
+        self
+    }
+
+    pub fn target(mut self, target_type: &'static str, target_id: impl ToString) -> NewEvent {
+        self.target_type = target_type;
+        self.target_id = Some(target_id.to_string());
+        self
+    }
+
+    pub fn before(mut self, hash: String) -> NewEvent {
+        self.before_hash = Some(hash);
+        self
+    }
+
+    pub fn after(mut self, hash: String) -> NewEvent {
+        self.after_hash = Some(hash);
+        self
+    }
+
+    pub fn metadata(mut self, metadata: serde_json::Value) -> NewEvent {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Appends the event. Audit writes never fail the request they describe; errors are logged.
+    pub async fn record(self) {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO audit_events
+                (actor_user_id, actor_email, account_id, action, target_type, target_id,
+                 before_hash, after_hash, ip, user_agent, metadata)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+            self.actor_user_id,
+            self.actor_email,
+            self.account_id,
+            self.action,
+            self.target_type,
+            self.target_id,
+            self.before_hash,
+            self.after_hash,
+            self.ip,
+            self.user_agent,
+            self.metadata
+        )
+        .execute(get_pool())
+        .await;
+
+        if let Err(e) = result {
+            log::error!("failed to write audit event {}: {:?}", self.action, e);
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub occurred_at: NaiveDateTime,
+    pub actor_user_id: Option<i32>,
+    pub actor_email: Option<String>,
+    pub account_id: Option<i32>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<String>,
+    pub before_hash: Option<String>,
+    pub after_hash: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: serde_json::Value,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Filter {
+    pub account_id: Option<i32>,
+    pub actor_user_id: Option<i32>,
+    /// Exact action, or a prefix ending in `.` such as `draft.`
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    /// Only events older than this id, for paging backwards
+    pub before_id: Option<i64>,
+}
+
+/// Events visible to `user_id`, newest first: their own actions plus everything that
+/// happened in mailboxes they own, directly or as a workspace owner
+pub async fn search(user_id: i32, filter: &Filter, limit: i64) -> Result<Vec<AuditEvent>, sqlx::Error> {
+    let action_prefix = filter
+        .action
+        .as_deref()
+        .filter(|a| a.ends_with('.'))
+        .map(|a| format!("{}%", a.replace('%', "\\%").replace('_', "\\_")));
+    let action_exact = filter.action.as_deref().filter(|a| !a.ends_with('.'));
+
+    sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT e.id, e.occurred_at, e.actor_user_id, e.actor_email, e.account_id, e.action,
+               e.target_type, e.target_id, e.before_hash, e.after_hash, e.ip, e.user_agent, e.metadata
+        FROM audit_events e
+        WHERE (e.actor_user_id = $1 OR e.account_id IN (
+                  SELECT a.id FROM mailbox_accounts a
+                  LEFT JOIN workspace_members m ON m.workspace_id = a.workspace_id AND m.user_id = $1
+                  WHERE a.user_id = $1 OR m.role = 'owner'))
+          AND ($2::int IS NULL OR e.account_id = $2)
+          AND ($3::int IS NULL OR e.actor_user_id = $3)
+          AND ($4::text IS NULL OR e.action = $4)
+          AND ($5::text IS NULL OR e.action LIKE $5)
+          AND ($6::text IS NULL OR e.target_type = $6)
+          AND ($7::text IS NULL OR e.target_id = $7)
+          AND ($8::timestamp IS NULL OR e.occurred_at >= $8)
+          AND ($9::timestamp IS NULL OR e.occurred_at < $9)
+          AND ($10::bigint IS NULL OR e.id < $10)
+        ORDER BY e.id DESC
+        LIMIT $11
+        "#,
+        user_id,
+        filter.account_id,
+        filter.actor_user_id,
+        action_exact,
+        action_prefix,
+        filter.target_type,
+        filter.target_id,
+        filter.from,
+        filter.to,
+        filter.before_id,
+        limit
+    )
+    .fetch_all(get_pool())
+    .await
+}
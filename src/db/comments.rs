@@ -0,0 +1,206 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use crate::db::get_pool;
+
+#[derive(Serialize)]
+pub struct Comment {
+    pub id: i32,
+    pub draft_id: i32,
+    pub parent_id: Option<i32>,
+    pub author_id: Option<i32>,
+    pub author_email: Option<String>,
+    pub body: String,
+    pub anchor_start: Option<i32>,
+    pub anchor_end: Option<i32>,
+    /// Draft revision the anchor offsets refer to
+    pub anchor_revision: Option<i32>,
+    pub anchor_text: Option<String>,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub resolved_by: Option<i32>,
+    /// Emails of the mentioned members
+    pub mentions: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A character range of the draft content a comment thread is attached to
+pub struct Anchor {
+    pub start: i32,
+    pub end: i32,
+    pub revision: i32,
+    pub text: String,
+}
+
+#[derive(Serialize)]
+pub struct Mention {
+    pub comment_id: i32,
+    pub draft_id: i32,
+    pub account_id: Option<i32>,
+    pub author_email: Option<String>,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+}
+
+pub async fn list(draft_id: i32) -> Result<Vec<Comment>, sqlx::Error> {
+    sqlx::query_as!(
+        Comment,
+        r#"
+        SELECT c.id, c.draft_id, c.parent_id, c.author_id, u.primary_email AS "author_email?", c.body,
+               c.anchor_start, c.anchor_end, c.anchor_revision, c.anchor_text, c.resolved_at, c.resolved_by,
+               ARRAY(SELECT mu.primary_email FROM comment_mentions cm JOIN users mu ON mu.id = cm.user_id
+                     WHERE cm.comment_id = c.id ORDER BY mu.primary_email) AS "mentions!",
+               c.created_at, c.updated_at
+        FROM draft_comments c
+        LEFT JOIN users u ON u.id = c.author_id
+        WHERE c.draft_id = $1
+        ORDER BY c.created_at, c.id
+        "#,
+        draft_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+pub async fn get(draft_id: i32, comment_id: i32) -> Result<Option<Comment>, sqlx::Error> {
+    sqlx::query_as!(
+        Comment,
+        r#"
+        SELECT c.id, c.draft_id, c.parent_id, c.author_id, u.primary_email AS "author_email?", c.body,
+               c.anchor_start, c.anchor_end, c.anchor_revision, c.anchor_text, c.resolved_at, c.resolved_by,
+               ARRAY(SELECT mu.primary_email FROM comment_mentions cm JOIN users mu ON mu.id = cm.user_id
+                     WHERE cm.comment_id = c.id ORDER BY mu.primary_email) AS "mentions!",
+               c.created_at, c.updated_at
+        FROM draft_comments c
+        LEFT JOIN users u ON u.id = c.author_id
+        WHERE c.draft_id = $1 AND c.id = $2
+        "#,
+        draft_id,
+        comment_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+/// Adds a comment with its mentions and returns its id
+pub async fn create(
+    draft_id: i32,
+    parent_id: Option<i32>,
+    author_id: i32,
+    body: &str,
+    anchor: Option<Anchor>,
+    mentioned: &[i32],
+) -> Result<i32, sqlx::Error> {
+    let mut tx = get_pool().begin().await?;
+
+    let (start, end, revision, text) = match anchor {
+        Some(a) => (Some(a.start), Some(a.end), Some(a.revision), Some(a.text)),
+        None => (None, None, None, None),
+    };
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO draft_comments (draft_id, parent_id, author_id, body, anchor_start, anchor_end, anchor_revision, anchor_text)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING id
+        "#,
+        draft_id,
+        parent_id,
+        author_id,
+        body,
+        start,
+        end,
+        revision,
+        text
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO comment_mentions (comment_id, user_id) SELECT $1, UNNEST($2::int[]) ON CONFLICT DO NOTHING",
+        row.id,
+        mentioned
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(row.id)
+}
+
+/// Replaces a comment's body and mentions
+pub async fn update_body(comment_id: i32, body: &str, mentioned: &[i32]) -> Result<(), sqlx::Error> {
+    let mut tx = get_pool().begin().await?;
+
+    sqlx::query!(
+        "UPDATE draft_comments SET body = $2, updated_at = NOW() WHERE id = $1",
+        comment_id,
+        body
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM comment_mentions WHERE comment_id = $1", comment_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        "INSERT INTO comment_mentions (comment_id, user_id) SELECT $1, UNNEST($2::int[]) ON CONFLICT DO NOTHING",
+        comment_id,
+        mentioned
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Deletes a comment; deleting a thread's first comment removes its replies too
+pub async fn delete(comment_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM draft_comments WHERE id = $1", comment_id)
+        .execute(get_pool())
+        .await?;
+
+    Ok(())
+}
+
+/// Marks a thread resolved by `resolved_by`, or reopens it when `None`
+pub async fn set_resolved(comment_id: i32, resolved_by: Option<i32>) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE draft_comments
+        SET resolved_at = CASE WHEN $2::int IS NULL THEN NULL ELSE NOW() END,
+            resolved_by = $2
+        WHERE id = $1
+        "#,
+        comment_id,
+        resolved_by
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
+
+/// Comments mentioning the user, limited to mailboxes they can still access
+pub async fn mentions_for_user(user_id: i32) -> Result<Vec<Mention>, sqlx::Error> {
+    sqlx::query_as!(
+        Mention,
+        r#"
+        SELECT c.id AS comment_id, c.draft_id, d.account_id, u.primary_email AS "author_email?", c.body, c.created_at
+        FROM comment_mentions cm
+        JOIN draft_comments c ON c.id = cm.comment_id
+        JOIN drafts d ON d.id = c.draft_id
+        JOIN mailbox_accounts a ON a.id = d.account_id
+        LEFT JOIN users u ON u.id = c.author_id
+        WHERE cm.user_id = $1
+          AND (a.user_id = $1 OR EXISTS (
+                SELECT 1 FROM workspace_members m WHERE m.workspace_id = a.workspace_id AND m.user_id = $1))
+        ORDER BY c.created_at DESC
+        LIMIT 100
+        "#,
+        user_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
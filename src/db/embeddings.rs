@@ -0,0 +1,107 @@
+use crate::db::get_pool;
+
+/// Hash of the text last embedded for a source, if it has a vector for `model`
+pub async fn content_hash_of(source_type: &str, source_id: i32, model: &str) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT content_hash FROM embeddings WHERE source_type = $1 AND source_id = $2 AND model = $3",
+        source_type,
+        source_id,
+        model
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.map(|r| r.content_hash))
+}
+
+/// Stores a vector, replacing the previous one for the same source and model
+pub async fn upsert(account_id: i32, source_type: &str, source_id: i32, model: &str, content_hash: &str, vector: &[f32]) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO embeddings (account_id, source_type, source_id, model, dims, vector, content_hash)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (source_type, source_id, model)
+        DO UPDATE SET vector = EXCLUDED.vector,
+                      dims = EXCLUDED.dims,
+                      content_hash = EXCLUDED.content_hash,
+                      created_at = NOW()
+        "#,
+        account_id,
+        source_type,
+        source_id,
+        model,
+        vector.len() as i32,
+        vector,
+        content_hash
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
+
+/// The newest vectors of a mailbox as `(source_type, source_id, vector)`, optionally of one source type
+pub async fn scan(account_id: i32, model: &str, source_type: Option<&str>, limit: i64) -> Result<Vec<(String, i32, Vec<f32>)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT source_type, source_id, vector
+        FROM embeddings
+        WHERE account_id = $1 AND model = $2 AND ($3::text IS NULL OR source_type = $3)
+        ORDER BY id DESC
+        LIMIT $4
+        "#,
+        account_id,
+        model,
+        source_type,
+        limit
+    )
+    .fetch_all(get_pool())
+    .await?;
+
+    Ok(rows.into_iter().map(|r| (r.source_type, r.source_id, r.vector)).collect())
+}
+
+/// Sources with a vector from an earlier version of the local embedding than `model`, as
+/// `(account_id, source_type, source_id)`
+pub async fn retired_local(model: &str) -> Result<Vec<(i32, String, i32)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT DISTINCT account_id, source_type, source_id
+        FROM embeddings
+        WHERE model LIKE 'local-hash-%' AND model <> $1
+        "#,
+        model
+    )
+    .fetch_all(get_pool())
+    .await?;
+
+    Ok(rows.into_iter().map(|r| (r.account_id, r.source_type, r.source_id)).collect())
+}
+
+pub async fn delete_retired_local(model: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM embeddings WHERE model LIKE 'local-hash-%' AND model <> $1",
+        model
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Drops vectors whose email or draft no longer exists
+pub async fn prune(account_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM embeddings v
+        WHERE v.account_id = $1
+          AND ((v.source_type = 'email' AND NOT EXISTS (SELECT 1 FROM emails e WHERE e.id = v.source_id))
+            OR (v.source_type = 'draft' AND NOT EXISTS (SELECT 1 FROM drafts d WHERE d.id = v.source_id)))
+        "#,
+        account_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
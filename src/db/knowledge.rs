@@ -0,0 +1,233 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use crate::db::get_pool;
+use crate::services::knowledge::RetrievedChunk;
+
+#[derive(Serialize)]
+pub struct Document {
+    pub id: i32,
+    pub workspace_id: i32,
+    pub title: String,
+    pub format: String,
+    pub content: String,
+    pub content_hash: String,
+    pub created_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A document without its content, for listings
+#[derive(Serialize)]
+pub struct DocumentSummary {
+    pub id: i32,
+    pub title: String,
+    pub format: String,
+    pub chars: i32,
+    pub chunk_count: i64,
+    pub created_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+pub struct StoredChunk {
+    pub id: i32,
+    pub document_id: i32,
+    pub document_title: String,
+    pub heading: Option<String>,
+    pub content: String,
+    pub vector: Vec<f32>,
+}
+
+/// A chunk cited by a draft, as it was when the draft was generated
+#[derive(Serialize)]
+pub struct Source {
+    pub rank: i32,
+    pub chunk_id: Option<i32>,
+    pub document_id: Option<i32>,
+    pub document_title: String,
+    pub heading: Option<String>,
+    pub content: String,
+    pub score: f32,
+}
+
+pub async fn list(workspace_id: i32) -> Result<Vec<DocumentSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        DocumentSummary,
+        r#"
+        SELECT d.id, d.title, d.format, length(d.content) AS "chars!", d.created_by, d.created_at, d.updated_at,
+               (SELECT COUNT(*) FROM knowledge_chunks c WHERE c.document_id = d.id) AS "chunk_count!"
+        FROM knowledge_documents d
+        WHERE d.workspace_id = $1
+        ORDER BY d.title, d.id
+        "#,
+        workspace_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+pub async fn get(workspace_id: i32, id: i32) -> Result<Option<Document>, sqlx::Error> {
+    sqlx::query_as!(
+        Document,
+        "SELECT * FROM knowledge_documents WHERE id = $1 AND workspace_id = $2",
+        id,
+        workspace_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+pub async fn create(workspace_id: i32, title: &str, format: &str, content: &str, content_hash: &str, user_id: i32) -> Result<Document, sqlx::Error> {
+    sqlx::query_as!(
+        Document,
+        r#"
+        INSERT INTO knowledge_documents (workspace_id, title, format, content, content_hash, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *
+        "#,
+        workspace_id,
+        title,
+        format,
+        content,
+        content_hash,
+        user_id
+    )
+    .fetch_one(get_pool())
+    .await
+}
+
+pub async fn update(workspace_id: i32, id: i32, title: &str, format: &str, content: &str, content_hash: &str) -> Result<Option<Document>, sqlx::Error> {
+    sqlx::query_as!(
+        Document,
+        r#"
+        UPDATE knowledge_documents
+        SET title = $3, format = $4, content = $5, content_hash = $6, updated_at = NOW()
+        WHERE id = $1 AND workspace_id = $2
+        RETURNING *
+        "#,
+        id,
+        workspace_id,
+        title,
+        format,
+        content,
+        content_hash
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+pub async fn delete(workspace_id: i32, id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM knowledge_documents WHERE id = $1 AND workspace_id = $2",
+        id,
+        workspace_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Replaces a document's chunks with `(heading, content, vector)` rows in one transaction
+pub async fn replace_chunks(document_id: i32, workspace_id: i32, model: &str, chunks: &[(Option<&str>, &str, Vec<f32>)]) -> Result<(), sqlx::Error> {
+    let mut tx = get_pool().begin().await?;
+
+    sqlx::query!("DELETE FROM knowledge_chunks WHERE document_id = $1", document_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for (position, (heading, content, vector)) in chunks.iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO knowledge_chunks (document_id, workspace_id, position, heading, content, model, vector)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            document_id,
+            workspace_id,
+            position as i32,
+            *heading,
+            *content,
+            model,
+            vector
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+/// The workspace's chunks embedded with `model`
+pub async fn scan_chunks(workspace_id: i32, model: &str, limit: i64) -> Result<Vec<StoredChunk>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredChunk,
+        r#"
+        SELECT c.id, c.document_id, d.title AS document_title, c.heading, c.content, c.vector
+        FROM knowledge_chunks c
+        JOIN knowledge_documents d ON d.id = c.document_id
+        WHERE c.workspace_id = $1 AND c.model = $2
+        ORDER BY c.id DESC
+        LIMIT $3
+        "#,
+        workspace_id,
+        model,
+        limit
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+/// Documents with no chunks for `model`, e.g. after the embedding provider changed
+pub async fn stale_documents(workspace_id: i32, model: &str) -> Result<Vec<Document>, sqlx::Error> {
+    sqlx::query_as!(
+        Document,
+        r#"
+        SELECT * FROM knowledge_documents d
+        WHERE d.workspace_id = $1
+          AND NOT EXISTS (SELECT 1 FROM knowledge_chunks c WHERE c.document_id = d.id AND c.model = $2)
+        ORDER BY d.id
+        "#,
+        workspace_id,
+        model
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+/// Records the chunks a draft was generated from, numbered as they were in the prompt
+pub async fn save_sources(draft_id: i32, chunks: &[RetrievedChunk]) -> Result<(), sqlx::Error> {
+    for (i, c) in chunks.iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO draft_sources (draft_id, rank, chunk_id, document_id, document_title, heading, content, score)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            draft_id,
+            i as i32 + 1,
+            c.chunk_id,
+            c.document_id,
+            c.document_title,
+            c.heading,
+            c.content,
+            c.score
+        )
+        .execute(get_pool())
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn sources(draft_id: i32) -> Result<Vec<Source>, sqlx::Error> {
+    sqlx::query_as!(
+        Source,
+        r#"
+        SELECT rank, chunk_id, document_id, document_title, heading, content, score
+        FROM draft_sources
+        WHERE draft_id = $1
+        ORDER BY rank
+        "#,
+        draft_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
@@ -0,0 +1,49 @@
+use crate::db::get_pool;
+use crate::services::session;
+
+/// How long the frontend has to exchange a login code after the OAuth redirect
+const LOGIN_CODE_TTL_SECONDS: f64 = 60.0;
+
+/// Creates a one-time login code for the user and returns the raw code.
+/// Only the hash is stored, so a leaked table dump can't be replayed.
+pub async fn create(user_id: i32) -> Result<String, sqlx::Error> {
+    let pool = get_pool();
+
+    // opportunistic cleanup of codes nobody exchanged
+    sqlx::query!("DELETE FROM login_codes WHERE expires_at < NOW()")
+        .execute(pool)
+        .await?;
+
+    let code = session::new_opaque_token();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO login_codes (code_hash, user_id, expires_at)
+        VALUES ($1, $2, NOW() + make_interval(secs => $3))
+        "#,
+        session::hash_token(&code),
+        user_id,
+        LOGIN_CODE_TTL_SECONDS
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(code)
+}
+
+/// Consumes a login code, returning the user it was issued for.
+/// The row is deleted in the same statement so a code can only be used once.
+pub async fn consume(code: &str) -> Result<Option<i32>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        DELETE FROM login_codes
+        WHERE code_hash = $1 AND expires_at > NOW()
+        RETURNING user_id
+        "#,
+        session::hash_token(code)
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.map(|r| r.user_id))
+}
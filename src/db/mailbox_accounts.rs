@@ -0,0 +1,276 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use crate::db::get_pool;
+use crate::services::token_crypto;
+
+/// A Gmail mailbox connected to a drafly user
+#[derive(Serialize)]
+pub struct MailboxAccount {
+    pub id: i32,
+    pub user_id: i32,
+    pub email: String,
+    pub needs_reauth: bool,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+pub struct GrantStatus {
+    pub needs_reauth: bool,
+    pub reauth_reason: Option<String>,
+    pub granted_scopes: Option<String>,
+}
+
+/// Stores the mailbox's Google refresh token envelope-encrypted with the active key
+/// and returns the account id. A fresh token means the user just consented, so any
+/// pending re-auth is cleared. The owning user of an existing mailbox never changes here.
+pub async fn upsert(user_id: i32, email: &str, token: &str, granted_scopes: Option<&str>) -> Result<i32, String> {
+    let sealed = token_crypto::encrypt(token)?;
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO mailbox_accounts (user_id, email, refresh_token, encrypted_dek, key_id, granted_scopes)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (email)
+        DO UPDATE SET refresh_token = EXCLUDED.refresh_token,
+                      encrypted_dek = EXCLUDED.encrypted_dek,
+                      key_id = EXCLUDED.key_id,
+                      granted_scopes = COALESCE(EXCLUDED.granted_scopes, mailbox_accounts.granted_scopes),
+                      needs_reauth = FALSE,
+                      reauth_reason = NULL
+        RETURNING id
+        "#,
+        user_id,
+        email,
+        sealed.ciphertext,
+        sealed.encrypted_dek,
+        sealed.key_id,
+        granted_scopes
+    )
+    .fetch_one(get_pool())
+    .await
+    .map_err(|e| format!("db insert error: {:?}", e))?;
+
+    Ok(row.id)
+}
+
+pub async fn find_by_email(email: &str) -> Result<Option<MailboxAccount>, sqlx::Error> {
+    sqlx::query_as!(
+        MailboxAccount,
+        "SELECT id, user_id, email, needs_reauth, created_at FROM mailbox_accounts WHERE email = $1",
+        email
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+pub async fn get_for_user(user_id: i32, account_id: i32) -> Result<Option<MailboxAccount>, sqlx::Error> {
+    sqlx::query_as!(
+        MailboxAccount,
+        "SELECT id, user_id, email, needs_reauth, created_at FROM mailbox_accounts
+         WHERE id = $1 AND user_id = $2",
+        account_id,
+        user_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+/// A mailbox the user can reach, either as its owner or through a workspace it is shared with
+#[derive(Serialize)]
+pub struct AccessibleMailbox {
+    pub id: i32,
+    pub user_id: i32,
+    pub email: String,
+    pub workspace_id: Option<i32>,
+    pub needs_reauth: bool,
+    /// The user's role on the mailbox; the owner of a mailbox is always `owner`
+    pub role: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+pub async fn get_accessible(user_id: i32, account_id: i32) -> Result<Option<AccessibleMailbox>, sqlx::Error> {
+    sqlx::query_as!(
+        AccessibleMailbox,
+        r#"
+        SELECT a.id, a.user_id, a.email, a.workspace_id, a.needs_reauth, a.created_at,
+               (CASE WHEN a.user_id = $2 THEN 'owner' ELSE m.role END) AS "role!"
+        FROM mailbox_accounts a
+        LEFT JOIN workspace_members m ON m.workspace_id = a.workspace_id AND m.user_id = $2
+        WHERE a.id = $1 AND (a.user_id = $2 OR m.user_id IS NOT NULL)
+        "#,
+        account_id,
+        user_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+pub async fn list_accessible(user_id: i32) -> Result<Vec<AccessibleMailbox>, sqlx::Error> {
+    sqlx::query_as!(
+        AccessibleMailbox,
+        r#"
+        SELECT a.id, a.user_id, a.email, a.workspace_id, a.needs_reauth, a.created_at,
+               (CASE WHEN a.user_id = $1 THEN 'owner' ELSE m.role END) AS "role!"
+        FROM mailbox_accounts a
+        LEFT JOIN workspace_members m ON m.workspace_id = a.workspace_id AND m.user_id = $1
+        WHERE a.user_id = $1 OR m.user_id IS NOT NULL
+        ORDER BY (a.user_id = $1) DESC, a.id
+        "#,
+        user_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+/// Users who can open the mailbox: its owner and the members of the workspace it is shared with
+pub async fn users_with_access(account_id: i32) -> Result<Vec<(i32, String)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT u.id AS "id!", u.primary_email AS "email!"
+        FROM users u
+        JOIN mailbox_accounts a ON a.user_id = u.id
+        WHERE a.id = $1
+        UNION
+        SELECT u.id, u.primary_email
+        FROM users u
+        JOIN workspace_members m ON m.user_id = u.id
+        JOIN mailbox_accounts a ON a.workspace_id = m.workspace_id
+        WHERE a.id = $1
+        "#,
+        account_id
+    )
+    .fetch_all(get_pool())
+    .await?;
+
+    Ok(rows.into_iter().map(|r| (r.id, r.email)).collect())
+}
+
+/// The mailbox used when a request doesn't pick one: the user's login mailbox, else the oldest
+pub async fn default_for_user(user_id: i32) -> Result<Option<MailboxAccount>, sqlx::Error> {
+    sqlx::query_as!(
+        MailboxAccount,
+        r#"
+        SELECT a.id, a.user_id, a.email, a.needs_reauth, a.created_at
+        FROM mailbox_accounts a
+        JOIN users u ON u.id = a.user_id
+        WHERE a.user_id = $1
+        ORDER BY (a.email = u.primary_email) DESC, a.id
+        LIMIT 1
+        "#,
+        user_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+/// Removes a mailbox owned by the user (its emails and drafts cascade), returning its email
+pub async fn delete(user_id: i32, account_id: i32) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        "DELETE FROM mailbox_accounts WHERE id = $1 AND user_id = $2 RETURNING email",
+        account_id,
+        user_id
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.map(|r| r.email))
+}
+
+/// Flags the user's Google grant as unusable until they consent again
+pub async fn mark_needs_reauth(email: &str, reason: &str) -> Result<(), String> {
+    sqlx::query!(
+        "UPDATE mailbox_accounts SET needs_reauth = TRUE, reauth_reason = $2 WHERE email = $1",
+        email,
+        reason
+    )
+    .execute(get_pool())
+    .await
+    .map_err(|e| format!("db update error: {:?}", e))?;
+
+    Ok(())
+}
+
+pub async fn needs_reauth(email: &str) -> Result<bool, String> {
+    Ok(grant_status(email).await?.is_some_and(|g| g.needs_reauth))
+}
+
+pub async fn grant_status(email: &str) -> Result<Option<GrantStatus>, String> {
+    sqlx::query_as!(
+        GrantStatus,
+        "SELECT needs_reauth, reauth_reason, granted_scopes FROM mailbox_accounts WHERE email = $1",
+        email
+    )
+    .fetch_optional(get_pool())
+    .await
+    .map_err(|e| format!("db fetch error: {:?}", e))
+}
+
+
+/// Returns the decrypted Google refresh token, if one is stored
+pub async fn get_refresh_token(email: &str) -> Result<Option<String>, String> {
+    let row = sqlx::query!(
+        "SELECT refresh_token, encrypted_dek, key_id FROM mailbox_accounts
+         WHERE email = $1 LIMIT 1",
+        email
+    )
+    .fetch_optional(get_pool())
+    .await
+    .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    match row {
+        Some(r) => reveal(r.refresh_token, r.encrypted_dek, r.key_id).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Re-encrypts every row not yet sealed with `key_id`, including legacy plaintext rows.
+/// Returns how many rows were rewritten.
+pub async fn reencrypt_all(key_id: &str) -> Result<u64, String> {
+    let pool = get_pool();
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, refresh_token, encrypted_dek, key_id FROM mailbox_accounts
+        WHERE key_id IS DISTINCT FROM $1
+        "#,
+        key_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    let mut rewritten = 0;
+    for r in rows {
+        let old_key_id = r.key_id.clone();
+        let plaintext = reveal(r.refresh_token, r.encrypted_dek, r.key_id)?;
+        let sealed = token_crypto::encrypt_with_key(&plaintext, key_id)?;
+
+        // skip rows that changed underneath us (e.g. a fresh login in the meantime)
+        let result = sqlx::query!(
+            r#"
+            UPDATE mailbox_accounts
+            SET refresh_token = $1, encrypted_dek = $2, key_id = $3
+            WHERE id = $4 AND key_id IS NOT DISTINCT FROM $5
+            "#,
+            sealed.ciphertext,
+            sealed.encrypted_dek,
+            sealed.key_id,
+            r.id,
+            old_key_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| format!("db update error: {:?}", e))?;
+
+        rewritten += result.rows_affected();
+    }
+
+    Ok(rewritten)
+}
+
+fn reveal(stored: String, encrypted_dek: Option<String>, key_id: Option<String>) -> Result<String, String> {
+    match (encrypted_dek, key_id) {
+        (Some(dek), Some(key_id)) => token_crypto::decrypt(&stored, &dek, &key_id),
+        // written before encryption at rest was introduced
+        _ => Ok(stored),
+    }
+}
@@ -1,7 +1,24 @@
 use sqlx::{Pool, Postgres};
 use once_cell::sync::OnceCell;
 
-pub mod user_tokens;
+pub mod users;
+pub mod mailbox_accounts;
+pub mod oauth_states;
+pub mod login_codes;
+pub mod sessions;
+pub mod workspaces;
+pub mod approvals;
+pub mod comments;
+pub mod audit;
+pub mod rules;
+pub mod post_send;
+pub mod summaries;
+pub mod embeddings;
+pub mod knowledge;
+pub mod templates;
+pub mod style_profiles;
+pub mod signatures;
+pub mod prompts;
 
 static DB: OnceCell<Pool<Postgres>> = OnceCell::new();
 
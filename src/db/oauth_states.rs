@@ -0,0 +1,50 @@
+use crate::db::get_pool;
+use crate::services::session;
+
+/// How long a user has to finish Google's consent screen
+const OAUTH_STATE_TTL_SECONDS: f64 = 600.0;
+
+pub struct OAuthState {
+    /// Set when an already signed-in user is connecting another mailbox
+    pub link_user_id: Option<i32>,
+}
+
+/// Creates the `state` parameter for a consent redirect
+pub async fn create(link_user_id: Option<i32>) -> Result<String, sqlx::Error> {
+    let pool = get_pool();
+
+    sqlx::query!("DELETE FROM oauth_states WHERE expires_at < NOW()")
+        .execute(pool)
+        .await?;
+
+    let state = session::new_opaque_token();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO oauth_states (state, link_user_id, expires_at)
+        VALUES ($1, $2, NOW() + make_interval(secs => $3))
+        "#,
+        state,
+        link_user_id,
+        OAUTH_STATE_TTL_SECONDS
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(state)
+}
+
+/// Consumes a `state` returned by Google; `None` means it is unknown, expired or already used
+pub async fn consume(state: &str) -> Result<Option<OAuthState>, sqlx::Error> {
+    sqlx::query_as!(
+        OAuthState,
+        r#"
+        DELETE FROM oauth_states
+        WHERE state = $1 AND expires_at > NOW()
+        RETURNING link_user_id
+        "#,
+        state
+    )
+    .fetch_optional(get_pool())
+    .await
+}
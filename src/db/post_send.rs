@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use crate::db::get_pool;
+use crate::services::gmail_triage::TriageActions;
+
+pub const DEFAULT_REPLIED_LABEL: &str = "Drafly/Replied";
+
+/// What happens to the source email in Gmail once a reply to it was sent
+#[derive(Serialize, Deserialize)]
+pub struct Settings {
+    pub mark_read: bool,
+    /// Label applied to the source email; `None` for no label
+    pub label: Option<String>,
+    pub archive: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            mark_read: true,
+            label: Some(DEFAULT_REPLIED_LABEL.to_string()),
+            archive: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn actions(&self) -> TriageActions {
+        TriageActions {
+            add_labels: self.label.iter().cloned().collect(),
+            archive: self.archive,
+            read: self.mark_read.then_some(true),
+            ..TriageActions::default()
+        }
+    }
+}
+
+pub async fn get(account_id: i32) -> Result<Settings, sqlx::Error> {
+    let row = sqlx::query_as!(
+        Settings,
+        "SELECT mark_read, label, archive FROM post_send_settings WHERE account_id = $1",
+        account_id
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.unwrap_or_default())
+}
+
+pub async fn set(account_id: i32, settings: &Settings) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO post_send_settings (account_id, mark_read, label, archive)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (account_id)
+        DO UPDATE SET mark_read = EXCLUDED.mark_read,
+                      label = EXCLUDED.label,
+                      archive = EXCLUDED.archive,
+                      updated_at = NOW()
+        "#,
+        account_id,
+        settings.mark_read,
+        settings.label,
+        settings.archive
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use crate::db::get_pool;
+
+/// One version of a prompt template
+#[derive(Serialize)]
+pub struct Prompt {
+    pub id: i32,
+    /// `None` for built-in prompts
+    pub workspace_id: Option<i32>,
+    pub name: String,
+    pub version: i32,
+    pub description: Option<String>,
+    pub system: String,
+    pub body: String,
+    pub model: String,
+    pub temperature: f64,
+    pub max_tokens: i32,
+    pub created_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Fields of a new version as written by the API
+pub struct PromptInput<'a> {
+    pub name: &'a str,
+    pub description: Option<&'a str>,
+    pub system: &'a str,
+    pub body: &'a str,
+    pub model: &'a str,
+    pub temperature: f64,
+    pub max_tokens: i32,
+}
+
+/// Whether a create lost a race for the next version number
+pub fn is_duplicate_version(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .and_then(|d| d.constraint())
+        .is_some_and(|c| c == "prompt_templates_name_version_key")
+}
+
+/// The version of prompt `name` a mailbox in the workspace generates with: the one the
+/// workspace selected, else the newest built-in version
+pub async fn resolve(workspace_id: Option<i32>, name: &str) -> Result<Option<Prompt>, sqlx::Error> {
+    sqlx::query_as!(
+        Prompt,
+        r#"
+        SELECT id, workspace_id, name, version, description, system, body, model, temperature, max_tokens,
+               created_by, created_at
+        FROM prompt_templates
+        WHERE id = COALESCE(
+            (SELECT prompt_id FROM workspace_prompts WHERE workspace_id = $1 AND name = $2),
+            (SELECT id FROM prompt_templates WHERE workspace_id IS NULL AND name = $2 ORDER BY version DESC LIMIT 1)
+        )
+        "#,
+        workspace_id,
+        name
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+/// Built-in versions and the workspace's own, by name and newest first
+pub async fn list(workspace_id: i32) -> Result<Vec<Prompt>, sqlx::Error> {
+    sqlx::query_as!(
+        Prompt,
+        r#"
+        SELECT id, workspace_id, name, version, description, system, body, model, temperature, max_tokens,
+               created_by, created_at
+        FROM prompt_templates
+        WHERE workspace_id IS NULL OR workspace_id = $1
+        ORDER BY name, version DESC
+        "#,
+        workspace_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+/// A built-in version or one of the workspace's
+pub async fn get(workspace_id: i32, prompt_id: i32) -> Result<Option<Prompt>, sqlx::Error> {
+    sqlx::query_as!(
+        Prompt,
+        r#"
+        SELECT id, workspace_id, name, version, description, system, body, model, temperature, max_tokens,
+               created_by, created_at
+        FROM prompt_templates
+        WHERE id = $2 AND (workspace_id IS NULL OR workspace_id = $1)
+        "#,
+        workspace_id,
+        prompt_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+/// Any version, for tools outside a workspace like `drafly-eval`
+pub async fn find(prompt_id: i32) -> Result<Option<Prompt>, sqlx::Error> {
+    sqlx::query_as!(
+        Prompt,
+        r#"
+        SELECT id, workspace_id, name, version, description, system, body, model, temperature, max_tokens,
+               created_by, created_at
+        FROM prompt_templates
+        WHERE id = $1
+        "#,
+        prompt_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+/// Adds the next version of a prompt to the workspace
+pub async fn create(workspace_id: i32, input: &PromptInput<'_>, created_by: i32) -> Result<Prompt, sqlx::Error> {
+    sqlx::query_as!(
+        Prompt,
+        r#"
+        INSERT INTO prompt_templates
+            (workspace_id, name, version, description, system, body, model, temperature, max_tokens, created_by)
+        SELECT $1, $2, COALESCE(MAX(version), 0) + 1, $3, $4, $5, $6, $7, $8, $9
+        FROM prompt_templates WHERE name = $2
+        RETURNING id, workspace_id, name, version, description, system, body, model, temperature, max_tokens,
+                  created_by, created_at
+        "#,
+        workspace_id,
+        input.name,
+        input.description,
+        input.system,
+        input.body,
+        input.model,
+        input.temperature,
+        input.max_tokens,
+        created_by
+    )
+    .fetch_one(get_pool())
+    .await
+}
+
+/// Makes the workspace generate with `prompt_id` for its prompt name
+pub async fn select(workspace_id: i32, name: &str, prompt_id: i32, selected_by: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO workspace_prompts (workspace_id, name, prompt_id, selected_by)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (workspace_id, name)
+        DO UPDATE SET prompt_id = EXCLUDED.prompt_id,
+                      selected_by = EXCLUDED.selected_by,
+                      selected_at = NOW()
+        "#,
+        workspace_id,
+        name,
+        prompt_id,
+        selected_by
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
+
+/// Goes back to the newest built-in version
+pub async fn clear_selection(workspace_id: i32, name: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM workspace_prompts WHERE workspace_id = $1 AND name = $2",
+        workspace_id,
+        name
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
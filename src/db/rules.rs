@@ -0,0 +1,188 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::types::Json;
+use crate::db::get_pool;
+use crate::services::gmail_triage::TriageActions;
+use crate::services::rules::Conditions;
+
+#[derive(Serialize)]
+pub struct Rule {
+    pub id: i32,
+    pub account_id: i32,
+    pub name: String,
+    pub enabled: bool,
+    pub priority: i32,
+    pub conditions: Conditions,
+    /// Triage applied to matching emails
+    pub actions: TriageActions,
+    /// Whether matching emails get a drafted reply
+    pub generate_draft: bool,
+    pub tone: String,
+    pub instructions: Option<String>,
+    /// Reply template the draft is made from instead of generating one
+    pub template_id: Option<i32>,
+    pub created_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Fields of a rule as written by the API
+pub struct RuleInput<'a> {
+    pub name: &'a str,
+    pub enabled: bool,
+    pub priority: i32,
+    pub conditions: &'a Conditions,
+    pub actions: &'a TriageActions,
+    pub generate_draft: bool,
+    pub tone: &'a str,
+    pub instructions: Option<&'a str>,
+    pub template_id: Option<i32>,
+}
+
+struct RuleRow {
+    id: i32,
+    account_id: i32,
+    name: String,
+    enabled: bool,
+    priority: i32,
+    conditions: Json<Conditions>,
+    actions: Json<TriageActions>,
+    generate_draft: bool,
+    tone: String,
+    instructions: Option<String>,
+    template_id: Option<i32>,
+    created_by: Option<i32>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<RuleRow> for Rule {
+    fn from(r: RuleRow) -> Rule {
+        Rule {
+            id: r.id,
+            account_id: r.account_id,
+            name: r.name,
+            enabled: r.enabled,
+            priority: r.priority,
+            conditions: r.conditions.0,
+            actions: r.actions.0,
+            generate_draft: r.generate_draft,
+            tone: r.tone,
+            instructions: r.instructions,
+            template_id: r.template_id,
+            created_by: r.created_by,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+        }
+    }
+}
+
+/// All rules of a mailbox in evaluation order
+pub async fn list(account_id: i32) -> Result<Vec<Rule>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        RuleRow,
+        r#"
+        SELECT id, account_id, name, enabled, priority, conditions AS "conditions: Json<Conditions>",
+               actions AS "actions: Json<TriageActions>", generate_draft, tone, instructions,
+               template_id, created_by, created_at, updated_at
+        FROM draft_rules
+        WHERE account_id = $1
+        ORDER BY priority, id
+        "#,
+        account_id
+    )
+    .fetch_all(get_pool())
+    .await?;
+
+    Ok(rows.into_iter().map(Rule::from).collect())
+}
+
+pub async fn list_enabled(account_id: i32) -> Result<Vec<Rule>, sqlx::Error> {
+    let rules = list(account_id).await?;
+    Ok(rules.into_iter().filter(|r| r.enabled).collect())
+}
+
+pub async fn get(account_id: i32, rule_id: i32) -> Result<Option<Rule>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        RuleRow,
+        r#"
+        SELECT id, account_id, name, enabled, priority, conditions AS "conditions: Json<Conditions>",
+               actions AS "actions: Json<TriageActions>", generate_draft, tone, instructions,
+               template_id, created_by, created_at, updated_at
+        FROM draft_rules
+        WHERE id = $1 AND account_id = $2
+        "#,
+        rule_id,
+        account_id
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.map(Rule::from))
+}
+
+pub async fn create(account_id: i32, input: &RuleInput<'_>, created_by: i32) -> Result<i32, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO draft_rules
+            (account_id, name, enabled, priority, conditions, actions, generate_draft, tone, instructions,
+             template_id, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING id
+        "#,
+        account_id,
+        input.name,
+        input.enabled,
+        input.priority,
+        Json(input.conditions) as _,
+        Json(input.actions) as _,
+        input.generate_draft,
+        input.tone,
+        input.instructions,
+        input.template_id,
+        created_by
+    )
+    .fetch_one(get_pool())
+    .await?;
+
+    Ok(row.id)
+}
+
+/// Returns the number of rows updated (0 when the rule isn't in the mailbox)
+pub async fn update(account_id: i32, rule_id: i32, input: &RuleInput<'_>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE draft_rules
+        SET name = $3, enabled = $4, priority = $5, conditions = $6, actions = $7, generate_draft = $8,
+            tone = $9, instructions = $10, template_id = $11, updated_at = NOW()
+        WHERE id = $1 AND account_id = $2
+        "#,
+        rule_id,
+        account_id,
+        input.name,
+        input.enabled,
+        input.priority,
+        Json(input.conditions) as _,
+        Json(input.actions) as _,
+        input.generate_draft,
+        input.tone,
+        input.instructions,
+        input.template_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn delete(account_id: i32, rule_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM draft_rules WHERE id = $1 AND account_id = $2",
+        rule_id,
+        account_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
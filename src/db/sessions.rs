@@ -0,0 +1,140 @@
+use uuid::Uuid;
+use crate::db::get_pool;
+use crate::services::session;
+
+/// Lifetime of a refresh token; every rotation issues a fresh one with a new expiry
+pub const REFRESH_TOKEN_DAYS: i32 = 30;
+
+pub enum RotateOutcome {
+    /// The refresh token was valid and has been replaced
+    Rotated {
+        session_id: String,
+        user_id: i32,
+        refresh_token: String,
+    },
+    /// An already-rotated refresh token was presented again; the session was revoked
+    Reused,
+    Invalid,
+}
+
+/// Opens a new session and returns `(session_id, refresh_token)`
+pub async fn create(user_id: i32, user_agent: Option<&str>) -> Result<(String, String), sqlx::Error> {
+    let session_id = Uuid::new_v4().to_string();
+    let refresh_token = session::new_opaque_token();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (id, user_id, refresh_token_hash, user_agent, expires_at)
+        VALUES ($1, $2, $3, $4, NOW() + make_interval(days => $5))
+        "#,
+        session_id,
+        user_id,
+        session::hash_token(&refresh_token),
+        user_agent,
+        REFRESH_TOKEN_DAYS
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok((session_id, refresh_token))
+}
+
+/// Exchanges a refresh token for a new one on the same session.
+/// Presenting a token that was already rotated away revokes the whole session,
+/// since it means the token was copied.
+pub async fn rotate(refresh_token: &str) -> Result<RotateOutcome, sqlx::Error> {
+    let pool = get_pool();
+    let presented_hash = session::hash_token(refresh_token);
+    let new_token = session::new_opaque_token();
+
+    let row = sqlx::query!(
+        r#"
+        UPDATE sessions
+        SET previous_token_hash = refresh_token_hash,
+            refresh_token_hash = $2,
+            last_used_at = NOW(),
+            expires_at = NOW() + make_interval(days => $3)
+        WHERE refresh_token_hash = $1
+          AND revoked_at IS NULL
+          AND expires_at > NOW()
+        RETURNING id, user_id
+        "#,
+        presented_hash,
+        session::hash_token(&new_token),
+        REFRESH_TOKEN_DAYS
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(r) = row {
+        return Ok(RotateOutcome::Rotated {
+            session_id: r.id,
+            user_id: r.user_id,
+            refresh_token: new_token,
+        });
+    }
+
+    let reused = sqlx::query!(
+        r#"
+        UPDATE sessions SET revoked_at = NOW()
+        WHERE previous_token_hash = $1 AND revoked_at IS NULL
+        RETURNING id
+        "#,
+        presented_hash
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    match reused {
+        Some(r) => {
+            log::warn!("Refresh token reuse detected, revoked session {}", r.id);
+            Ok(RotateOutcome::Reused)
+        }
+        None => Ok(RotateOutcome::Invalid),
+    }
+}
+
+/// Returns the login email of the session's user if the session backing an access
+/// token is still usable
+pub async fn active_user_email(session_id: &str, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT u.primary_email FROM sessions s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.id = $1 AND s.user_id = $2
+          AND s.revoked_at IS NULL AND s.expires_at > NOW()
+        "#,
+        session_id,
+        user_id
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.map(|r| r.primary_email))
+}
+
+pub async fn revoke(session_id: &str, user_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE sessions SET revoked_at = NOW()
+         WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        session_id,
+        user_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
+
+/// Revokes every session of the user, returning how many were still active
+pub async fn revoke_all(user_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE sessions SET revoked_at = NOW()
+         WHERE user_id = $1 AND revoked_at IS NULL",
+        user_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
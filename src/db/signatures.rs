@@ -0,0 +1,60 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use crate::db::get_pool;
+
+/// Signature appended to replies sent from a mailbox
+#[derive(Serialize)]
+pub struct Signature {
+    pub plain: Option<String>,
+    pub html: Option<String>,
+    pub enabled: bool,
+    /// `manual`, or `gmail` when imported from the Gmail sendAs settings
+    pub source: String,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Signature {
+    /// Whether sent replies get the signature
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.plain.as_deref().is_some_and(|p| !p.trim().is_empty())
+    }
+}
+
+pub async fn get(account_id: i32) -> Result<Option<Signature>, sqlx::Error> {
+    sqlx::query_as!(
+        Signature,
+        "SELECT plain, html, enabled, source, updated_at FROM signatures WHERE account_id = $1",
+        account_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+/// The signature sent replies get, if the mailbox has an enabled one
+pub async fn active(account_id: i32) -> Result<Option<Signature>, sqlx::Error> {
+    Ok(get(account_id).await?.filter(Signature::is_active))
+}
+
+pub async fn set(account_id: i32, plain: Option<&str>, html: Option<&str>, enabled: bool, source: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO signatures (account_id, plain, html, enabled, source)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (account_id)
+        DO UPDATE SET plain = EXCLUDED.plain,
+                      html = EXCLUDED.html,
+                      enabled = EXCLUDED.enabled,
+                      source = EXCLUDED.source,
+                      updated_at = NOW()
+        "#,
+        account_id,
+        plain,
+        html,
+        enabled,
+        source
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use crate::db::get_pool;
+use crate::services::style::Analysis;
+
+#[derive(Serialize)]
+pub struct StyleProfile {
+    pub user_id: i32,
+    /// e.g. `Hi <name>,`
+    pub greeting: Option<String>,
+    pub sign_off: Option<String>,
+    /// Median length of the body, in words
+    pub typical_words: Option<i32>,
+    /// `formal`, `neutral` or `casual`
+    pub formality: Option<String>,
+    pub common_phrases: Vec<String>,
+    /// Guidance written by the user
+    pub notes: Option<String>,
+    /// Whether generated drafts follow the profile
+    pub enabled: bool,
+    /// Sent messages the profile was learned from
+    pub sample_count: i32,
+    pub source_account_id: Option<i32>,
+    pub analyzed_at: Option<NaiveDateTime>,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Fields of a profile as written by the API
+pub struct ProfileInput<'a> {
+    pub greeting: Option<&'a str>,
+    pub sign_off: Option<&'a str>,
+    pub typical_words: Option<i32>,
+    pub formality: Option<&'a str>,
+    pub common_phrases: &'a [String],
+    pub notes: Option<&'a str>,
+    pub enabled: bool,
+}
+
+pub async fn get(user_id: i32) -> Result<Option<StyleProfile>, sqlx::Error> {
+    sqlx::query_as!(
+        StyleProfile,
+        r#"
+        SELECT user_id, greeting, sign_off, typical_words, formality, common_phrases, notes, enabled,
+               sample_count, source_account_id, analyzed_at, updated_at
+        FROM style_profiles
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+/// The enabled profile a draft in the mailbox is written with: the requesting user's, or
+/// the mailbox owner's for drafts written by automation
+pub async fn for_generation(user_id: Option<i32>, account_id: i32) -> Result<Option<StyleProfile>, sqlx::Error> {
+    sqlx::query_as!(
+        StyleProfile,
+        r#"
+        SELECT user_id, greeting, sign_off, typical_words, formality, common_phrases, notes, enabled,
+               sample_count, source_account_id, analyzed_at, updated_at
+        FROM style_profiles
+        WHERE user_id = COALESCE($1, (SELECT user_id FROM mailbox_accounts WHERE id = $2))
+          AND enabled
+        "#,
+        user_id,
+        account_id
+    )
+    .fetch_optional(get_pool())
+    .await
+}
+
+/// Stores a learned profile, replacing the learned fields of an existing one. Notes and
+/// `enabled` are the user's and are kept.
+pub async fn save_analysis(user_id: i32, account_id: i32, analysis: &Analysis) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO style_profiles
+            (user_id, greeting, sign_off, typical_words, formality, common_phrases, sample_count,
+             source_account_id, analyzed_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW(), NOW())
+        ON CONFLICT (user_id) DO UPDATE SET
+            greeting = EXCLUDED.greeting,
+            sign_off = EXCLUDED.sign_off,
+            typical_words = EXCLUDED.typical_words,
+            formality = EXCLUDED.formality,
+            common_phrases = EXCLUDED.common_phrases,
+            sample_count = EXCLUDED.sample_count,
+            source_account_id = EXCLUDED.source_account_id,
+            analyzed_at = EXCLUDED.analyzed_at,
+            updated_at = EXCLUDED.updated_at
+        "#,
+        user_id,
+        analysis.greeting,
+        analysis.sign_off,
+        analysis.typical_words,
+        analysis.formality,
+        &analysis.common_phrases,
+        analysis.sample_count,
+        account_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
+
+/// Replaces the profile with the user's edits, creating it if there is none yet
+pub async fn save(user_id: i32, input: &ProfileInput<'_>) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO style_profiles (user_id, greeting, sign_off, typical_words, formality, common_phrases, notes, enabled)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (user_id) DO UPDATE SET
+            greeting = EXCLUDED.greeting,
+            sign_off = EXCLUDED.sign_off,
+            typical_words = EXCLUDED.typical_words,
+            formality = EXCLUDED.formality,
+            common_phrases = EXCLUDED.common_phrases,
+            notes = EXCLUDED.notes,
+            enabled = EXCLUDED.enabled,
+            updated_at = NOW()
+        "#,
+        user_id,
+        input.greeting,
+        input.sign_off,
+        input.typical_words,
+        input.formality,
+        input.common_phrases,
+        input.notes,
+        input.enabled
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete(user_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM style_profiles WHERE user_id = $1", user_id)
+        .execute(get_pool())
+        .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,88 @@
+use chrono::NaiveDateTime;
+use sqlx::types::Json;
+use crate::db::get_pool;
+use crate::services::summarizer::Summary;
+
+/// A stored summary and what it was generated from
+pub struct Cached {
+    pub source_hash: String,
+    pub message_count: i32,
+    pub summary: Summary,
+    pub created_at: NaiveDateTime,
+}
+
+pub async fn get(account_id: i32, scope: &str, target: &str) -> Result<Option<Cached>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT source_hash, message_count, summary,
+               key_asks AS "key_asks: Json<Vec<String>>",
+               deadlines AS "deadlines: Json<Vec<String>>",
+               open_questions AS "open_questions: Json<Vec<String>>",
+               created_at
+        FROM summaries
+        WHERE account_id = $1 AND scope = $2 AND target = $3
+        "#,
+        account_id,
+        scope,
+        target
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.map(|r| Cached {
+        source_hash: r.source_hash,
+        message_count: r.message_count,
+        summary: Summary {
+            summary: r.summary,
+            key_asks: r.key_asks.0,
+            deadlines: r.deadlines.0,
+            open_questions: r.open_questions.0,
+        },
+        created_at: r.created_at,
+    }))
+}
+
+/// Stores a summary, replacing the previous one for the same email or thread
+pub async fn put(account_id: i32, scope: &str, target: &str, source_hash: &str, message_count: i32, summary: &Summary) -> Result<NaiveDateTime, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO summaries (account_id, scope, target, source_hash, message_count, summary, key_asks, deadlines, open_questions)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (account_id, scope, target)
+        DO UPDATE SET source_hash = EXCLUDED.source_hash,
+                      message_count = EXCLUDED.message_count,
+                      summary = EXCLUDED.summary,
+                      key_asks = EXCLUDED.key_asks,
+                      deadlines = EXCLUDED.deadlines,
+                      open_questions = EXCLUDED.open_questions,
+                      created_at = NOW()
+        RETURNING created_at
+        "#,
+        account_id,
+        scope,
+        target,
+        source_hash,
+        message_count,
+        summary.summary,
+        Json(&summary.key_asks) as _,
+        Json(&summary.deadlines) as _,
+        Json(&summary.open_questions) as _
+    )
+    .fetch_one(get_pool())
+    .await?;
+
+    Ok(row.created_at)
+}
+
+/// Drops the cached summary of a thread, e.g. when a new message arrived in it
+pub async fn invalidate_thread(account_id: i32, thread_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM summaries WHERE account_id = $1 AND scope = 'thread' AND target = $2",
+        account_id,
+        thread_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(())
+}
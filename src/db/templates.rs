@@ -0,0 +1,181 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::types::Json;
+use crate::db::get_pool;
+use crate::services::templates::Field;
+
+#[derive(Serialize)]
+pub struct Template {
+    pub id: i32,
+    pub account_id: i32,
+    pub folder: Option<String>,
+    pub name: String,
+    pub body: String,
+    pub fields: Vec<Field>,
+    pub created_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Fields of a template as written by the API
+pub struct TemplateInput<'a> {
+    pub folder: Option<&'a str>,
+    pub name: &'a str,
+    pub body: &'a str,
+    pub fields: &'a [Field],
+}
+
+struct TemplateRow {
+    id: i32,
+    account_id: i32,
+    folder: Option<String>,
+    name: String,
+    body: String,
+    fields: Json<Vec<Field>>,
+    created_by: Option<i32>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<TemplateRow> for Template {
+    fn from(r: TemplateRow) -> Template {
+        Template {
+            id: r.id,
+            account_id: r.account_id,
+            folder: r.folder,
+            name: r.name,
+            body: r.body,
+            fields: r.fields.0,
+            created_by: r.created_by,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Folder {
+    /// `None` for templates outside any folder
+    pub folder: Option<String>,
+    pub count: i64,
+}
+
+/// Whether a write failed because the folder already has a template with that name
+pub fn is_duplicate_name(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .and_then(|d| d.constraint())
+        .is_some_and(|c| c == "reply_templates_folder_name_key")
+}
+
+/// Templates of a mailbox by folder and name. `folder` limits the list to one folder and its
+/// subfolders (`""` for the top level only); `q` matches name or body.
+pub async fn list(account_id: i32, folder: Option<&str>, q: Option<&str>) -> Result<Vec<Template>, sqlx::Error> {
+    let rows = sqlx::query_as!(
+        TemplateRow,
+        r#"
+        SELECT id, account_id, folder, name, body, fields AS "fields: Json<Vec<Field>>",
+               created_by, created_at, updated_at
+        FROM reply_templates
+        WHERE account_id = $1
+          AND ($2::text IS NULL
+               OR ($2 = '' AND folder IS NULL)
+               OR folder = $2 OR left(folder, length($2) + 1) = $2 || '/')
+          AND ($3::text IS NULL OR name ILIKE $3 OR body ILIKE $3)
+        ORDER BY folder NULLS FIRST, lower(name)
+        "#,
+        account_id,
+        folder,
+        q
+    )
+    .fetch_all(get_pool())
+    .await?;
+
+    Ok(rows.into_iter().map(Template::from).collect())
+}
+
+pub async fn folders(account_id: i32) -> Result<Vec<Folder>, sqlx::Error> {
+    sqlx::query_as!(
+        Folder,
+        r#"
+        SELECT folder, COUNT(*) AS "count!"
+        FROM reply_templates
+        WHERE account_id = $1
+        GROUP BY folder
+        ORDER BY folder NULLS FIRST
+        "#,
+        account_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+pub async fn get(account_id: i32, template_id: i32) -> Result<Option<Template>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        TemplateRow,
+        r#"
+        SELECT id, account_id, folder, name, body, fields AS "fields: Json<Vec<Field>>",
+               created_by, created_at, updated_at
+        FROM reply_templates
+        WHERE id = $1 AND account_id = $2
+        "#,
+        template_id,
+        account_id
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.map(Template::from))
+}
+
+pub async fn create(account_id: i32, input: &TemplateInput<'_>, created_by: i32) -> Result<i32, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO reply_templates (account_id, folder, name, body, fields, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id
+        "#,
+        account_id,
+        input.folder,
+        input.name,
+        input.body,
+        Json(input.fields) as _,
+        created_by
+    )
+    .fetch_one(get_pool())
+    .await?;
+
+    Ok(row.id)
+}
+
+/// Returns the number of rows updated (0 when the template isn't in the mailbox)
+pub async fn update(account_id: i32, template_id: i32, input: &TemplateInput<'_>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE reply_templates
+        SET folder = $3, name = $4, body = $5, fields = $6, updated_at = NOW()
+        WHERE id = $1 AND account_id = $2
+        "#,
+        template_id,
+        account_id,
+        input.folder,
+        input.name,
+        input.body,
+        Json(input.fields) as _
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn delete(account_id: i32, template_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM reply_templates WHERE id = $1 AND account_id = $2",
+        template_id,
+        account_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,26 @@
+use crate::db::get_pool;
+
+/// Returns the id of the drafly user whose login email is `primary_email`, creating it if needed
+pub async fn find_or_create(primary_email: &str) -> Result<i32, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO users (primary_email)
+        VALUES ($1)
+        ON CONFLICT (primary_email) DO UPDATE SET primary_email = EXCLUDED.primary_email
+        RETURNING id
+        "#,
+        primary_email
+    )
+    .fetch_one(get_pool())
+    .await?;
+
+    Ok(row.id)
+}
+
+pub async fn primary_email(user_id: i32) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!("SELECT primary_email FROM users WHERE id = $1", user_id)
+        .fetch_optional(get_pool())
+        .await?;
+
+    Ok(row.map(|r| r.primary_email))
+}
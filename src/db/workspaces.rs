@@ -0,0 +1,303 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use crate::db::get_pool;
+use crate::services::session;
+
+/// How long an invitation link stays valid
+const INVITATION_TTL_DAYS: i32 = 7;
+
+/// Workspace roles, ordered from least to most privileged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Approver,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Approver => "approver",
+            Role::Owner => "owner",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "approver" => Some(Role::Approver),
+            "owner" => Some(Role::Owner),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct WorkspaceSummary {
+    pub id: i32,
+    pub name: String,
+    pub role: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct Member {
+    pub user_id: i32,
+    pub email: String,
+    pub role: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct Invitation {
+    pub id: i32,
+    pub email: String,
+    pub role: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub accepted_at: Option<NaiveDateTime>,
+}
+
+/// Creates a workspace with `user_id` as its owner and returns its id
+pub async fn create(name: &str, user_id: i32) -> Result<i32, sqlx::Error> {
+    let mut tx = get_pool().begin().await?;
+
+    let row = sqlx::query!(
+        "INSERT INTO workspaces (name, created_by) VALUES ($1, $2) RETURNING id",
+        name,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO workspace_members (workspace_id, user_id, role) VALUES ($1, $2, 'owner')",
+        row.id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(row.id)
+}
+
+pub async fn list_for_user(user_id: i32) -> Result<Vec<WorkspaceSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        WorkspaceSummary,
+        r#"
+        SELECT w.id, w.name, m.role, w.created_at
+        FROM workspaces w
+        JOIN workspace_members m ON m.workspace_id = w.id
+        WHERE m.user_id = $1
+        ORDER BY w.id
+        "#,
+        user_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+pub async fn member_role(workspace_id: i32, user_id: i32) -> Result<Option<Role>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT role FROM workspace_members WHERE workspace_id = $1 AND user_id = $2",
+        workspace_id,
+        user_id
+    )
+    .fetch_optional(get_pool())
+    .await?;
+
+    Ok(row.and_then(|r| Role::parse(&r.role)))
+}
+
+pub async fn list_members(workspace_id: i32) -> Result<Vec<Member>, sqlx::Error> {
+    sqlx::query_as!(
+        Member,
+        r#"
+        SELECT m.user_id, u.primary_email AS email, m.role, m.created_at
+        FROM workspace_members m
+        JOIN users u ON u.id = m.user_id
+        WHERE m.workspace_id = $1
+        ORDER BY m.created_at
+        "#,
+        workspace_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+pub async fn set_member_role(workspace_id: i32, user_id: i32, role: Role) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE workspace_members SET role = $3 WHERE workspace_id = $1 AND user_id = $2",
+        workspace_id,
+        user_id,
+        role.as_str()
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn remove_member(workspace_id: i32, user_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM workspace_members WHERE workspace_id = $1 AND user_id = $2",
+        workspace_id,
+        user_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn owner_count(workspace_id: i32) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM workspace_members WHERE workspace_id = $1 AND role = 'owner'"#,
+        workspace_id
+    )
+    .fetch_one(get_pool())
+    .await?;
+
+    Ok(row.count)
+}
+
+/// Creates an invitation and returns `(invitation_id, raw_token)`; only the hash is stored
+pub async fn create_invitation(workspace_id: i32, email: &str, role: Role, invited_by: i32) -> Result<(i32, String), sqlx::Error> {
+    let token = session::new_opaque_token();
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO workspace_invitations (workspace_id, email, role, token_hash, invited_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5, NOW() + make_interval(days => $6))
+        RETURNING id
+        "#,
+        workspace_id,
+        email,
+        role.as_str(),
+        session::hash_token(&token),
+        invited_by,
+        INVITATION_TTL_DAYS
+    )
+    .fetch_one(get_pool())
+    .await?;
+
+    Ok((row.id, token))
+}
+
+pub async fn list_invitations(workspace_id: i32) -> Result<Vec<Invitation>, sqlx::Error> {
+    sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT id, email, role, created_at, expires_at, accepted_at
+        FROM workspace_invitations
+        WHERE workspace_id = $1
+        ORDER BY created_at DESC
+        "#,
+        workspace_id
+    )
+    .fetch_all(get_pool())
+    .await
+}
+
+pub async fn revoke_invitation(workspace_id: i32, invitation_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM workspace_invitations WHERE id = $1 AND workspace_id = $2 AND accepted_at IS NULL",
+        invitation_id,
+        workspace_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Accepts a pending invitation addressed to `user_email`, returning the workspace id and the
+/// member's role. An invitation never lowers the role of someone already in the workspace, so
+/// accepting one can't leave a workspace without an owner.
+pub async fn accept_invitation(token: &str, user_id: i32, user_email: &str) -> Result<Option<(i32, Role)>, sqlx::Error> {
+    let mut tx = get_pool().begin().await?;
+
+    let invitation = sqlx::query!(
+        r#"
+        UPDATE workspace_invitations
+        SET accepted_at = NOW(), accepted_by = $2
+        WHERE token_hash = $1
+          AND accepted_at IS NULL
+          AND expires_at > NOW()
+          AND LOWER(email) = LOWER($3)
+        RETURNING workspace_id, role
+        "#,
+        session::hash_token(token),
+        user_id,
+        user_email
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let invitation = match invitation {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+
+    let current = sqlx::query!(
+        "SELECT role FROM workspace_members WHERE workspace_id = $1 AND user_id = $2 FOR UPDATE",
+        invitation.workspace_id,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .and_then(|m| Role::parse(&m.role));
+
+    let role = match Role::parse(&invitation.role).max(current) {
+        Some(role) => role,
+        None => return Ok(None),
+    };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO workspace_members (workspace_id, user_id, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (workspace_id, user_id) DO UPDATE SET role = EXCLUDED.role
+        "#,
+        invitation.workspace_id,
+        user_id,
+        role.as_str()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some((invitation.workspace_id, role)))
+}
+
+/// Shares a mailbox owned by `owner_user_id` with the workspace
+pub async fn share_mailbox(workspace_id: i32, account_id: i32, owner_user_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE mailbox_accounts SET workspace_id = $1 WHERE id = $2 AND user_id = $3",
+        workspace_id,
+        account_id,
+        owner_user_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn unshare_mailbox(workspace_id: i32, account_id: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE mailbox_accounts SET workspace_id = NULL WHERE id = $1 AND workspace_id = $2",
+        account_id,
+        workspace_id
+    )
+    .execute(get_pool())
+    .await?;
+
+    Ok(result.rows_affected())
+}
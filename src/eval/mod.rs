@@ -0,0 +1,552 @@
+//! Offline evaluation of reply generation, run by the `drafly-eval` binary: a fixture set of
+//! emails goes through the reply prompt of one or two configurations, and every reply is
+//! scored with rule checks and, optionally, a model acting as judge.
+
+pub mod report;
+
+use std::path::Path;
+use std::time::Instant;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::db;
+use crate::db::prompts::Prompt;
+use crate::services::groq_ai::{self, Reply, ReplyPrompt, Usage};
+use crate::services::{guardrails, prompts, quality};
+
+/// Length bounds of a reply, in words, when the fixture doesn't set them
+const DEFAULT_MIN_WORDS: usize = 10;
+const DEFAULT_MAX_WORDS: usize = 250;
+
+/// One email to reply to, with what a good reply looks like
+#[derive(Deserialize)]
+pub struct Fixture {
+    pub id: String,
+    pub sender: String,
+    #[serde(default)]
+    pub subject: String,
+    pub body: String,
+    #[serde(default = "default_tone")]
+    pub tone: String,
+    pub instructions: Option<String>,
+    /// Knowledge base excerpts put in the prompt
+    #[serde(default)]
+    pub knowledge: Vec<String>,
+    /// Past replies put in the prompt as examples
+    #[serde(default)]
+    pub examples: Vec<String>,
+    /// Writing style section, as `style::prompt_section` writes it
+    pub style: Option<String>,
+    /// Whether the mailbox has a signature, so the reply must not sign off
+    #[serde(default)]
+    pub signature: bool,
+    /// Language the reply must be in; detected from the email when unset
+    pub language: Option<String>,
+    pub min_words: Option<usize>,
+    pub max_words: Option<usize>,
+    /// Text the reply must contain, ignoring case
+    #[serde(default)]
+    pub must_include: Vec<String>,
+    /// Text the reply must not contain, ignoring case
+    #[serde(default)]
+    pub must_not_include: Vec<String>,
+    /// What the mock provider answers instead of its made-up reply
+    pub mock_reply: Option<String>,
+}
+
+fn default_tone() -> String {
+    "friendly".to_string()
+}
+
+/// Reads a JSONL fixture file. Blank lines and lines starting with `#` are skipped.
+pub fn load_fixtures(path: &Path) -> Result<Vec<Fixture>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let fixtures: Vec<Fixture> = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))
+        })
+        .collect::<Result<_, _>>()?;
+
+    if fixtures.is_empty() {
+        return Err(format!("{}: no fixtures", path.display()));
+    }
+    Ok(fixtures)
+}
+
+/// Where replies come from
+#[derive(Clone, Copy, PartialEq)]
+pub enum Provider {
+    /// Groq's chat completions API, as used in production
+    Groq,
+    /// Made-up replies derived from the prompt, the same for the same prompt. No network or
+    /// API key needed; for checking fixtures and the harness itself.
+    Mock,
+}
+
+impl Provider {
+    pub fn parse(name: &str) -> Result<Provider, String> {
+        match name {
+            "groq" => Ok(Provider::Groq),
+            "mock" => Ok(Provider::Mock),
+            other => Err(format!("unknown provider {} (groq or mock)", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::Groq => "groq",
+            Provider::Mock => "mock",
+        }
+    }
+
+    async fn reply(&self, prompt: &Prompt, fixture: &Fixture) -> Result<Reply, String> {
+        let reply = ReplyPrompt {
+            email_body: &fixture.body,
+            sender: &fixture.sender,
+            subject: &fixture.subject,
+            tone: &fixture.tone,
+            instructions: fixture.instructions.as_deref(),
+            examples: &fixture.examples,
+            knowledge: &fixture.knowledge,
+            style: fixture.style.as_deref(),
+            signature: fixture.signature,
+        };
+        match self {
+            Provider::Groq => groq_ai::generate_reply(prompt, &reply).await,
+            Provider::Mock => {
+                let (system, user) = groq_ai::reply_messages(prompt, &reply);
+                let content = fixture
+                    .mock_reply
+                    .clone()
+                    .unwrap_or_else(|| mock_reply(fixture, &format!("{}{}{}", prompt.model, system, user)));
+                Ok(Reply {
+                    usage: Usage {
+                        prompt_tokens: Some(estimate_tokens(&system) + estimate_tokens(&user)),
+                        completion_tokens: Some(estimate_tokens(&content)),
+                    },
+                    content,
+                })
+            }
+        }
+    }
+}
+
+/// Roughly four characters a token, as with most English text
+fn estimate_tokens(text: &str) -> i32 {
+    text.chars().count().div_ceil(4) as i32
+}
+
+/// A plausible reply picked by a hash of the prompt, so a changed prompt or model can
+/// change the reply but a rerun can't
+fn mock_reply(fixture: &Fixture, prompt: &str) -> String {
+    const MIDDLES: [&str; 4] = [
+        "I have looked into this and will get back to you with the details shortly.",
+        "I have passed this on to the team and we will follow up as soon as we can.",
+        "Could you send me any further details that might help us look into it?",
+        "We are on it and will keep you posted on the progress.",
+    ];
+    let hash = Sha256::digest(prompt.as_bytes());
+    let name = fixture
+        .sender
+        .split(['<', '@'])
+        .next()
+        .and_then(|s| s.split_whitespace().next())
+        .filter(|s| !s.is_empty())
+        .unwrap_or("there");
+    let about = if fixture.subject.is_empty() {
+        "your email".to_string()
+    } else {
+        format!("your email about \"{}\"", fixture.subject)
+    };
+
+    let mut reply = format!("Hi {},\n\nThanks for {}. ", name, about);
+    let sentences = 1 + hash[0] as usize % 2;
+    for i in 0..sentences {
+        if i > 0 {
+            reply.push(' ');
+        }
+        reply.push_str(MIDDLES[(hash[1] as usize + i) % MIDDLES.len()]);
+    }
+    if !fixture.signature {
+        reply.push_str("\n\nBest regards");
+    }
+    reply
+}
+
+/// Settings of one side of a comparison, as read from a JSON file
+#[derive(Deserialize)]
+pub struct ConfigFile {
+    /// Name in the report; the file name when unset
+    pub label: Option<String>,
+    /// `groq` (default) or `mock`
+    pub provider: Option<String>,
+    /// Version from the prompt registry; needs `DATABASE_URL`
+    pub prompt_id: Option<i32>,
+    /// A `reply` prompt that isn't in the registry, as `system` and `body`
+    pub system: Option<String>,
+    pub body: Option<String>,
+    /// Override the prompt's model settings
+    pub model: Option<String>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<i32>,
+}
+
+/// A prompt, its model settings and where replies come from
+pub struct Variant {
+    pub label: String,
+    pub provider: Provider,
+    pub prompt: Prompt,
+}
+
+impl ConfigFile {
+    /// Whether loading the config reads the prompt registry
+    pub fn uses_registry(&self) -> bool {
+        self.prompt_id.is_some()
+    }
+}
+
+pub fn load_config(path: &Path) -> Result<ConfigFile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Resolves a config read from `path`. `provider` replaces the config's provider when given.
+pub async fn variant(config: ConfigFile, path: &Path, provider: Option<Provider>) -> Result<Variant, String> {
+    let mut prompt = match (config.prompt_id, &config.system, &config.body) {
+        (Some(id), None, None) => db::prompts::find(id)
+            .await
+            .map_err(|e| format!("db fetch error: {:?}", e))?
+            .ok_or_else(|| format!("{}: no prompt with id {}", path.display(), id))?,
+        (None, Some(system), Some(body)) => {
+            prompts::validate("reply", system, body).map_err(|e| format!("{}: {}", path.display(), e))?;
+            Prompt {
+                id: 0,
+                workspace_id: None,
+                name: "reply".to_string(),
+                version: 0,
+                description: None,
+                system: system.clone(),
+                body: body.clone(),
+                model: groq_ai::DEFAULT_MODEL.to_string(),
+                temperature: 0.7,
+                max_tokens: 500,
+                created_by: None,
+                created_at: Utc::now().naive_utc(),
+            }
+        }
+        _ => return Err(format!("{}: set either prompt_id or both system and body", path.display())),
+    };
+    if let Some(model) = config.model {
+        prompt.model = model;
+    }
+    if let Some(temperature) = config.temperature {
+        prompt.temperature = temperature;
+    }
+    if let Some(max_tokens) = config.max_tokens {
+        prompt.max_tokens = max_tokens;
+    }
+
+    let provider = match provider {
+        Some(p) => p,
+        None => Provider::parse(config.provider.as_deref().unwrap_or("groq"))?,
+    };
+    let label = config
+        .label
+        .or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "config".to_string());
+
+    Ok(Variant { label, provider, prompt })
+}
+
+/// Result of one rule check on a reply
+#[derive(Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    /// What failed
+    pub detail: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, failure: Option<String>) -> Check {
+        Check { name, passed: failure.is_none(), detail: failure }
+    }
+}
+
+/// Score from the judge model
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Judgement {
+    /// 1 (unusable) to 5 (send as is)
+    pub score: u8,
+    pub reason: String,
+}
+
+/// How one configuration did on one fixture
+#[derive(Serialize)]
+pub struct Outcome {
+    pub fixture_id: String,
+    pub reply: Option<String>,
+    /// Why there is no reply
+    pub error: Option<String>,
+    pub checks: Vec<Check>,
+    pub words: usize,
+    pub language: Option<&'static str>,
+    pub judgement: Option<Judgement>,
+    pub judge_error: Option<String>,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub latency_ms: u64,
+}
+
+impl Outcome {
+    /// Whether the reply was written and passed every check
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.checks.iter().all(|c| c.passed)
+    }
+}
+
+/// Rule checks of a reply to a fixture. The language check is left out when the language
+/// of the email or the reply can't be told.
+pub fn check(fixture: &Fixture, reply: &str) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let placeholders = quality::placeholders(reply);
+    checks.push(Check::new(
+        "no_placeholders",
+        (!placeholders.is_empty()).then(|| placeholders.join(", ")),
+    ));
+
+    let words = quality::word_count(reply);
+    let (min, max) = (
+        fixture.min_words.unwrap_or(DEFAULT_MIN_WORDS),
+        fixture.max_words.unwrap_or(DEFAULT_MAX_WORDS),
+    );
+    checks.push(Check::new(
+        "length",
+        (!(min..=max).contains(&words)).then(|| format!("{} words, expected {}-{}", words, min, max)),
+    ));
+
+    let expected = fixture.language.as_deref().or_else(|| quality::detect_language(&fixture.body));
+    if let (Some(expected), Some(found)) = (expected, quality::detect_language(reply)) {
+        checks.push(Check::new(
+            "language",
+            (expected != found).then(|| format!("reply is in {}, expected {}", found, expected)),
+        ));
+    }
+
+    // what would block approval of the reply as a draft
+    let findings = guardrails::check(
+        reply,
+        &guardrails::Context {
+            email_body: &fixture.body,
+            knowledge: &fixture.knowledge,
+            instructions: fixture.instructions.as_deref(),
+        },
+    );
+    let blocking: Vec<String> = findings
+        .iter()
+        .filter(|w| w.severity == guardrails::HIGH && w.kind != "placeholder")
+        .map(|w| format!("{} ({})", w.kind, w.excerpt.as_deref().unwrap_or("")))
+        .collect();
+    checks.push(Check::new("guardrails", (!blocking.is_empty()).then(|| blocking.join(", "))));
+
+    let lower = reply.to_lowercase();
+    if !fixture.must_include.is_empty() {
+        let missing: Vec<&str> = fixture
+            .must_include
+            .iter()
+            .filter(|t| !lower.contains(&t.to_lowercase()))
+            .map(String::as_str)
+            .collect();
+        checks.push(Check::new("must_include", (!missing.is_empty()).then(|| format!("missing {}", missing.join(", ")))));
+    }
+    if !fixture.must_not_include.is_empty() {
+        let present: Vec<&str> = fixture
+            .must_not_include
+            .iter()
+            .filter(|t| lower.contains(&t.to_lowercase()))
+            .map(String::as_str)
+            .collect();
+        checks.push(Check::new("must_not_include", (!present.is_empty()).then(|| format!("contains {}", present.join(", ")))));
+    }
+
+    checks
+}
+
+/// Asks a model to grade a reply, with the fixture's expectations as the rubric
+pub async fn judge(fixture: &Fixture, reply: &str) -> Result<Judgement, String> {
+    let prompt = format!(
+        r#"Grade this email reply from 1 to 5.
+5: could be sent as is. 4: small edits needed. 3: usable after rewriting parts. 2: mostly wrong or unhelpful. 1: unusable.
+Judge whether it answers what the email asks, stays {tone} in tone, claims nothing the email or notes don't support, and contains no placeholders.
+{notes}
+Original email:
+From: {sender}
+Subject: {subject}
+{body}
+
+Reply:
+{reply}
+
+Answer with a JSON object only: {{"score": <1-5>, "reason": "<one sentence>"}}"#,
+        tone = fixture.tone,
+        notes = if fixture.knowledge.is_empty() {
+            String::new()
+        } else {
+            format!("Notes the reply may use:\n{}\n", fixture.knowledge.join("\n"))
+        },
+        sender = fixture.sender,
+        subject = fixture.subject,
+        body = fixture.body,
+        reply = reply,
+    );
+
+    let mut judgement: Judgement = groq_ai::complete_json(
+        "You review email replies written by an assistant. You are strict and consistent.",
+        &prompt,
+        200,
+        0.0,
+    )
+    .await?;
+    judgement.score = judgement.score.clamp(1, 5);
+    Ok(judgement)
+}
+
+/// Replies to every fixture with a variant and scores the replies, one at a time
+pub async fn run(variant: &Variant, fixtures: &[Fixture], use_judge: bool) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    for fixture in fixtures {
+        let started = Instant::now();
+        let result = variant.provider.reply(&variant.prompt, fixture).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let outcome = match result {
+            Ok(reply) => {
+                let (judgement, judge_error) = if use_judge {
+                    match judge(fixture, &reply.content).await {
+                        Ok(j) => (Some(j), None),
+                        Err(e) => (None, Some(e)),
+                    }
+                } else {
+                    (None, None)
+                };
+                Outcome {
+                    fixture_id: fixture.id.clone(),
+                    checks: check(fixture, &reply.content),
+                    words: quality::word_count(&reply.content),
+                    language: quality::detect_language(&reply.content),
+                    judgement,
+                    judge_error,
+                    usage: reply.usage,
+                    latency_ms,
+                    reply: Some(reply.content),
+                    error: None,
+                }
+            }
+            Err(e) => Outcome {
+                fixture_id: fixture.id.clone(),
+                reply: None,
+                error: Some(e),
+                checks: Vec::new(),
+                words: 0,
+                language: None,
+                judgement: None,
+                judge_error: None,
+                usage: Usage::default(),
+                latency_ms,
+            },
+        };
+        log::info!("{} {}: {}", variant.label, fixture.id, if outcome.passed() { "pass" } else { "fail" });
+        outcomes.push(outcome);
+    }
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMAIL: &str = "Hi, could you tell me when my order will ship? I need it for the trip this weekend and would like to have it with me. Thanks";
+    const REPLY: &str = "Hi Ann,\n\nThanks for your email. Your order will ship tomorrow and should be with you before the weekend.\n\nBest regards";
+
+    fn fixture(extra: serde_json::Value) -> Fixture {
+        let mut value = serde_json::json!({ "id": "order", "sender": "Ann <ann@x.com>", "body": EMAIL });
+        value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn find<'a>(checks: &'a [Check], name: &str) -> Option<&'a Check> {
+        checks.iter().find(|c| c.name == name)
+    }
+
+    #[test]
+    fn a_good_reply_passes_every_check() {
+        let checks = check(&fixture(serde_json::json!({})), REPLY);
+        let names: Vec<&str> = checks.iter().map(|c| c.name).collect();
+        assert_eq!(names, vec!["no_placeholders", "length", "language", "guardrails"]);
+        assert!(checks.iter().all(|c| c.passed));
+    }
+
+    #[test]
+    fn length_uses_the_fixture_bounds_or_the_defaults() {
+        let checks = check(&fixture(serde_json::json!({})), "Thanks, it ships tomorrow.");
+        let length = find(&checks, "length").unwrap();
+        assert!(!length.passed);
+        assert_eq!(length.detail.as_deref(), Some("4 words, expected 10-250"));
+
+        let checks = check(&fixture(serde_json::json!({ "max_words": 10 })), REPLY);
+        assert!(!find(&checks, "length").unwrap().passed);
+
+        let checks = check(&fixture(serde_json::json!({ "min_words": 2, "max_words": 4 })), "Thanks, it ships tomorrow.");
+        assert!(find(&checks, "length").unwrap().passed);
+    }
+
+    #[test]
+    fn language_must_match_the_email() {
+        let spanish = "Hola Ann,\n\nGracias por su mensaje. El pedido sale mañana y lo tendrá antes del fin de semana.\n\nSaludos";
+        let checks = check(&fixture(serde_json::json!({})), spanish);
+        let language = find(&checks, "language").unwrap();
+        assert!(!language.passed);
+        assert_eq!(language.detail.as_deref(), Some("reply is in es, expected en"));
+
+        // the fixture's language wins over the one detected in the email
+        let checks = check(&fixture(serde_json::json!({ "language": "es" })), spanish);
+        assert!(find(&checks, "language").unwrap().passed);
+    }
+
+    #[test]
+    fn language_is_skipped_when_it_cant_be_told() {
+        let checks = check(&fixture(serde_json::json!({ "body": "Order 1234?" })), REPLY);
+        assert!(find(&checks, "language").is_none());
+    }
+
+    #[test]
+    fn must_include_ignores_case_and_lists_what_is_missing() {
+        let checks = check(&fixture(serde_json::json!({ "must_include": ["SHIP TOMORROW", "tracking number", "refund"] })), REPLY);
+        let include = find(&checks, "must_include").unwrap();
+        assert!(!include.passed);
+        assert_eq!(include.detail.as_deref(), Some("missing tracking number, refund"));
+
+        let checks = check(&fixture(serde_json::json!({ "must_include": ["Ship Tomorrow"] })), REPLY);
+        assert!(find(&checks, "must_include").unwrap().passed);
+    }
+
+    #[test]
+    fn must_not_include_flags_what_is_there() {
+        let checks = check(&fixture(serde_json::json!({ "must_not_include": ["weekend", "refund"] })), REPLY);
+        let exclude = find(&checks, "must_not_include").unwrap();
+        assert!(!exclude.passed);
+        assert_eq!(exclude.detail.as_deref(), Some("contains weekend"));
+    }
+
+    #[test]
+    fn placeholders_fail_the_reply() {
+        let checks = check(&fixture(serde_json::json!({})), &REPLY.replace("Ann", "[Name]"));
+        let placeholders = find(&checks, "no_placeholders").unwrap();
+        assert!(!placeholders.passed);
+        assert_eq!(placeholders.detail.as_deref(), Some("[Name]"));
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use crate::eval::{Outcome, Variant};
+
+#[derive(Serialize, Default, Clone, Copy)]
+pub struct Rate {
+    pub passed: usize,
+    pub total: usize,
+}
+
+impl Rate {
+    fn show(&self) -> String {
+        format!("{}/{}", self.passed, self.total)
+    }
+}
+
+/// Totals of one configuration over the fixture set
+#[derive(Serialize)]
+pub struct Summary {
+    pub label: String,
+    pub provider: &'static str,
+    /// `reply v2 (#2)` for registry prompts, `inline` otherwise
+    pub prompt: String,
+    pub model: String,
+    pub temperature: f64,
+    pub max_tokens: i32,
+    pub fixtures: usize,
+    /// Fixtures the provider wrote no reply for
+    pub errors: usize,
+    /// Fixtures whose reply passed every check
+    pub passed: Rate,
+    pub checks: BTreeMap<&'static str, Rate>,
+    /// Mean judge score over the judged replies
+    pub judge_score: Option<f64>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub mean_latency_ms: u64,
+}
+
+#[derive(Serialize)]
+pub struct Run {
+    pub summary: Summary,
+    pub outcomes: Vec<Outcome>,
+}
+
+/// A fixture the two configurations did differently on
+#[derive(Serialize)]
+pub struct Difference {
+    pub fixture_id: String,
+    /// Failed checks (or `error`) of each configuration
+    pub failed: [Vec<&'static str>; 2],
+    pub scores: [Option<u8>; 2],
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub runs: Vec<Run>,
+    /// Only when two configurations were compared
+    pub differences: Vec<Difference>,
+}
+
+pub fn summarize(variant: &Variant, outcomes: Vec<Outcome>) -> Run {
+    let mut checks: BTreeMap<&'static str, Rate> = BTreeMap::new();
+    for check in outcomes.iter().flat_map(|o| &o.checks) {
+        let rate = checks.entry(check.name).or_default();
+        rate.total += 1;
+        if check.passed {
+            rate.passed += 1;
+        }
+    }
+    let scores: Vec<u8> = outcomes.iter().filter_map(|o| o.judgement.as_ref().map(|j| j.score)).collect();
+    let prompt = &variant.prompt;
+
+    let summary = Summary {
+        label: variant.label.clone(),
+        provider: variant.provider.name(),
+        prompt: if prompt.id == 0 {
+            "inline".to_string()
+        } else {
+            format!("{} v{} (#{})", prompt.name, prompt.version, prompt.id)
+        },
+        model: prompt.model.clone(),
+        temperature: prompt.temperature,
+        max_tokens: prompt.max_tokens,
+        fixtures: outcomes.len(),
+        errors: outcomes.iter().filter(|o| o.error.is_some()).count(),
+        passed: Rate {
+            passed: outcomes.iter().filter(|o| o.passed()).count(),
+            total: outcomes.len(),
+        },
+        checks,
+        judge_score: (!scores.is_empty())
+            .then(|| scores.iter().map(|s| *s as f64).sum::<f64>() / scores.len() as f64),
+        prompt_tokens: outcomes.iter().filter_map(|o| o.usage.prompt_tokens).map(i64::from).sum(),
+        completion_tokens: outcomes.iter().filter_map(|o| o.usage.completion_tokens).map(i64::from).sum(),
+        mean_latency_ms: outcomes.iter().map(|o| o.latency_ms).sum::<u64>() / outcomes.len().max(1) as u64,
+    };
+    Run { summary, outcomes }
+}
+
+fn failed_checks(outcome: &Outcome) -> Vec<&'static str> {
+    if outcome.error.is_some() {
+        return vec!["error"];
+    }
+    outcome.checks.iter().filter(|c| !c.passed).map(|c| c.name).collect()
+}
+
+/// Fixtures where the failed checks or the judge score differ. Outcomes are matched by
+/// fixture id since both runs read the same fixture file.
+fn differences(a: &Run, b: &Run) -> Vec<Difference> {
+    a.outcomes
+        .iter()
+        .filter_map(|oa| {
+            let ob = b.outcomes.iter().find(|o| o.fixture_id == oa.fixture_id)?;
+            let failed = [failed_checks(oa), failed_checks(ob)];
+            let scores = [oa.judgement.as_ref().map(|j| j.score), ob.judgement.as_ref().map(|j| j.score)];
+            (failed[0] != failed[1] || scores[0] != scores[1]).then(|| Difference {
+                fixture_id: oa.fixture_id.clone(),
+                failed,
+                scores,
+            })
+        })
+        .collect()
+}
+
+impl Report {
+    pub fn new(runs: Vec<Run>) -> Report {
+        let differences = match runs.as_slice() {
+            [a, b] => differences(a, b),
+            _ => Vec::new(),
+        };
+        Report { runs, differences }
+    }
+
+    /// The report as a Markdown document: a summary table with a column per configuration,
+    /// the fixtures they differ on and every failed check
+    pub fn markdown(&self) -> String {
+        let mut out = String::from("# Draft evaluation\n\n");
+
+        let mut row = |name: &str, cell: &dyn Fn(&Summary) -> String| {
+            let cells: Vec<String> = self.runs.iter().map(|r| cell(&r.summary)).collect();
+            out.push_str(&format!("| {} | {} |\n", name, cells.join(" | ")));
+        };
+        row("", &|s| s.label.clone());
+        row("---", &|_| "---".to_string());
+        row("Provider", &|s| s.provider.to_string());
+        row("Prompt", &|s| s.prompt.clone());
+        row("Model", &|s| s.model.clone());
+        row("Temperature", &|s| format!("{}", s.temperature));
+        row("Max tokens", &|s| s.max_tokens.to_string());
+        row("**Passed all checks**", &|s| format!("**{}**", s.passed.show()));
+        let names: Vec<&'static str> = {
+            let mut names: Vec<&'static str> =
+                self.runs.iter().flat_map(|r| r.summary.checks.keys().copied()).collect();
+            names.sort();
+            names.dedup();
+            names
+        };
+        for name in names {
+            row(name, &|s| s.checks.get(name).map(Rate::show).unwrap_or_else(|| "-".to_string()));
+        }
+        row("Errors", &|s| s.errors.to_string());
+        row("Judge score", &|s| s.judge_score.map(|j| format!("{:.2}", j)).unwrap_or_else(|| "-".to_string()));
+        row("Prompt tokens", &|s| s.prompt_tokens.to_string());
+        row("Completion tokens", &|s| s.completion_tokens.to_string());
+        row("Mean latency (ms)", &|s| s.mean_latency_ms.to_string());
+
+        if let [a, b] = self.runs.as_slice() {
+            out.push_str(&format!("\n## Differences\n\n{} fixture(s) differ.\n", self.differences.len()));
+            if !self.differences.is_empty() {
+                out.push_str(&format!("\n| Fixture | {} | {} |\n| --- | --- | --- |\n", a.summary.label, b.summary.label));
+                for d in &self.differences {
+                    let cell = |i: usize| {
+                        let verdict = if d.failed[i].is_empty() {
+                            "pass".to_string()
+                        } else {
+                            format!("fail: {}", d.failed[i].join(", "))
+                        };
+                        match d.scores[i] {
+                            Some(score) => format!("{} (judge {})", verdict, score),
+                            None => verdict,
+                        }
+                    };
+                    out.push_str(&format!("| {} | {} | {} |\n", d.fixture_id, cell(0), cell(1)));
+                }
+            }
+        }
+
+        for run in &self.runs {
+            let failures: Vec<String> = run
+                .outcomes
+                .iter()
+                .flat_map(|o| {
+                    let error = o.error.iter().map(move |e| format!("- `{}` error: {}", o.fixture_id, e));
+                    let checks = o.checks.iter().filter(|c| !c.passed).map(move |c| {
+                        format!("- `{}` {}: {}", o.fixture_id, c.name, c.detail.as_deref().unwrap_or(""))
+                    });
+                    error.chain(checks)
+                })
+                .collect();
+            if !failures.is_empty() {
+                out.push_str(&format!("\n## Failures: {}\n\n{}\n", run.summary.label, failures.join("\n")));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::db::prompts::Prompt;
+    use crate::eval::{Check, Judgement, Provider};
+    use crate::services::groq_ai::Usage;
+
+    fn variant(label: &str, id: i32) -> Variant {
+        Variant {
+            label: label.to_string(),
+            provider: Provider::Mock,
+            prompt: Prompt {
+                id,
+                workspace_id: None,
+                name: "reply".to_string(),
+                version: 2,
+                description: None,
+                system: String::new(),
+                body: String::new(),
+                model: "test-model".to_string(),
+                temperature: 0.7,
+                max_tokens: 500,
+                created_by: None,
+                created_at: Utc::now().naive_utc(),
+            },
+        }
+    }
+
+    /// An outcome with `length` and `language` checks, failing the checks named in `failed`
+    fn outcome(fixture_id: &str, failed: &[&'static str], score: Option<u8>) -> Outcome {
+        let checks = ["length", "language"]
+            .iter()
+            .chain(failed.iter().filter(|n| !["length", "language"].contains(n)))
+            .map(|name| Check { name, passed: !failed.contains(name), detail: None })
+            .collect();
+        Outcome {
+            fixture_id: fixture_id.to_string(),
+            reply: Some("reply".to_string()),
+            error: None,
+            checks,
+            words: 1,
+            language: None,
+            judgement: score.map(|score| Judgement { score, reason: String::new() }),
+            judge_error: None,
+            usage: Usage { prompt_tokens: Some(100), completion_tokens: Some(20) },
+            latency_ms: 30,
+        }
+    }
+
+    fn failed_outcome(fixture_id: &str) -> Outcome {
+        Outcome {
+            reply: None,
+            error: Some("timed out".to_string()),
+            checks: Vec::new(),
+            judgement: None,
+            usage: Usage::default(),
+            ..outcome(fixture_id, &[], None)
+        }
+    }
+
+    #[test]
+    fn summarize_counts_checks_scores_and_usage() {
+        let run = summarize(
+            &variant("a", 2),
+            vec![
+                outcome("one", &[], Some(5)),
+                outcome("two", &["length"], Some(2)),
+                failed_outcome("three"),
+            ],
+        );
+        let s = &run.summary;
+        assert_eq!(s.prompt, "reply v2 (#2)");
+        assert_eq!(s.provider, "mock");
+        assert_eq!((s.fixtures, s.errors), (3, 1));
+        assert_eq!((s.passed.passed, s.passed.total), (1, 3));
+        assert_eq!(s.checks.get("length").map(|r| (r.passed, r.total)), Some((1, 2)));
+        assert_eq!(s.checks.get("language").map(|r| (r.passed, r.total)), Some((2, 2)));
+        assert_eq!(s.judge_score, Some(3.5));
+        assert_eq!((s.prompt_tokens, s.completion_tokens), (200, 40));
+        assert_eq!(s.mean_latency_ms, 30);
+    }
+
+    #[test]
+    fn summarize_an_inline_prompt_without_judge() {
+        let run = summarize(&variant("b", 0), vec![outcome("one", &[], None)]);
+        assert_eq!(run.summary.prompt, "inline");
+        assert_eq!(run.summary.judge_score, None);
+    }
+
+    #[test]
+    fn differences_list_fixtures_with_other_failures_or_scores() {
+        let a = summarize(
+            &variant("a", 1),
+            vec![
+                outcome("same", &["length"], Some(4)),
+                outcome("checks", &["length"], None),
+                outcome("score", &[], Some(4)),
+                outcome("error", &[], None),
+                outcome("only_a", &["must_include"], None),
+            ],
+        );
+        let b = summarize(
+            &variant("b", 2),
+            vec![
+                outcome("error", &[], None),
+                outcome("score", &[], Some(3)),
+                outcome("checks", &["language", "must_include"], None),
+                outcome("same", &["length"], Some(4)),
+            ],
+        );
+        let b = Run {
+            outcomes: b.outcomes.into_iter().map(|o| if o.fixture_id == "error" { failed_outcome("error") } else { o }).collect(),
+            ..b
+        };
+
+        let report = Report::new(vec![a, b]);
+        let ids: Vec<&str> = report.differences.iter().map(|d| d.fixture_id.as_str()).collect();
+        assert_eq!(ids, vec!["checks", "score", "error"]);
+        let [checks, score, error] = &report.differences[..] else { unreachable!() };
+        assert_eq!(checks.failed, [vec!["length"], vec!["language", "must_include"]]);
+        assert_eq!(score.failed, [Vec::<&str>::new(), Vec::new()]);
+        assert_eq!(score.scores, [Some(4), Some(3)]);
+        assert_eq!(error.failed, [Vec::new(), vec!["error"]]);
+        assert!(report.markdown().contains("3 fixture(s) differ."));
+    }
+
+    #[test]
+    fn one_run_has_no_differences() {
+        let report = Report::new(vec![summarize(&variant("a", 1), vec![outcome("one", &["length"], None)])]);
+        assert!(report.differences.is_empty());
+        assert!(!report.markdown().contains("## Differences"));
+    }
+}
@@ -0,0 +1,8 @@
+pub mod config;
+pub mod db;
+pub mod eval;
+pub mod models;
+pub mod routes;
+pub mod services;
+pub mod middleware;
+pub mod tasks;
@@ -1,13 +1,7 @@
 use actix_web::{App, HttpServer, middleware::Logger};
 use actix_cors::Cors;
 use dotenv::dotenv;
-mod config;
-mod db;
-mod models;
-mod routes;
-mod services;
-mod middleware;
-mod tasks;
+use drafly::{db, routes, tasks};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -16,11 +10,18 @@ async fn main() -> std::io::Result<()> {
 
     db::init().await.expect("DB init failed");
 
+    if std::env::args().nth(1).as_deref() == Some("rotate-token-keys") {
+        return tasks::key_rotation::rotate_token_keys()
+            .await
+            .map_err(std::io::Error::other);
+    }
+
     let frontend_url = std::env::var("FRONTEND_URL")
         .unwrap_or_else(|_| "http://localhost:3000".to_string());
 
     println!("CORS ALLOWED ORIGIN = {}", frontend_url);
     tasks::cron::start_cron().await;
+    tasks::embeddings::refresh_local_embeddings().await;
 
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -30,6 +31,7 @@ async fn main() -> std::io::Result<()> {
             .allowed_headers(vec![
                 actix_web::http::header::CONTENT_TYPE,
                 actix_web::http::header::AUTHORIZATION,
+                actix_web::http::header::HeaderName::from_static("x-mailbox-account"),
             ])
             .supports_credentials()
             .max_age(3600);
@@ -40,6 +42,18 @@ async fn main() -> std::io::Result<()> {
             .configure(routes::auth::init)
             .configure(routes::gmail::init)
             .configure(routes::drafts::init)
+            .configure(routes::accounts::init)
+            .configure(routes::workspaces::init)
+            .configure(routes::comments::init)
+            .configure(routes::audit::init)
+            .configure(routes::rules::init)
+            .configure(routes::summaries::init)
+            .configure(routes::search::init)
+            .configure(routes::embeddings::init)
+            .configure(routes::knowledge::init)
+            .configure(routes::templates::init)
+            .configure(routes::style::init)
+            .configure(routes::prompts::init)
     })
     .bind(("0.0.0.0", 8000))?
     .run()
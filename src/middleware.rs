@@ -1,9 +1,20 @@
-use crate::services::jwt;
+use actix_web::web;
+use serde::Deserialize;
+use crate::db;
+use crate::db::workspaces::Role;
+use crate::services::{jwt, session};
 
-/// Extractor to get the authenticated user's email from the JWT token
+/// Extractor to get the authenticated drafly user from the JWT token
+/// (Bearer header or HttpOnly session cookie)
 #[derive(Clone)]
 pub struct AuthenticatedUser {
+    pub user_id: i32,
+    /// Login email of the user
     pub email: String,
+    pub session_id: String,
+    /// Client address (honours `Forwarded`/`X-Forwarded-For` from the proxy) and user agent, for the audit log
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
 }
 
 impl actix_web::FromRequest for AuthenticatedUser {
@@ -14,40 +25,152 @@ impl actix_web::FromRequest for AuthenticatedUser {
         req: &actix_web::HttpRequest,
         _payload: &mut actix_web::dev::Payload,
     ) -> Self::Future {
-        // Extract token from Authorization header
-        let auth_header = req.headers().get("Authorization");
-        
-        let token = match auth_header {
-            Some(header_value) => {
-                let header_str = header_value.to_str().unwrap_or("");
-                if header_str.starts_with("Bearer ") {
-                    Some(header_str[7..].to_string())
-                } else {
-                    None
-                }
-            }
-            None => None,
-        };
+        // Extract token from Authorization header, falling back to the session cookie
+        let bearer = req
+            .headers()
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .map(|t| t.to_string());
+
+        let token = bearer.or_else(|| {
+            req.cookie(session::SESSION_COOKIE)
+                .map(|c| c.value().to_string())
+        });
 
         let token = match token {
             Some(t) => t,
             None => {
                 return Box::pin(async move {
-                    Err(actix_web::error::ErrorUnauthorized("Missing Authorization header"))
+                    Err(actix_web::error::ErrorUnauthorized("Missing Authorization header or session cookie"))
+                });
+            }
+        };
+
+        // Validate token, then make sure its session hasn't been revoked
+        let claims = jwt::validate_jwt(&token)
+            .ok()
+            .and_then(|c| c.sub.parse::<i32>().ok().map(|user_id| (user_id, c.sid)));
+
+        let ip = req.connection_info().realip_remote_addr().map(|a| a.to_string());
+        let user_agent = req
+            .headers()
+            .get("User-Agent")
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.to_string());
+
+        let (user_id, session_id) = match claims {
+            Some(c) => c,
+            None => {
+                return Box::pin(async move {
+                    Err(actix_web::error::ErrorUnauthorized("Invalid or expired token"))
                 });
             }
         };
 
-        // Validate token
-        match jwt::validate_jwt(&token) {
-            Ok(claims) => {
-                let email = claims.sub;
-                Box::pin(async move { Ok(AuthenticatedUser { email }) })
+        Box::pin(async move {
+            match db::sessions::active_user_email(&session_id, user_id).await {
+                Ok(Some(email)) => Ok(AuthenticatedUser {
+                    user_id,
+                    email,
+                    session_id,
+                    ip,
+                    user_agent,
+                }),
+                Ok(None) => Err(actix_web::error::ErrorUnauthorized("Session revoked")),
+                Err(e) => {
+                    log::error!("session lookup failed: {:?}", e);
+                    Err(actix_web::error::ErrorInternalServerError("db error"))
+                }
             }
-            Err(_) => Box::pin(async move {
-                Err(actix_web::error::ErrorUnauthorized("Invalid or expired token"))
-            }),
+        })
+    }
+}
+
+/// Header selecting which connected mailbox a request operates on
+pub const MAILBOX_HEADER: &str = "X-Mailbox-Account";
+
+#[derive(Deserialize)]
+struct MailboxQuery {
+    account_id: Option<i32>,
+}
+
+/// Extractor for the Gmail mailbox a request operates on. Picked with the
+/// `X-Mailbox-Account` header or `account_id` query parameter, defaulting to
+/// the user's login mailbox. Mailboxes shared through a workspace are
+/// reachable too, with the member's workspace role.
+#[derive(Clone)]
+pub struct Mailbox {
+    pub account_id: i32,
+    /// Gmail address of the mailbox
+    pub email: String,
+    pub user: AuthenticatedUser,
+    pub role: Role,
+}
+
+impl Mailbox {
+    /// Fails with 403 unless the user's role on this mailbox is at least `role`
+    pub fn require(&self, role: Role) -> Result<(), actix_web::Error> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(actix_web::error::ErrorForbidden(format!(
+                "This action requires the {} role",
+                role.as_str()
+            )))
         }
     }
 }
 
+impl actix_web::FromRequest for Mailbox {
+    type Error = actix_web::Error;
+    type Future = std::pin::Pin<Box<dyn std::future::Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        let user = AuthenticatedUser::from_request(req, payload);
+
+        let requested = match req.headers().get(MAILBOX_HEADER) {
+            Some(h) => h.to_str().ok().and_then(|v| v.trim().parse::<i32>().ok()).map(Some),
+            None => Some(
+                web::Query::<MailboxQuery>::from_query(req.query_string())
+                    .ok()
+                    .and_then(|q| q.account_id),
+            ),
+        };
+
+        Box::pin(async move {
+            let user = user.await?;
+
+            let requested = requested.ok_or_else(|| {
+                actix_web::error::ErrorBadRequest("Invalid X-Mailbox-Account header")
+            })?;
+
+            let account = match requested {
+                Some(id) => db::mailbox_accounts::get_accessible(user.user_id, id)
+                    .await
+                    .map(|a| a.map(|a| (a.id, a.email, Role::parse(&a.role).unwrap_or(Role::Viewer)))),
+                None => db::mailbox_accounts::default_for_user(user.user_id)
+                    .await
+                    .map(|a| a.map(|a| (a.id, a.email, Role::Owner))),
+            }
+            .map_err(|e| {
+                log::error!("mailbox lookup failed: {:?}", e);
+                actix_web::error::ErrorInternalServerError("db error")
+            })?;
+
+            match account {
+                Some((account_id, email, role)) => Ok(Mailbox {
+                    account_id,
+                    email,
+                    user,
+                    role,
+                }),
+                None if requested.is_some() => Err(actix_web::error::ErrorNotFound("Mailbox account not found")),
+                None => Err(actix_web::error::ErrorConflict("No mailbox connected")),
+            }
+        })
+    }
+}
@@ -0,0 +1,345 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use serde::Deserialize;
+use crate::db;
+use crate::db::approvals::Policy;
+use crate::db::post_send::Settings;
+use crate::db::workspaces::Role;
+use crate::middleware::AuthenticatedUser;
+use crate::services::{google_oauth, signatures};
+
+/// Longest signature accepted, in characters (Gmail's own limit)
+const MAX_SIGNATURE_CHARS: usize = 10_000;
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_accounts)
+       .service(connect_account)
+       .service(disconnect_account)
+       .service(get_approval_policy)
+       .service(set_approval_policy)
+       .service(get_post_send_settings)
+       .service(set_post_send_settings)
+       .service(get_signature)
+       .service(set_signature)
+       .service(import_signature);
+}
+
+#[get("/accounts")]
+async fn list_accounts(user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let accounts = db::mailbox_accounts::list_accessible(user.user_id)
+        .await
+        .map_err(|e| {
+            log::error!("db error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?;
+
+    let mapped: Vec<_> = accounts.into_iter().map(|a| {
+        serde_json::json!({
+            "id": a.id,
+            "email": a.email,
+            "is_primary": a.email == user.email,
+            "is_owner": a.user_id == user.user_id,
+            "workspace_id": a.workspace_id,
+            "role": a.role,
+            "needs_reauth": a.needs_reauth,
+            "created_at": a.created_at,
+        })
+    }).collect();
+
+    Ok(HttpResponse::Ok().json(mapped))
+}
+
+#[derive(Deserialize)]
+struct ConnectRequest {
+    login_hint: Option<String>,
+}
+
+/// Starts Google consent for another mailbox; the callback links it to the current user
+#[post("/accounts/connect")]
+async fn connect_account(user: AuthenticatedUser, req: Option<web::Json<ConnectRequest>>) -> Result<HttpResponse, actix_web::Error> {
+    let state = db::oauth_states::create(Some(user.user_id)).await.map_err(|e| {
+        log::error!("Failed to store oauth state: {}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    let login_hint = req.and_then(|r| r.into_inner().login_hint);
+    let auth_url = google_oauth::build_auth_url(state.clone(), None, login_hint.as_deref());
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "auth_url": auth_url,
+        "state": state
+    })))
+}
+
+/// Disconnects a mailbox: revokes drafly's Google grant and deletes its emails and drafts
+#[delete("/accounts/{id}")]
+async fn disconnect_account(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let account_id = path.into_inner();
+
+    let account = db::mailbox_accounts::get_for_user(user.user_id, account_id)
+        .await
+        .map_err(|e| {
+            log::error!("db error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?;
+
+    let account = match account {
+        Some(a) => a,
+        None => return Ok(HttpResponse::NotFound().body("Mailbox account not found")),
+    };
+
+    // best effort: the mailbox is disconnected locally even if Google is unreachable
+    if let Err(e) = google_oauth::revoke_grant(&account.email).await {
+        log::warn!("Failed to revoke Google grant for {}: {}", account.email, e);
+    }
+
+    db::mailbox_accounts::delete(user.user_id, account_id)
+        .await
+        .map_err(|e| {
+            log::error!("db error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?;
+
+    db::audit::NewEvent::by(&user, "mailbox.disconnected")
+        .mailbox(account_id)
+        .target("mailbox", account_id)
+        .metadata(serde_json::json!({ "email": account.email }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "disconnected": true
+    })))
+}
+
+/// The caller's role on a mailbox they own or reach through a workspace
+async fn mailbox_role(user: &AuthenticatedUser, account_id: i32) -> Result<Option<Role>, actix_web::Error> {
+    let account = db::mailbox_accounts::get_accessible(user.user_id, account_id)
+        .await
+        .map_err(|e| {
+            log::error!("db error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?;
+
+    Ok(account.and_then(|a| Role::parse(&a.role)))
+}
+
+#[get("/accounts/{id}/approval-policy")]
+async fn get_approval_policy(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let account_id = path.into_inner();
+
+    if mailbox_role(&user, account_id).await?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Mailbox account not found"));
+    }
+
+    let policy = db::approvals::get_policy(account_id).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    Ok(HttpResponse::Ok().json(policy))
+}
+
+/// Replaces the mailbox's approval policy; only owners can change it
+#[put("/accounts/{id}/approval-policy")]
+async fn set_approval_policy(path: web::Path<i32>, req: web::Json<Policy>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let account_id = path.into_inner();
+
+    match mailbox_role(&user, account_id).await? {
+        Some(Role::Owner) => {}
+        Some(_) => return Ok(HttpResponse::Forbidden().body("This action requires the owner role")),
+        None => return Ok(HttpResponse::NotFound().body("Mailbox account not found")),
+    }
+
+    let policy = req.into_inner();
+    if policy.required_approvals < 1 || policy.rules.iter().any(|r| r.required_approvals < 1) {
+        return Ok(HttpResponse::BadRequest().body("required_approvals must be at least 1"));
+    }
+    if policy.rules.iter().any(|r| !matches!(r.match_type.as_str(), "sender" | "domain") || r.pattern.trim().is_empty()) {
+        return Ok(HttpResponse::BadRequest().body("rules need a match_type of sender or domain and a pattern"));
+    }
+
+    let previous = db::approvals::get_policy(account_id).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    db::approvals::set_policy(account_id, &policy).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    let previous = serde_json::to_value(&previous).unwrap_or_default();
+    let current = serde_json::to_value(&policy).unwrap_or_default();
+    db::audit::NewEvent::by(&user, "approval_policy.updated")
+        .mailbox(account_id)
+        .target("mailbox", account_id)
+        .before(db::approvals::content_hash(&previous.to_string()))
+        .after(db::approvals::content_hash(&current.to_string()))
+        .metadata(serde_json::json!({ "before": previous, "after": current }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(policy))
+}
+
+#[get("/accounts/{id}/post-send")]
+async fn get_post_send_settings(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let account_id = path.into_inner();
+
+    if mailbox_role(&user, account_id).await?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Mailbox account not found"));
+    }
+
+    let settings = db::post_send::get(account_id).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+/// Replaces what is done to source emails after a reply is sent; only owners can change it
+#[put("/accounts/{id}/post-send")]
+async fn set_post_send_settings(path: web::Path<i32>, req: web::Json<Settings>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let account_id = path.into_inner();
+
+    match mailbox_role(&user, account_id).await? {
+        Some(Role::Owner) => {}
+        Some(_) => return Ok(HttpResponse::Forbidden().body("This action requires the owner role")),
+        None => return Ok(HttpResponse::NotFound().body("Mailbox account not found")),
+    }
+
+    let mut settings = req.into_inner();
+    settings.label = settings.label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty());
+
+    db::post_send::set(account_id, &settings).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    db::audit::NewEvent::by(&user, "post_send.updated")
+        .mailbox(account_id)
+        .target("mailbox", account_id)
+        .metadata(serde_json::to_value(&settings).unwrap_or_default())
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+#[get("/accounts/{id}/signature")]
+async fn get_signature(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let account_id = path.into_inner();
+
+    if mailbox_role(&user, account_id).await?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Mailbox account not found"));
+    }
+
+    let signature = db::signatures::get(account_id).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    match signature {
+        Some(s) => Ok(HttpResponse::Ok().json(s)),
+        None => Ok(HttpResponse::NotFound().body("No signature")),
+    }
+}
+
+#[derive(Deserialize)]
+struct SignatureRequest {
+    plain: Option<String>,
+    /// Sanitized before it is stored; the plain version is derived from it when not given
+    html: Option<String>,
+    enabled: Option<bool>,
+}
+
+/// Replaces the signature appended to replies sent from the mailbox; only owners can change it
+#[put("/accounts/{id}/signature")]
+async fn set_signature(path: web::Path<i32>, req: web::Json<SignatureRequest>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let account_id = path.into_inner();
+
+    match mailbox_role(&user, account_id).await? {
+        Some(Role::Owner) => {}
+        Some(_) => return Ok(HttpResponse::Forbidden().body("This action requires the owner role")),
+        None => return Ok(HttpResponse::NotFound().body("Mailbox account not found")),
+    }
+
+    let too_long = |v: &Option<String>| v.as_deref().is_some_and(|v| v.chars().count() > MAX_SIGNATURE_CHARS);
+    if too_long(&req.plain) || too_long(&req.html) {
+        return Ok(HttpResponse::BadRequest().body(format!("signature is longer than {} characters", MAX_SIGNATURE_CHARS)));
+    }
+
+    let html = req.html.as_deref().map(signatures::sanitize_html).filter(|h| !h.is_empty());
+    let plain = req
+        .plain
+        .as_deref()
+        .map(|p| p.trim_end().to_string())
+        .filter(|p| !p.trim().is_empty())
+        .or_else(|| html.as_deref().map(signatures::plain_from_html));
+    let enabled = req.enabled.unwrap_or(true);
+
+    db::signatures::set(account_id, plain.as_deref(), html.as_deref(), enabled, "manual")
+        .await
+        .map_err(|e| {
+            log::error!("db error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?;
+
+    db::audit::NewEvent::by(&user, "signature.updated")
+        .mailbox(account_id)
+        .target("mailbox", account_id)
+        .metadata(serde_json::json!({ "source": "manual", "enabled": enabled, "html": html.is_some() }))
+        .record()
+        .await;
+
+    let signature = db::signatures::get(account_id).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+    Ok(HttpResponse::Ok().json(signature))
+}
+
+/// Copies the signature Gmail uses for the mailbox's address from its sendAs settings
+#[post("/accounts/{id}/signature/import")]
+async fn import_signature(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let account_id = path.into_inner();
+
+    let account = db::mailbox_accounts::get_accessible(user.user_id, account_id)
+        .await
+        .map_err(|e| {
+            log::error!("db error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?;
+    let account = match account {
+        Some(a) if Role::parse(&a.role) == Some(Role::Owner) => a,
+        Some(_) => return Ok(HttpResponse::Forbidden().body("This action requires the owner role")),
+        None => return Ok(HttpResponse::NotFound().body("Mailbox account not found")),
+    };
+
+    let html = match signatures::fetch_from_gmail(&account.email).await? {
+        Some(html) => signatures::sanitize_html(&html),
+        None => return Ok(HttpResponse::NotFound().body("Gmail has no signature for this address")),
+    };
+    let plain = signatures::plain_from_html(&html);
+
+    db::signatures::set(account_id, Some(&plain), Some(&html), true, "gmail")
+        .await
+        .map_err(|e| {
+            log::error!("db error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?;
+
+    db::audit::NewEvent::by(&user, "signature.updated")
+        .mailbox(account_id)
+        .target("mailbox", account_id)
+        .metadata(serde_json::json!({ "source": "gmail", "enabled": true, "html": true }))
+        .record()
+        .await;
+
+    let signature = db::signatures::get(account_id).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+    Ok(HttpResponse::Ok().json(signature))
+}
@@ -0,0 +1,123 @@
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use crate::db;
+use crate::db::audit::{AuditEvent, Filter};
+use crate::middleware::AuthenticatedUser;
+
+/// Upper bound of rows in one export
+const EXPORT_LIMIT: i64 = 50_000;
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_audit_events)
+       .service(export_audit_events);
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    limit: Option<i64>,
+}
+
+#[get("/audit")]
+async fn list_audit_events(user: AuthenticatedUser, filter: web::Query<Filter>, page: web::Query<PageQuery>) -> Result<HttpResponse, actix_web::Error> {
+    let limit = page.limit.unwrap_or(100).clamp(1, 1000);
+
+    let events = db::audit::search(user.user_id, &filter, limit).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// `csv` (default) or `jsonl`
+    format: Option<String>,
+}
+
+/// Downloads the events matching the filters as CSV or JSON Lines
+#[get("/audit/export")]
+async fn export_audit_events(user: AuthenticatedUser, filter: web::Query<Filter>, export: web::Query<ExportQuery>) -> Result<HttpResponse, actix_web::Error> {
+    let format = export.format.as_deref().unwrap_or("csv");
+    if format != "csv" && format != "jsonl" {
+        return Ok(HttpResponse::BadRequest().body("format must be csv or jsonl"));
+    }
+
+    let events = db::audit::search(user.user_id, &filter, EXPORT_LIMIT).await.map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    db::audit::NewEvent::by(&user, "audit.exported")
+        .target("audit", format)
+        .metadata(serde_json::json!({ "rows": events.len() }))
+        .record()
+        .await;
+
+    let (body, content_type) = if format == "jsonl" {
+        (to_jsonl(&events), "application/x-ndjson")
+    } else {
+        (to_csv(&events), "text/csv; charset=utf-8")
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"drafly-audit.{}\"", format),
+        ))
+        .body(body))
+}
+
+fn to_jsonl(events: &[AuditEvent]) -> String {
+    events
+        .iter()
+        .filter_map(|e| serde_json::to_string(e).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Quotes a CSV field when needed. Fields starting with a formula character are prefixed
+/// with `'` so spreadsheets don't evaluate them.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn to_csv(events: &[AuditEvent]) -> String {
+    let mut out = String::from(
+        "id,occurred_at,actor_user_id,actor_email,account_id,action,target_type,target_id,before_hash,after_hash,ip,user_agent,metadata\n",
+    );
+
+    for e in events {
+        let fields = [
+            e.id.to_string(),
+            e.occurred_at.to_string(),
+            e.actor_user_id.map(|v| v.to_string()).unwrap_or_default(),
+            e.actor_email.clone().unwrap_or_default(),
+            e.account_id.map(|v| v.to_string()).unwrap_or_default(),
+            e.action.clone(),
+            e.target_type.clone(),
+            e.target_id.clone().unwrap_or_default(),
+            e.before_hash.clone().unwrap_or_default(),
+            e.after_hash.clone().unwrap_or_default(),
+            e.ip.clone().unwrap_or_default(),
+            e.user_agent.clone().unwrap_or_default(),
+            e.metadata.to_string(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+
+    out
+}
@@ -1,25 +1,70 @@
-use actix_web::{get, web, HttpResponse};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, HttpResponseBuilder};
 use serde::Deserialize;
-use uuid::Uuid;
-use crate::db;
-use crate::services::{google_oauth, jwt};
+use crate::{config, db};
+use crate::middleware::{AuthenticatedUser, Mailbox};
+use crate::services::{google_oauth, jwt, session};
 
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(start_google_auth)
        .service(google_callback)
+       .service(google_grant_status)
+       .service(exchange_login_code)
+       .service(refresh_session)
+       .service(logout)
+       .service(logout_all)
        .service(health_check);
 
 }
 
+#[derive(Deserialize)]
+struct StartQuery {
+    /// Comma-separated scopes for incremental consent, e.g. `gmail.modify`
+    scopes: Option<String>,
+    login_hint: Option<String>,
+}
+
 #[get("/auth/google/start")]
-async fn start_google_auth() -> HttpResponse {
-    let state = Uuid::new_v4().to_string();
-    let auth_url = google_oauth::build_auth_url(state.clone());
+async fn start_google_auth(query: web::Query<StartQuery>) -> Result<HttpResponse, actix_web::Error> {
+    let state = db::oauth_states::create(None).await.map_err(|e| {
+        log::error!("Failed to store oauth state: {}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+    let scopes = query.scopes.as_deref().map(google_oauth::parse_scope_list);
+    let auth_url = google_oauth::build_auth_url(state.clone(), scopes, query.login_hint.as_deref());
 
-    HttpResponse::Ok().json(serde_json::json!({
+    Ok(HttpResponse::Ok().json(serde_json::json!({
         "auth_url": auth_url,
         "state": state
-    }))
+    })))
+}
+
+/// Whether the mailbox's Google grant still works and which required scopes are missing
+#[get("/auth/google/status")]
+async fn google_grant_status(mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let status = db::mailbox_accounts::grant_status(&mailbox.email).await.map_err(|e| {
+        log::error!("grant status lookup failed for {}: {}", mailbox.email, e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    let out = match status {
+        Some(g) => {
+            let granted = g.granted_scopes.unwrap_or_default();
+            serde_json::json!({
+                "connected": true,
+                "needs_reauth": g.needs_reauth,
+                "reauth_reason": g.reauth_reason,
+                "granted_scopes": granted.split_whitespace().collect::<Vec<_>>(),
+                "missing_scopes": google_oauth::missing_scopes(&granted),
+            })
+        }
+        None => serde_json::json!({
+            "connected": false,
+            "needs_reauth": true,
+            "missing_scopes": google_oauth::REQUIRED_SCOPES,
+        }),
+    };
+
+    Ok(HttpResponse::Ok().json(out))
 }
 
 #[derive(Deserialize)]
@@ -29,7 +74,23 @@ struct CallbackQuery {
 }
 
 #[get("/auth/google/callback")]
-async fn google_callback(query: web::Query<CallbackQuery>) -> Result<HttpResponse, actix_web::Error> {
+async fn google_callback(http_req: HttpRequest, query: web::Query<CallbackQuery>) -> Result<HttpResponse, actix_web::Error> {
+    // 0. The state must be one we issued (login, or connecting another mailbox)
+    let oauth_state = match db::oauth_states::consume(&query.state).await {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid_state"
+            })));
+        }
+        Err(e) => {
+            log::error!("Failed to consume oauth state: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "database_error"
+            })));
+        }
+    };
+
     // 1. Exchange the auth code for tokens
     let tokens = match google_oauth::exchange_code_for_tokens(query.code.clone()).await {
         Ok(t) => t,
@@ -61,27 +122,74 @@ async fn google_callback(query: web::Query<CallbackQuery>) -> Result<HttpRespons
         }
     };
 
-    // 3. Store refresh_token (if provided)
+    // 3. Work out which drafly user this mailbox belongs to
+    let existing = db::mailbox_accounts::find_by_email(&email).await.map_err(|e| {
+        log::error!("Mailbox lookup failed for {}: {}", email, e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    let user_id = match (oauth_state.link_user_id, &existing) {
+        (Some(link_user_id), Some(account)) if account.user_id != link_user_id => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "mailbox_connected_to_another_user"
+            })));
+        }
+        (Some(link_user_id), _) => link_user_id,
+        (None, Some(account)) => account.user_id,
+        (None, None) => db::users::find_or_create(&email).await.map_err(|e| {
+            log::error!("Failed to create user for {}: {}", email, e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?,
+    };
+
+    // 4. Store refresh_token (if provided)
+    let mut account_id = existing.as_ref().map(|a| a.id);
     if let Some(refresh) = tokens.refresh_token.clone() {
-        if let Err(e) = db::user_tokens::insert_token(&email, &refresh).await {
-            log::error!("Failed to store refresh token for {}: {}", email, e);
+        match db::mailbox_accounts::upsert(user_id, &email, &refresh, tokens.scope.as_deref()).await {
+            Ok(id) => account_id = Some(id),
+            Err(e) => log::error!("Failed to store refresh token for {}: {}", email, e),
         }
     }
 
-    // 4. Backend-generated JWT for frontend sessions
-    let jwt = jwt::generate_jwt(&email);
+    if let Some(id) = account_id {
+        db::audit::NewEvent::by_user_id(user_id, &http_req, "mailbox.connected")
+            .mailbox(id)
+            .target("mailbox", id)
+            .metadata(serde_json::json!({ "email": email, "scopes": tokens.scope }))
+            .record()
+            .await;
+    }
 
-    // 5. Redirect the user safely to frontend
-    let frontend = std::env::var("FRONTEND_URL")
-        .unwrap_or_else(|_| "http://localhost:3000".into());
+    // Connecting another mailbox: the user already has a session
+    if oauth_state.link_user_id.is_some() {
+        let redirect_url = format!(
+            "{}/settings?connected_account={}",
+            config::frontend_url(),
+            account_id.map(|id| id.to_string()).unwrap_or_default()
+        );
+        return Ok(HttpResponse::Found()
+            .append_header(("Location", redirect_url))
+            .finish());
+    }
 
+    // 5. Hand the frontend a one-time login code instead of the session JWT,
+    //    so the token never shows up in browser history or proxy logs
+    let login_code = match db::login_codes::create(user_id).await {
+        Ok(c) => c,
+        Err(e) => {
+            log::error!("Failed to create login code for {}: {}", email, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "login_code_failed"
+            })));
+        }
+    };
+
+    // 6. Redirect the user safely to frontend
     let redirect_url = format!(
-        "{}/login?token={}&email={}",
-        frontend,
-        jwt,
-        urlencoding::encode(&email)
+        "{}/login?login_code={}",
+        config::frontend_url(),
+        login_code
     );
-    println!("\n🔍 REDIRECTING TO: {}\n", redirect_url);
 
     Ok(HttpResponse::Found()
         .append_header(("Location", redirect_url))
@@ -89,6 +197,157 @@ async fn google_callback(query: web::Query<CallbackQuery>) -> Result<HttpRespons
 
 }
 
+#[derive(Deserialize)]
+struct ExchangeRequest {
+    code: String,
+}
+
+/// Exchanges the one-time login code from the OAuth redirect for a session.
+/// Tokens are returned in the body for Bearer clients and also set as HttpOnly cookies.
+#[post("/auth/session")]
+async fn exchange_login_code(http_req: HttpRequest, req: web::Json<ExchangeRequest>) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = match db::login_codes::consume(&req.code).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "invalid_or_expired_code"
+            })));
+        }
+        Err(e) => {
+            log::error!("Failed to consume login code: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "database_error"
+            })));
+        }
+    };
+
+    let user_agent = http_req
+        .headers()
+        .get("User-Agent")
+        .and_then(|h| h.to_str().ok());
+
+    let (session_id, refresh_token) = db::sessions::create(user_id, user_agent)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to create session for user {}: {}", user_id, e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?;
+
+    db::audit::NewEvent::by_user_id(user_id, &http_req, "auth.login")
+        .target("session", &session_id)
+        .record()
+        .await;
+
+    token_response(user_id, &session_id, &refresh_token).await
+}
+
+#[derive(Deserialize)]
+struct RefreshRequest {
+    refresh_token: Option<String>,
+}
+
+/// Rotates the refresh token (from the body or the refresh cookie) and issues a new access token
+#[post("/auth/refresh")]
+async fn refresh_session(http_req: HttpRequest, req: Option<web::Json<RefreshRequest>>) -> Result<HttpResponse, actix_web::Error> {
+    let presented = req
+        .and_then(|r| r.into_inner().refresh_token)
+        .or_else(|| http_req.cookie(session::REFRESH_COOKIE).map(|c| c.value().to_string()));
+
+    let presented = match presented {
+        Some(t) if !t.is_empty() => t,
+        _ => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "missing_refresh_token"
+            })));
+        }
+    };
+
+    let outcome = db::sessions::rotate(&presented).await.map_err(|e| {
+        log::error!("Failed to rotate refresh token: {}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    match outcome {
+        db::sessions::RotateOutcome::Rotated { session_id, user_id, refresh_token } => {
+            token_response(user_id, &session_id, &refresh_token).await
+        }
+        db::sessions::RotateOutcome::Reused | db::sessions::RotateOutcome::Invalid => {
+            Ok(cleared_cookies(HttpResponse::Unauthorized()).json(serde_json::json!({
+                "error": "invalid_refresh_token"
+            })))
+        }
+    }
+}
+
+/// Revokes the current session
+#[post("/auth/logout")]
+async fn logout(user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    db::sessions::revoke(&user.session_id, user.user_id).await.map_err(|e| {
+        log::error!("Failed to revoke session {}: {}", user.session_id, e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    db::audit::NewEvent::by(&user, "auth.logout")
+        .target("session", &user.session_id)
+        .record()
+        .await;
+
+    Ok(cleared_cookies(HttpResponse::Ok()).json(serde_json::json!({
+        "logged_out": true
+    })))
+}
+
+/// Revokes every session of the user ("log out everywhere")
+#[post("/auth/logout-all")]
+async fn logout_all(user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let revoked = db::sessions::revoke_all(user.user_id).await.map_err(|e| {
+        log::error!("Failed to revoke sessions for {}: {}", user.email, e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    db::audit::NewEvent::by(&user, "auth.logout_all")
+        .target("user", user.user_id)
+        .metadata(serde_json::json!({ "revoked_sessions": revoked }))
+        .record()
+        .await;
+
+    Ok(cleared_cookies(HttpResponse::Ok()).json(serde_json::json!({
+        "logged_out": true,
+        "revoked_sessions": revoked
+    })))
+}
+
+async fn token_response(user_id: i32, session_id: &str, refresh_token: &str) -> Result<HttpResponse, actix_web::Error> {
+    let email = db::users::primary_email(user_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load user {}: {}", user_id, e);
+            actix_web::error::ErrorInternalServerError("db error")
+        })?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Unknown user"))?;
+
+    let access_token = jwt::generate_jwt(user_id, session_id);
+    let refresh_max_age = actix_web::cookie::time::Duration::days(db::sessions::REFRESH_TOKEN_DAYS.into());
+
+    Ok(HttpResponse::Ok()
+        .cookie(session::session_cookie(&access_token, jwt::access_token_max_age()))
+        .cookie(session::refresh_cookie(refresh_token, refresh_max_age))
+        .json(serde_json::json!({
+            "access_token": access_token,
+            "refresh_token": refresh_token,
+            "expires_in": jwt::ACCESS_TOKEN_MINUTES * 60,
+            "user_id": user_id,
+            "email": email
+        })))
+}
+
+fn cleared_cookies(mut builder: HttpResponseBuilder) -> HttpResponseBuilder {
+    for cookie in session::cleared_cookies() {
+        builder.cookie(cookie);
+    }
+    builder
+}
+
 
 #[get("/health")] 
 async fn health_check() -> HttpResponse {
@@ -0,0 +1,299 @@
+use actix_web::{delete, get, patch, post, web, HttpResponse};
+use serde::Deserialize;
+use crate::db;
+use crate::db::comments::Anchor;
+use crate::db::workspaces::Role;
+use crate::middleware::{AuthenticatedUser, Mailbox};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_comments)
+       .service(create_comment)
+       .service(update_comment)
+       .service(delete_comment)
+       .service(resolve_comment)
+       .service(unresolve_comment)
+       .service(list_mentions);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+/// Loads the content and revision of a draft in the selected mailbox
+async fn find_draft(mailbox: &Mailbox, draft_id: i32) -> Result<Option<(String, i32)>, actix_web::Error> {
+    let row = sqlx::query!(
+        "SELECT content, revision FROM drafts WHERE id = $1 AND account_id = $2",
+        draft_id,
+        mailbox.account_id
+    )
+    .fetch_optional(db::get_pool())
+    .await
+    .map_err(db_error)?;
+
+    Ok(row.map(|r| (r.content.unwrap_or_default(), r.revision)))
+}
+
+/// Members mentioned as `@jane@example.com`, or as `@jane` when only one member's address starts that way
+fn mentioned_users(body: &str, members: &[(i32, String)]) -> Vec<i32> {
+    let mut ids = Vec::new();
+
+    for token in body.split_whitespace() {
+        let handle = match token.strip_prefix('@') {
+            Some(h) => h.trim_end_matches(|c: char| !c.is_alphanumeric()).to_lowercase(),
+            None => continue,
+        };
+        if handle.is_empty() {
+            continue;
+        }
+
+        let matches: Vec<i32> = members
+            .iter()
+            .filter(|(_, email)| {
+                let email = email.to_lowercase();
+                if handle.contains('@') {
+                    email == handle
+                } else {
+                    email.split('@').next() == Some(handle.as_str())
+                }
+            })
+            .map(|(id, _)| *id)
+            .collect();
+
+        if let [id] = matches[..] {
+            ids.push(id);
+        }
+    }
+
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+async fn mentions_in(mailbox: &Mailbox, body: &str) -> Result<Vec<i32>, actix_web::Error> {
+    let members = db::mailbox_accounts::users_with_access(mailbox.account_id)
+        .await
+        .map_err(db_error)?;
+    Ok(mentioned_users(body, &members))
+}
+
+/// Comments of a draft grouped into threads, oldest first
+#[get("/drafts/{id}/comments")]
+async fn list_comments(path: web::Path<i32>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = path.into_inner();
+    if find_draft(&mailbox, draft_id).await?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Draft not found"));
+    }
+
+    let comments = db::comments::list(draft_id).await.map_err(db_error)?;
+    let (replies, roots): (Vec<_>, Vec<_>) = comments.into_iter().partition(|c| c.parent_id.is_some());
+
+    let threads: Vec<_> = roots.into_iter().map(|root| {
+        let thread_replies: Vec<_> = replies.iter().filter(|r| r.parent_id == Some(root.id)).collect();
+        let mut thread = serde_json::to_value(&root).unwrap_or_default();
+        thread["replies"] = serde_json::json!(thread_replies);
+        thread
+    }).collect();
+
+    Ok(HttpResponse::Ok().json(threads))
+}
+
+#[derive(Deserialize)]
+struct AnchorRequest {
+    /// Character offsets into the current draft content, end exclusive
+    start: i32,
+    end: i32,
+}
+
+#[derive(Deserialize)]
+struct CommentRequest {
+    body: String,
+    parent_id: Option<i32>,
+    anchor: Option<AnchorRequest>,
+}
+
+/// Starts a thread (optionally anchored to a range of the draft) or replies to one
+#[post("/drafts/{id}/comments")]
+async fn create_comment(path: web::Path<i32>, req: web::Json<CommentRequest>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let draft_id = path.into_inner();
+    let req = req.into_inner();
+
+    let body = req.body.trim();
+    if body.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("body is required"));
+    }
+
+    let (content, revision) = match find_draft(&mailbox, draft_id).await? {
+        Some(d) => d,
+        None => return Ok(HttpResponse::NotFound().body("Draft not found")),
+    };
+
+    // replies always hang off the first comment of the thread
+    let parent_id = match req.parent_id {
+        Some(id) => match db::comments::get(draft_id, id).await.map_err(db_error)? {
+            Some(parent) => Some(parent.parent_id.unwrap_or(parent.id)),
+            None => return Ok(HttpResponse::NotFound().body("Parent comment not found")),
+        },
+        None => None,
+    };
+
+    let anchor = match req.anchor {
+        Some(_) if parent_id.is_some() => {
+            return Ok(HttpResponse::BadRequest().body("Replies can't be anchored"));
+        }
+        Some(a) => {
+            let len = content.chars().count() as i32;
+            if a.start < 0 || a.end <= a.start || a.end > len {
+                return Ok(HttpResponse::BadRequest().body("anchor must be a non-empty range inside the draft content"));
+            }
+            Some(Anchor {
+                start: a.start,
+                end: a.end,
+                revision,
+                text: content.chars().skip(a.start as usize).take((a.end - a.start) as usize).collect(),
+            })
+        }
+        None => None,
+    };
+
+    let mentioned = mentions_in(&mailbox, body).await?;
+    let id = db::comments::create(draft_id, parent_id, mailbox.user.user_id, body, anchor, &mentioned)
+        .await
+        .map_err(db_error)?;
+
+    let comment = db::comments::get(draft_id, id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+#[derive(Deserialize)]
+struct CommentUpdate {
+    body: String,
+}
+
+/// Edits a comment; only its author can
+#[patch("/drafts/{id}/comments/{comment_id}")]
+async fn update_comment(path: web::Path<(i32, i32)>, req: web::Json<CommentUpdate>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let (draft_id, comment_id) = path.into_inner();
+
+    if find_draft(&mailbox, draft_id).await?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Draft not found"));
+    }
+    let comment = match db::comments::get(draft_id, comment_id).await.map_err(db_error)? {
+        Some(c) => c,
+        None => return Ok(HttpResponse::NotFound().body("Comment not found")),
+    };
+    if comment.author_id != Some(mailbox.user.user_id) {
+        return Ok(HttpResponse::Forbidden().body("Only the author can edit a comment"));
+    }
+
+    let body = req.body.trim();
+    if body.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("body is required"));
+    }
+
+    let mentioned = mentions_in(&mailbox, body).await?;
+    db::comments::update_body(comment_id, body, &mentioned).await.map_err(db_error)?;
+
+    let comment = db::comments::get(draft_id, comment_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+/// Deletes a comment (and its replies); allowed for the author and mailbox owners
+#[delete("/drafts/{id}/comments/{comment_id}")]
+async fn delete_comment(path: web::Path<(i32, i32)>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let (draft_id, comment_id) = path.into_inner();
+
+    if find_draft(&mailbox, draft_id).await?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Draft not found"));
+    }
+    let comment = match db::comments::get(draft_id, comment_id).await.map_err(db_error)? {
+        Some(c) => c,
+        None => return Ok(HttpResponse::NotFound().body("Comment not found")),
+    };
+    if comment.author_id != Some(mailbox.user.user_id) {
+        mailbox.require(Role::Owner)?;
+    }
+
+    db::comments::delete(comment_id).await.map_err(db_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "deleted": true
+    })))
+}
+
+async fn set_resolved(path: web::Path<(i32, i32)>, mailbox: Mailbox, resolved: bool) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let (draft_id, comment_id) = path.into_inner();
+
+    if find_draft(&mailbox, draft_id).await?.is_none() {
+        return Ok(HttpResponse::NotFound().body("Draft not found"));
+    }
+    let comment = match db::comments::get(draft_id, comment_id).await.map_err(db_error)? {
+        Some(c) => c,
+        None => return Ok(HttpResponse::NotFound().body("Comment not found")),
+    };
+    if comment.parent_id.is_some() {
+        return Ok(HttpResponse::BadRequest().body("Only a thread's first comment can be resolved"));
+    }
+
+    let resolved_by = resolved.then_some(mailbox.user.user_id);
+    db::comments::set_resolved(comment_id, resolved_by).await.map_err(db_error)?;
+
+    let comment = db::comments::get(draft_id, comment_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+#[post("/drafts/{id}/comments/{comment_id}/resolve")]
+async fn resolve_comment(path: web::Path<(i32, i32)>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    set_resolved(path, mailbox, true).await
+}
+
+#[post("/drafts/{id}/comments/{comment_id}/unresolve")]
+async fn unresolve_comment(path: web::Path<(i32, i32)>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    set_resolved(path, mailbox, false).await
+}
+
+/// Recent comments mentioning the current user, across all mailboxes they can access
+#[get("/mentions")]
+async fn list_mentions(user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let mentions = db::comments::mentions_for_user(user.user_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(mentions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members() -> Vec<(i32, String)> {
+        vec![
+            (1, "jane@example.com".to_string()),
+            (2, "Carl@Example.com".to_string()),
+            (3, "jane@other.org".to_string()),
+        ]
+    }
+
+    #[test]
+    fn mentions_by_full_address() {
+        assert_eq!(mentioned_users("@jane@example.com please check", &members()), vec![1]);
+        assert_eq!(mentioned_users("cc @CARL@example.com.", &members()), vec![2]);
+    }
+
+    #[test]
+    fn mentions_by_unambiguous_name() {
+        assert_eq!(mentioned_users("@carl, can you look?", &members()), vec![2]);
+        // two members are called jane
+        assert!(mentioned_users("@jane can you look?", &members()).is_empty());
+    }
+
+    #[test]
+    fn ignores_non_mentions() {
+        assert!(mentioned_users("mail jane@example.com or @ or @nobody", &members()).is_empty());
+    }
+
+    #[test]
+    fn each_member_once() {
+        assert_eq!(mentioned_users("@carl @jane@other.org and @carl again", &members()), vec![2, 3]);
+    }
+}
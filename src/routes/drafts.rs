@@ -1,92 +1,121 @@
-use actix_web::{post, get, patch, web, HttpResponse};
+use actix_web::{post, get, patch, web, HttpResponse, ResponseError};
 use serde::Deserialize;
+use crate::config;
 use crate::db;
-use crate::middleware::AuthenticatedUser;
+use crate::db::workspaces::Role;
+use crate::middleware::Mailbox;
 //use crate::services::ai_service;
-use crate::services::groq_ai;
+use crate::services::{draft_generator, embeddings, gmail_triage, guardrails, signatures};
 
 #[derive(Deserialize)]
 pub struct DraftRequest {
     email_id: i32,
     tone: Option<String>,
+    /// Add sent replies to similar emails to the prompt (default true)
+    use_examples: Option<bool>,
+    /// Add excerpts from the workspace knowledge base to the prompt (default true)
+    use_knowledge: Option<bool>,
+    /// Write in the user's learned writing style, if they have a profile (default true)
+    use_style: Option<bool>,
 }
 
 #[post("/drafts/generate")]
-async fn generate_draft(req: web::Json<DraftRequest>, user: AuthenticatedUser) -> HttpResponse {
-    let pool = db::get_pool();
-    let user_email = user.email.clone();
-
-    // fetch email content with sender and subject
-    let email_row = sqlx::query!(
-        "SELECT body_text, sender, subject, user_email FROM emails WHERE id = $1 AND user_email = $2",
-        req.email_id,
-        user_email
-    )
-    .fetch_optional(pool)
-    .await
-    .unwrap();
-
-    if email_row.is_none() {
-        return HttpResponse::NotFound().body("Email not found");
+async fn generate_draft(req: web::Json<DraftRequest>, mailbox: Mailbox) -> HttpResponse {
+    if let Err(e) = mailbox.require(Role::Editor) {
+        return e.error_response();
     }
 
-    let email_data = email_row.unwrap();
-    let email_body = email_data.body_text.clone().unwrap_or_default();
-    let sender = email_data.sender.clone().unwrap_or_default();
-    let subject = email_data.subject.clone().unwrap_or_default();
-
     let tone = req.tone.clone().unwrap_or("friendly".into());
 
-    // generate draft using AI
-    let generated = match groq_ai::generate_reply(&email_body, &sender, &subject, &tone).await {
-        Ok(text) => text,
+    // generate draft using AI and save it
+    let options = draft_generator::DraftOptions {
+        tone: &tone,
+        instructions: None,
+        created_by: Some(mailbox.user.user_id),
+        rule_id: None,
+        use_examples: req.use_examples.unwrap_or(true),
+        use_knowledge: req.use_knowledge.unwrap_or(true),
+        use_style: req.use_style.unwrap_or(true),
+    };
+    let draft = match draft_generator::generate_for_email(mailbox.account_id, req.email_id, &options).await {
+        Ok(Some(d)) => d,
+        Ok(None) => return HttpResponse::NotFound().body("Email not found"),
         Err(e) => return HttpResponse::InternalServerError().body(e),
     };
 
-    // save draft
-    let row = sqlx::query!(
-        r#"
-        INSERT INTO drafts (email_id, user_email, content, tone)
-        VALUES ($1, $2, $3, $4)
-        RETURNING id
-        "#,
-        req.email_id,
-        user_email,
-        generated,
-        tone
-    )
-    .fetch_one(pool)
-    .await
-    .unwrap();
+    db::audit::NewEvent::by(&mailbox.user, "draft.generated")
+        .mailbox(mailbox.account_id)
+        .target("draft", draft.id)
+        .after(db::approvals::content_hash(&draft.content))
+        .metadata(serde_json::json!({
+            "email_id": req.email_id,
+            "tone": tone,
+            "example_draft_ids": draft.example_draft_ids,
+            "source_chunk_ids": draft.sources.iter().map(|s| s.chunk_id).collect::<Vec<_>>(),
+            "style_applied": draft.style_applied,
+            "generation": draft.generation,
+            "attempts": draft.attempts,
+            "warnings": draft.warnings.iter().map(|w| &w.kind).collect::<Vec<_>>()
+        }))
+        .record()
+        .await;
 
     HttpResponse::Ok().json(serde_json::json!({
-        "draft_id": row.id,
-        "content": generated
+        "draft_id": draft.id,
+        "content": draft.content,
+        "example_draft_ids": draft.example_draft_ids,
+        "sources": draft.sources,
+        "style_applied": draft.style_applied,
+        "generation": draft.generation,
+        "warnings": draft.warnings,
+        "attempts": draft.attempts
     }))
 }
 
 #[get("/drafts/{id}")]
-async fn get_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpResponse {
+async fn get_draft(path: web::Path<i32>, mailbox: Mailbox) -> HttpResponse {
     let id = path.into_inner();
     let pool = db::get_pool();
 
     let row = sqlx::query!(
-        "SELECT * FROM drafts WHERE id = $1 AND user_email = $2",
+        r#"
+        SELECT d.id, d.email_id, d.content, d.tone, d.status, d.revision, d.created_at,
+               d.prompt_id, p.name AS "prompt_name?", p.version AS "prompt_version?", d.model, d.temperature,
+               d.prompt_tokens, d.completion_tokens, d.warnings
+        FROM drafts d
+        LEFT JOIN prompt_templates p ON p.id = d.prompt_id
+        WHERE d.id = $1 AND d.account_id = $2
+        "#,
         id,
-        user.email
+        mailbox.account_id
     )
     .fetch_optional(pool)
     .await
     .unwrap();
 
     if let Some(r) = row {
+        let sources = db::knowledge::sources(r.id).await.unwrap();
+
         return HttpResponse::Ok().json(serde_json::json!({
             "id": r.id,
             "email_id": r.email_id,
             "content": r.content,
             "tone": r.tone,
             "status": r.status,
-            "created_at": r.created_at
+            "revision": r.revision,
+            "created_at": r.created_at,
+            "sources": sources,
+            "warnings": r.warnings,
+            // unset for drafts that weren't generated from a registry prompt
+            "generation": r.model.as_ref().map(|model| serde_json::json!({
+                "prompt_id": r.prompt_id,
+                "prompt_name": r.prompt_name,
+                "prompt_version": r.prompt_version,
+                "model": model,
+                "temperature": r.temperature,
+                "prompt_tokens": r.prompt_tokens,
+                "completion_tokens": r.completion_tokens
+            }))
         }));
     }
 
@@ -99,55 +128,223 @@ pub struct DraftUpdate {
 }
 
 #[patch("/drafts/{id}")]
-async fn update_draft(path: web::Path<i32>, req: web::Json<DraftUpdate>, user: AuthenticatedUser) -> HttpResponse {
+async fn update_draft(path: web::Path<i32>, req: web::Json<DraftUpdate>, mailbox: Mailbox) -> HttpResponse {
+    if let Err(e) = mailbox.require(Role::Editor) {
+        return e.error_response();
+    }
+
+    let id = path.into_inner();
+
+    if !draft_in_mailbox(id, mailbox.account_id).await {
+        return HttpResponse::NotFound().body("Draft not found");
+    }
+    if let Some(email) = claimed_by_other(id, mailbox.account_id, mailbox.user.user_id).await {
+        return claim_conflict(&email);
+    }
+
+    let row = set_content(id, &mailbox, &req.content).await;
+
+    match row {
+        Some((revision, previous_content, warnings)) => {
+            db::audit::NewEvent::by(&mailbox.user, "draft.edited")
+                .mailbox(mailbox.account_id)
+                .target("draft", id)
+                .before(db::approvals::content_hash(previous_content.as_deref().unwrap_or("")))
+                .after(db::approvals::content_hash(&req.content))
+                .metadata(serde_json::json!({ "revision": revision }))
+                .record()
+                .await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "updated": true,
+                "revision": revision,
+                "warnings": warnings
+            }))
+        }
+        None => HttpResponse::NotFound().body("Draft not found"),
+    }
+}
+
+/// Replaces a draft's content and returns the new revision, the previous content and the
+/// guardrail findings on the new content. Only a real content change starts a new revision
+/// and invalidates earlier approvals.
+pub(crate) async fn set_content(id: i32, mailbox: &Mailbox, content: &str) -> Option<(i32, Option<String>, Vec<guardrails::Warning>)> {
+    let (revision, previous_content) = sqlx::query!(
+        r#"
+        WITH previous AS (
+            SELECT id, content FROM drafts WHERE id = $2 AND account_id = $3 FOR UPDATE
+        )
+        UPDATE drafts d
+        SET revision = d.revision + (d.content IS DISTINCT FROM $1)::int,
+            status = CASE WHEN d.content IS DISTINCT FROM $1 AND d.status IN ('approved', 'pending_approval')
+                          THEN 'draft' ELSE d.status END,
+            content = $1,
+            updated_by = CASE WHEN d.content IS DISTINCT FROM $1 THEN $4 ELSE d.updated_by END,
+            updated_at = NOW()
+        FROM previous p
+        WHERE d.id = p.id
+        RETURNING d.revision, p.content AS previous_content
+        "#,
+        content,
+        id,
+        mailbox.account_id,
+        mailbox.user.user_id
+    )
+    .fetch_optional(db::get_pool())
+    .await
+    .unwrap()
+    .map(|r| (r.revision, r.previous_content))?;
+
+    let warnings = guardrails::recheck(id).await.unwrap_or_else(|e| {
+        log::error!("guardrail check of draft {} failed: {}", id, e);
+        Vec::new()
+    });
+    Some((revision, previous_content, warnings))
+}
+
+#[derive(Deserialize)]
+pub struct ApproveRequest {
+    /// Approve despite high-severity guardrail findings
+    #[serde(default)]
+    override_guardrails: bool,
+}
+
+#[post("/drafts/{id}/approve")]
+async fn approve_draft(path: web::Path<i32>, req: Option<web::Json<ApproveRequest>>, mailbox: Mailbox) -> HttpResponse {
+    if let Err(e) = mailbox.require(Role::Approver) {
+        return e.error_response();
+    }
+
     let id = path.into_inner();
     let pool = db::get_pool();
+    let approver = mailbox.user.user_id;
 
-    sqlx::query!(
-        "UPDATE drafts SET content = $1, updated_at = NOW() WHERE id = $2 AND user_email = $3",
-        req.content,
+    let draft = sqlx::query!(
+        r#"
+        SELECT d.id, d.content, d.revision, d.status, d.created_by, d.updated_by, d.warnings, e.sender
+        FROM drafts d
+        JOIN emails e ON e.id = d.email_id
+        WHERE d.id = $1 AND d.account_id = $2
+        "#,
         id,
-        user.email
+        mailbox.account_id
+    )
+    .fetch_optional(pool)
+    .await
+    .unwrap();
+
+    let d = match draft {
+        Some(d) => d,
+        None => return HttpResponse::NotFound().body("Draft not found"),
+    };
+
+    if let Some(email) = claimed_by_other(id, mailbox.account_id, approver).await {
+        return claim_conflict(&email);
+    }
+
+    if d.status.as_deref() == Some("sent") {
+        return HttpResponse::BadRequest().body("Draft has already been sent");
+    }
+
+    let policy = db::approvals::get_policy(mailbox.account_id).await.unwrap();
+
+    // four-eyes: whoever generated or last edited the draft can't approve it unless the policy allows it
+    let is_author = d.created_by == Some(approver) || d.updated_by == Some(approver);
+    if is_author && !policy.allow_self_approval {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "self_approval_not_allowed",
+            "message": "This mailbox requires drafts to be approved by someone other than their author"
+        }));
+    }
+
+    // the findings are refreshed on every content change, so they are about this revision
+    let warnings: Vec<guardrails::Warning> = serde_json::from_value(d.warnings).unwrap_or_default();
+    let blocking: Vec<&guardrails::Warning> = warnings.iter().filter(|w| w.severity == guardrails::HIGH).collect();
+    let overridden = !blocking.is_empty();
+    if overridden && !req.is_some_and(|r| r.override_guardrails) {
+        return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "guardrail_findings",
+            "message": "The draft has high-severity guardrail findings; fix them or approve with override_guardrails",
+            "warnings": blocking
+        }));
+    }
+
+    let content_hash = db::approvals::content_hash(d.content.as_deref().unwrap_or(""));
+    db::approvals::record(d.id, d.revision, &content_hash, approver).await.unwrap();
+
+    let approvals = db::approvals::count_for_revision(d.id, d.revision, &content_hash).await.unwrap();
+    let required = policy.required_for_sender(d.sender.as_deref().unwrap_or(""));
+    let status = if approvals >= required as i64 { "approved" } else { "pending_approval" };
+
+    sqlx::query!(
+        "UPDATE drafts SET status = $1, updated_at = NOW() WHERE id = $2",
+        status,
+        d.id
     )
     .execute(pool)
     .await
     .unwrap();
 
+    db::audit::NewEvent::by(&mailbox.user, "draft.approved")
+        .mailbox(mailbox.account_id)
+        .target("draft", d.id)
+        .after(content_hash)
+        .metadata(serde_json::json!({
+            "revision": d.revision,
+            "approvals": approvals,
+            "required_approvals": required,
+            "status": status,
+            "guardrails_overridden": overridden.then(|| blocking.iter().map(|w| &w.kind).collect::<Vec<_>>())
+        }))
+        .record()
+        .await;
+
     HttpResponse::Ok().json(serde_json::json!({
-        "updated": true
+        "approved": status == "approved",
+        "status": status,
+        "revision": d.revision,
+        "approvals": approvals,
+        "required_approvals": required
     }))
 }
 
-#[post("/drafts/{id}/approve")]
-async fn approve_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpResponse {
+#[get("/drafts/{id}/approvals")]
+async fn list_approvals(path: web::Path<i32>, mailbox: Mailbox) -> HttpResponse {
     let id = path.into_inner();
     let pool = db::get_pool();
 
-    sqlx::query!(
-        "UPDATE drafts SET status = 'approved', updated_at = NOW() WHERE id = $1 AND user_email = $2",
+    let draft = sqlx::query!(
+        "SELECT id FROM drafts WHERE id = $1 AND account_id = $2",
         id,
-        user.email
+        mailbox.account_id
     )
-    .execute(pool)
+    .fetch_optional(pool)
     .await
     .unwrap();
 
-    HttpResponse::Ok().json(serde_json::json!({
-        "approved": true
-    }))
+    if draft.is_none() {
+        return HttpResponse::NotFound().body("Draft not found");
+    }
+
+    let approvals = db::approvals::list(id).await.unwrap();
+    HttpResponse::Ok().json(approvals)
 }
 
 #[post("/drafts/{id}/send")]
-async fn send_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpResponse {
+async fn send_draft(path: web::Path<i32>, mailbox: Mailbox) -> HttpResponse {
+    if let Err(e) = mailbox.require(Role::Editor) {
+        return e.error_response();
+    }
+
     let draft_id = path.into_inner();
     let pool = db::get_pool();
 
     // fetch draft
     let draft = sqlx::query!(
-        "SELECT id, email_id, user_email, content, status 
-         FROM drafts WHERE id = $1 AND user_email = $2",
+        "SELECT id, email_id, user_email, content, status, revision
+         FROM drafts WHERE id = $1 AND account_id = $2",
         draft_id,
-        user.email
+        mailbox.account_id
     )
     .fetch_optional(pool)
     .await
@@ -165,7 +362,7 @@ async fn send_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpRespon
 
     // fetch parent email info
     let email = sqlx::query!(
-        "SELECT sender, subject, thread_id FROM emails WHERE id = $1",
+        "SELECT gmail_id, sender, subject, thread_id FROM emails WHERE id = $1",
         d.email_id
     )
     .fetch_one(pool)
@@ -173,16 +370,40 @@ async fn send_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpRespon
     .unwrap();
 
     let sender_email = email.sender.unwrap_or_default();
+
+    // the approvals must cover exactly the content about to be sent
+    let policy = db::approvals::get_policy(mailbox.account_id).await.unwrap();
+    let required = policy.required_for_sender(&sender_email);
+    let content_hash = db::approvals::content_hash(d.content.as_deref().unwrap_or(""));
+    let approvals = db::approvals::count_for_revision(d.id, d.revision, &content_hash).await.unwrap();
+
+    if approvals < required as i64 {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": "approval_mismatch",
+            "message": "The current draft revision is not approved; approve it again before sending",
+            "revision": d.revision,
+            "approvals": approvals,
+            "required_approvals": required
+        }));
+    }
     let subject = email.subject.unwrap_or("No subject".to_string());
     let thread_id = email.thread_id.unwrap_or_default();
 
+    // the signature is added here, so approvals and stored content never include it
+    let signature = db::signatures::active(mailbox.account_id).await.unwrap();
+    let body = match &signature {
+        Some(sig) => signatures::append(d.content.as_deref().unwrap_or(""), sig),
+        None => signatures::Body { plain: d.content.clone().unwrap_or_default(), html: None },
+    };
+
     // send email via Gmail API
    let result = crate::services::gmail_sender::send_reply(
-    d.user_email.as_deref().unwrap_or(""),
+    &mailbox.email,
     &sender_email,
     &subject,
     &thread_id,
-    d.content.as_deref().unwrap_or(""),
+    &body.plain,
+    body.html.as_deref(),
 )
 .await;
 
@@ -190,7 +411,7 @@ async fn send_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpRespon
         Ok(sent_gmail_id) => {
             // update draft status to "sent"
             sqlx::query!(
-                "UPDATE drafts SET status = 'sent', sent = TRUE, sent_gmail_id = $1, updated_at = NOW() WHERE id = $2",
+                "UPDATE drafts SET status = 'sent', sent = TRUE, sent_gmail_id = $1, claimed_by = NULL, claimed_at = NULL, updated_at = NOW() WHERE id = $2",
                 sent_gmail_id,
                 d.id
             )
@@ -198,26 +419,104 @@ async fn send_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpRespon
             .await
             .unwrap();
 
+            db::audit::NewEvent::by(&mailbox.user, "draft.sent")
+                .mailbox(mailbox.account_id)
+                .target("draft", d.id)
+                .after(content_hash)
+                .metadata(serde_json::json!({
+                    "revision": d.revision,
+                    "sent_gmail_id": sent_gmail_id,
+                    "to": sender_email,
+                    "signature": signature.is_some()
+                }))
+                .record()
+                .await;
+
+            sqlx::query!(
+                "UPDATE emails SET replied_at = NOW(), replied_draft_id = $1 WHERE id = $2",
+                d.id,
+                d.email_id
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+
+            let post_send = post_send_actions(&mailbox, d.email_id, &email.gmail_id).await;
+            index_sent_reply(mailbox.account_id, d.id, d.email_id, d.content.as_deref().unwrap_or("")).await;
+
             HttpResponse::Ok().json(serde_json::json!({
                 "sent": true,
-                "sent_gmail_id": sent_gmail_id
+                "sent_gmail_id": sent_gmail_id,
+                "post_send": post_send
             }))
         }
-        Err(e) => HttpResponse::InternalServerError().body(e),
+        Err(e) => e.error_response(),
     }
 }
 
+/// Embeds the sent reply and the email it answers so later drafts can use them as examples
+async fn index_sent_reply(account_id: i32, draft_id: i32, email_id: Option<i32>, content: &str) {
+    if let Err(e) = embeddings::index_draft(account_id, draft_id, content).await {
+        log::error!("embedding failed for draft {}: {}", draft_id, e);
+    }
+    let Some(email_id) = email_id else { return };
+    if let Err(e) = embeddings::index_email(account_id, email_id).await {
+        log::error!("embedding failed for email {}: {}", email_id, e);
+    }
+}
+
+/// Applies the mailbox's post-send settings to the email that was replied to. The reply
+/// is already out, so failures are reported in the response instead of failing the send.
+async fn post_send_actions(mailbox: &Mailbox, email_id: Option<i32>, gmail_id: &str) -> serde_json::Value {
+    let settings = match db::post_send::get(mailbox.account_id).await {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("db error: {:?}", e);
+            return serde_json::json!({ "applied": false, "error": "db error" });
+        }
+    };
+
+    let actions = settings.actions();
+    if actions.is_empty() {
+        return serde_json::json!({ "applied": false });
+    }
+
+    let gmail_ids = [gmail_id.to_string()];
+    match gmail_triage::apply(mailbox.account_id, &mailbox.email, &gmail_ids, &actions).await {
+        Ok(()) => {
+            if let Some(email_id) = email_id {
+                db::audit::NewEvent::by(&mailbox.user, "email.triaged")
+                    .mailbox(mailbox.account_id)
+                    .target("email", email_id)
+                    .metadata(serde_json::json!({ "actions": actions, "post_send": true }))
+                    .record()
+                    .await;
+            }
+            serde_json::json!({ "applied": true, "actions": actions })
+        }
+        Err(e) => {
+            log::error!("post-send actions failed for {}: {}", gmail_id, e);
+            serde_json::json!({ "applied": false, "error": e.to_string() })
+        }
+    }
+}
 
 #[get("/drafts")]
-async fn list_drafts(user: AuthenticatedUser) -> HttpResponse {
+async fn list_drafts(mailbox: Mailbox) -> HttpResponse {
     let pool = db::get_pool();
 
     let rows = sqlx::query!(
-        "SELECT id, email_id, user_email, content, tone, status, created_at, updated_at 
-         FROM drafts 
-         WHERE user_email = $1
-         ORDER BY created_at DESC",
-        user.email
+        r#"
+        SELECT d.id, d.email_id, d.user_email, d.content, d.tone, d.status, d.revision, d.created_at, d.updated_at,
+               COUNT(c.id) AS "comment_count!",
+               COUNT(c.id) FILTER (WHERE c.parent_id IS NULL AND c.resolved_at IS NULL) AS "open_threads!"
+        FROM drafts d
+        LEFT JOIN draft_comments c ON c.draft_id = d.id
+        WHERE d.account_id = $1
+        GROUP BY d.id
+        ORDER BY d.created_at DESC
+        "#,
+        mailbox.account_id
     )
     .fetch_all(pool)
     .await
@@ -230,6 +529,9 @@ async fn list_drafts(user: AuthenticatedUser) -> HttpResponse {
             "content": r.content,
             "tone": r.tone,
             "status": r.status,
+            "revision": r.revision,
+            "comment_count": r.comment_count,
+            "open_threads": r.open_threads,
             "created_at": r.created_at
         })
     }).collect();
@@ -238,7 +540,7 @@ async fn list_drafts(user: AuthenticatedUser) -> HttpResponse {
 }
 
 #[get("/drafts/{id}")]
-async fn single_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpResponse {
+async fn single_draft(path: web::Path<i32>, mailbox: Mailbox) -> HttpResponse {
     let draft_id = path.into_inner();
     let pool = db::get_pool();
 
@@ -247,9 +549,9 @@ async fn single_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpResp
         r#"
         SELECT id, email_id, user_email, content, tone, status, created_at, updated_at 
         FROM drafts 
-        WHERE user_email = $1 AND id = $2
+        WHERE account_id = $1 AND id = $2
         "#,
-        user.email,
+        mailbox.account_id,
         draft_id
     )
     .fetch_optional(pool)
@@ -280,11 +582,271 @@ async fn single_draft(path: web::Path<i32>, user: AuthenticatedUser) -> HttpResp
 }
 
 
+/// Minutes after which a claim lapses and another reviewer can take the draft
+const CLAIM_MINUTES: i32 = 30;
+
+async fn draft_in_mailbox(draft_id: i32, account_id: i32) -> bool {
+    sqlx::query!(
+        "SELECT id FROM drafts WHERE id = $1 AND account_id = $2",
+        draft_id,
+        account_id
+    )
+    .fetch_optional(db::get_pool())
+    .await
+    .unwrap()
+    .is_some()
+}
+
+/// Email of another member of the mailbox holding an active claim on the draft, if any
+pub(crate) async fn claimed_by_other(draft_id: i32, account_id: i32, user_id: i32) -> Option<String> {
+    sqlx::query!(
+        r#"
+        SELECT u.primary_email
+        FROM drafts d
+        JOIN users u ON u.id = d.claimed_by
+        WHERE d.id = $1 AND d.account_id = $3 AND d.claimed_by <> $2
+          AND d.claimed_at > NOW() - make_interval(mins => $4)
+        "#,
+        draft_id,
+        user_id,
+        account_id,
+        CLAIM_MINUTES
+    )
+    .fetch_optional(db::get_pool())
+    .await
+    .unwrap()
+    .map(|r| r.primary_email)
+}
+
+pub(crate) fn claim_conflict(claimed_by: &str) -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({
+        "error": "draft_claimed",
+        "message": format!("{} is reviewing this draft", claimed_by),
+        "claimed_by": claimed_by
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct AssignRequest {
+    /// `null` unassigns the draft
+    user_id: Option<i32>,
+}
+
+#[post("/drafts/{id}/assign")]
+async fn assign_draft(path: web::Path<i32>, req: web::Json<AssignRequest>, mailbox: Mailbox) -> HttpResponse {
+    if let Err(e) = mailbox.require(Role::Editor) {
+        return e.error_response();
+    }
+
+    let id = path.into_inner();
+    let pool = db::get_pool();
+
+    if let Some(assignee) = req.user_id {
+        let members = db::mailbox_accounts::users_with_access(mailbox.account_id).await.unwrap();
+        if !members.iter().any(|(user_id, _)| *user_id == assignee) {
+            return HttpResponse::BadRequest().body("Assignee has no access to this mailbox");
+        }
+    }
+
+    let row = sqlx::query!(
+        r#"
+        UPDATE drafts
+        SET assigned_to = $1,
+            assigned_at = CASE WHEN $1::int IS NULL THEN NULL ELSE NOW() END
+        WHERE id = $2 AND account_id = $3
+        RETURNING assigned_to, assigned_at
+        "#,
+        req.user_id,
+        id,
+        mailbox.account_id
+    )
+    .fetch_optional(pool)
+    .await
+    .unwrap();
+
+    match row {
+        Some(r) => {
+            db::audit::NewEvent::by(&mailbox.user, "draft.assigned")
+                .mailbox(mailbox.account_id)
+                .target("draft", id)
+                .metadata(serde_json::json!({ "assigned_to": r.assigned_to }))
+                .record()
+                .await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "assigned_to": r.assigned_to,
+                "assigned_at": r.assigned_at
+            }))
+        }
+        None => HttpResponse::NotFound().body("Draft not found"),
+    }
+}
+
+/// Marks the draft as being reviewed by the current user so nobody else edits or approves it meanwhile
+#[post("/drafts/{id}/claim")]
+async fn claim_draft(path: web::Path<i32>, mailbox: Mailbox) -> HttpResponse {
+    if let Err(e) = mailbox.require(Role::Editor) {
+        return e.error_response();
+    }
+
+    let id = path.into_inner();
+    let pool = db::get_pool();
+    let user_id = mailbox.user.user_id;
+
+    if !draft_in_mailbox(id, mailbox.account_id).await {
+        return HttpResponse::NotFound().body("Draft not found");
+    }
+
+    let row = sqlx::query!(
+        r#"
+        UPDATE drafts
+        SET claimed_by = $3, claimed_at = NOW()
+        WHERE id = $1 AND account_id = $2
+          AND status IS DISTINCT FROM 'sent'
+          AND (claimed_by IS NULL OR claimed_by = $3 OR claimed_at <= NOW() - make_interval(mins => $4))
+        RETURNING claimed_at
+        "#,
+        id,
+        mailbox.account_id,
+        user_id,
+        CLAIM_MINUTES
+    )
+    .fetch_optional(pool)
+    .await
+    .unwrap();
+
+    if let Some(r) = row {
+        return HttpResponse::Ok().json(serde_json::json!({
+            "claimed": true,
+            "claimed_at": r.claimed_at,
+            "expires_in_minutes": CLAIM_MINUTES
+        }));
+    }
+
+    match claimed_by_other(id, mailbox.account_id, user_id).await {
+        Some(email) => claim_conflict(&email),
+        None => HttpResponse::NotFound().body("Draft not found or already sent"),
+    }
+}
+
+/// Releases a claim; the claimer can always, owners can release anyone's
+#[post("/drafts/{id}/unclaim")]
+async fn unclaim_draft(path: web::Path<i32>, mailbox: Mailbox) -> HttpResponse {
+    let id = path.into_inner();
+    let pool = db::get_pool();
+    let user_id = mailbox.user.user_id;
+
+    if !draft_in_mailbox(id, mailbox.account_id).await {
+        return HttpResponse::NotFound().body("Draft not found");
+    }
+    let held_by_other = claimed_by_other(id, mailbox.account_id, user_id).await.is_some();
+    if let Err(e) = mailbox.require(if held_by_other { Role::Owner } else { Role::Viewer }) {
+        return e.error_response();
+    }
+
+    let result = sqlx::query!(
+        "UPDATE drafts SET claimed_by = NULL, claimed_at = NULL WHERE id = $1 AND account_id = $2",
+        id,
+        mailbox.account_id
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+
+    if result.rows_affected() == 0 {
+        return HttpResponse::NotFound().body("Draft not found");
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "claimed": false
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ReviewQueueQuery {
+    /// `me`, `unassigned` or a user id; all drafts when omitted
+    assignee: Option<String>,
+    /// `draft`, `pending_approval` or `approved`; every unsent draft when omitted
+    status: Option<String>,
+}
+
+/// Unsent drafts of the mailbox waiting for review, oldest first, with their age against the review SLA
+#[get("/review-queue")]
+async fn review_queue(query: web::Query<ReviewQueueQuery>, mailbox: Mailbox) -> HttpResponse {
+    let pool = db::get_pool();
+
+    let (assignee_id, only_unassigned) = match query.assignee.as_deref() {
+        None => (None, false),
+        Some("me") => (Some(mailbox.user.user_id), false),
+        Some("unassigned") => (None, true),
+        Some(other) => match other.parse::<i32>() {
+            Ok(id) => (Some(id), false),
+            Err(_) => return HttpResponse::BadRequest().body("assignee must be me, unassigned or a user id"),
+        },
+    };
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT d.id, d.email_id, COALESCE(d.status, 'draft') AS "status!", d.revision,
+               d.assigned_to, au.primary_email AS "assignee_email?",
+               cu.id AS "claimed_by?", cu.primary_email AS "claimed_by_email?",
+               e.sender, e.subject, d.created_at,
+               (EXTRACT(EPOCH FROM NOW() - COALESCE(d.created_at, NOW())) / 60)::bigint AS "age_minutes!"
+        FROM drafts d
+        LEFT JOIN emails e ON e.id = d.email_id
+        LEFT JOIN users au ON au.id = d.assigned_to
+        LEFT JOIN users cu ON cu.id = d.claimed_by AND d.claimed_at > NOW() - make_interval(mins => $5)
+        WHERE d.account_id = $1
+          AND d.status IS DISTINCT FROM 'sent'
+          AND ($2::text IS NULL OR COALESCE(d.status, 'draft') = $2)
+          AND ($3::int IS NULL OR d.assigned_to = $3)
+          AND (NOT $4 OR d.assigned_to IS NULL)
+        ORDER BY d.created_at ASC
+        "#,
+        mailbox.account_id,
+        query.status,
+        assignee_id,
+        only_unassigned,
+        CLAIM_MINUTES
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    let sla_minutes = config::review_sla_minutes();
+
+    let queue: Vec<_> = rows.into_iter().map(|r| {
+        serde_json::json!({
+            "id": r.id,
+            "email_id": r.email_id,
+            "sender": r.sender,
+            "subject": r.subject,
+            "status": r.status,
+            "revision": r.revision,
+            "assigned_to": r.assigned_to,
+            "assignee_email": r.assignee_email,
+            "claimed_by": r.claimed_by,
+            "claimed_by_email": r.claimed_by_email,
+            "created_at": r.created_at,
+            "age_minutes": r.age_minutes,
+            "sla_minutes": sla_minutes,
+            "overdue": r.age_minutes > sla_minutes
+        })
+    }).collect();
+
+    HttpResponse::Ok().json(queue)
+}
+
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(list_drafts)
         .service(generate_draft)
         .service(get_draft)
         .service(update_draft)
         .service(approve_draft)
+        .service(list_approvals)
+        .service(assign_draft)
+        .service(claim_draft)
+        .service(unclaim_draft)
+        .service(review_queue)
         .service(send_draft);
 }
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+use crate::db;
+use crate::db::workspaces::Role;
+use crate::middleware::Mailbox;
+use crate::services::embeddings::{self, Provider};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(similar_replies)
+       .service(semantic_search)
+       .service(reindex);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+#[derive(Deserialize)]
+struct SimilarQuery {
+    limit: Option<usize>,
+    /// Lowest cosine similarity returned, 0 to 1 (default `MIN_SIMILAR_SCORE`)
+    min_score: Option<f32>,
+}
+
+/// Replies the mailbox sent to the emails most similar to this one, best first
+#[get("/emails/{id}/similar-replies")]
+async fn similar_replies(path: web::Path<i32>, query: web::Query<SimilarQuery>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let email_id = path.into_inner();
+
+    let email = sqlx::query!(
+        "SELECT subject, body_text FROM emails WHERE id = $1 AND account_id = $2",
+        email_id,
+        mailbox.account_id
+    )
+    .fetch_optional(db::get_pool())
+    .await
+    .map_err(db_error)?;
+
+    let email = match email {
+        Some(e) => e,
+        None => return Ok(HttpResponse::NotFound().body("Email not found")),
+    };
+
+    let text = embeddings::email_text(email.subject.as_deref().unwrap_or(""), email.body_text.as_deref().unwrap_or(""));
+    let limit = query.limit.unwrap_or(3).clamp(1, 20);
+    let min_score = query.min_score.unwrap_or(embeddings::MIN_SIMILAR_SCORE).clamp(0.0, 1.0);
+
+    match embeddings::similar_replies(mailbox.account_id, &text, Some(email_id), limit, min_score).await {
+        Ok(replies) => Ok(HttpResponse::Ok().json(serde_json::json!({ "replies": replies }))),
+        Err(e) => Ok(HttpResponse::BadGateway().body(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct SemanticQuery {
+    q: String,
+    /// `all` (default), `emails` or `drafts`
+    #[serde(rename = "in")]
+    scope: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Emails and drafts ranked by meaning rather than exact words
+#[get("/search/semantic")]
+async fn semantic_search(query: web::Query<SemanticQuery>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    if query.q.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().body("q is required"));
+    }
+    let source_type = match query.scope.as_deref() {
+        None | Some("all") => None,
+        Some("emails") => Some("email"),
+        Some("drafts") => Some("draft"),
+        Some(_) => return Ok(HttpResponse::BadRequest().body("in must be all, emails or drafts")),
+    };
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+
+    let matches = match embeddings::semantic_search(mailbox.account_id, &query.q, source_type, limit).await {
+        Ok(m) => m,
+        Err(e) => return Ok(HttpResponse::BadGateway().body(e)),
+    };
+
+    let ids = |kind: &str| -> Vec<i32> {
+        matches.iter().filter(|m| m.source_type == kind).map(|m| m.id).collect()
+    };
+    let pool = db::get_pool();
+
+    let emails: HashMap<i32, serde_json::Value> = sqlx::query!(
+        "SELECT id, thread_id, sender, subject, snippet, fetched_at FROM emails WHERE account_id = $1 AND id = ANY($2)",
+        mailbox.account_id,
+        &ids("email")[..]
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?
+    .into_iter()
+    .map(|r| {
+        (r.id, serde_json::json!({
+            "thread_id": r.thread_id,
+            "sender": r.sender,
+            "subject": r.subject,
+            "snippet": r.snippet,
+            "fetched_at": r.fetched_at
+        }))
+    })
+    .collect();
+
+    let drafts: HashMap<i32, serde_json::Value> = sqlx::query!(
+        r#"
+        SELECT d.id, d.email_id, COALESCE(d.status, 'draft') AS "status!", left(coalesce(d.content, ''), 200) AS snippet,
+               d.created_at, e.subject
+        FROM drafts d
+        LEFT JOIN emails e ON e.id = d.email_id
+        WHERE d.account_id = $1 AND d.id = ANY($2)
+        "#,
+        mailbox.account_id,
+        &ids("draft")[..]
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?
+    .into_iter()
+    .map(|r| {
+        (r.id, serde_json::json!({
+            "email_id": r.email_id,
+            "status": r.status,
+            "subject": r.subject,
+            "snippet": r.snippet,
+            "created_at": r.created_at
+        }))
+    })
+    .collect();
+
+    // vectors can outlive their source until the next reindex prunes them
+    let results: Vec<serde_json::Value> = matches
+        .iter()
+        .filter_map(|m| {
+            let record = match m.source_type.as_str() {
+                "email" => emails.get(&m.id),
+                _ => drafts.get(&m.id),
+            }?;
+            let mut result = serde_json::json!({ "type": m.source_type, "id": m.id, "score": m.score });
+            if let (Some(obj), Some(fields)) = (result.as_object_mut(), record.as_object()) {
+                obj.extend(fields.clone());
+            }
+            Some(result)
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "results": results })))
+}
+
+#[derive(Deserialize)]
+struct ReindexQuery {
+    /// Emails and drafts embedded per call
+    limit: Option<i64>,
+}
+
+/// Embeds emails and sent drafts that have no vector for the configured model yet, e.g.
+/// mail fetched before embeddings existed or after switching providers. Call repeatedly
+/// until `remaining` is 0.
+#[post("/embeddings/reindex")]
+async fn reindex(query: web::Query<ReindexQuery>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+
+    let provider = match Provider::from_env() {
+        Ok(p) => p,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(e)),
+    };
+    let model = provider.model();
+    let limit = query.limit.unwrap_or(200).clamp(1, 1000);
+    let pool = db::get_pool();
+
+    let pruned = db::embeddings::prune(mailbox.account_id).await.map_err(db_error)?;
+
+    let emails = sqlx::query!(
+        r#"
+        SELECT e.id, e.subject, e.body_text
+        FROM emails e
+        WHERE e.account_id = $1
+          AND NOT EXISTS (SELECT 1 FROM embeddings v WHERE v.source_type = 'email' AND v.source_id = e.id AND v.model = $2)
+        ORDER BY e.id DESC
+        LIMIT $3
+        "#,
+        mailbox.account_id,
+        model,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut indexed_emails = 0;
+    for e in &emails {
+        let text = embeddings::email_text(e.subject.as_deref().unwrap_or(""), e.body_text.as_deref().unwrap_or(""));
+        if let Err(err) = embeddings::index(&provider, mailbox.account_id, "email", e.id, &text).await {
+            return Ok(HttpResponse::BadGateway().body(err));
+        }
+        indexed_emails += 1;
+    }
+
+    let drafts = sqlx::query!(
+        r#"
+        SELECT d.id, d.content AS "content!"
+        FROM drafts d
+        WHERE d.account_id = $1 AND d.status = 'sent' AND d.content IS NOT NULL
+          AND NOT EXISTS (SELECT 1 FROM embeddings v WHERE v.source_type = 'draft' AND v.source_id = d.id AND v.model = $2)
+        ORDER BY d.id DESC
+        LIMIT $3
+        "#,
+        mailbox.account_id,
+        model,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut indexed_drafts = 0;
+    for d in &drafts {
+        if let Err(err) = embeddings::index(&provider, mailbox.account_id, "draft", d.id, &d.content).await {
+            return Ok(HttpResponse::BadGateway().body(err));
+        }
+        indexed_drafts += 1;
+    }
+
+    let remaining = sqlx::query_scalar!(
+        r#"
+        SELECT (SELECT COUNT(*) FROM emails e WHERE e.account_id = $1
+                  AND NOT EXISTS (SELECT 1 FROM embeddings v WHERE v.source_type = 'email' AND v.source_id = e.id AND v.model = $2))
+             + (SELECT COUNT(*) FROM drafts d WHERE d.account_id = $1 AND d.status = 'sent' AND d.content IS NOT NULL
+                  AND NOT EXISTS (SELECT 1 FROM embeddings v WHERE v.source_type = 'draft' AND v.source_id = d.id AND v.model = $2))
+            AS "remaining!"
+        "#,
+        mailbox.account_id,
+        model
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(db_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "model": model,
+        "indexed_emails": indexed_emails,
+        "indexed_drafts": indexed_drafts,
+        "pruned": pruned,
+        "remaining": remaining
+    })))
+}
@@ -1,6 +1,11 @@
-use actix_web::{get, post, web, HttpResponse};
+use actix_web::{get, post, web, HttpResponse, ResponseError};
 use serde::Deserialize;
-use crate::middleware::AuthenticatedUser;
+use crate::middleware::Mailbox;
+use crate::db;
+use crate::db::workspaces::Role;
+use crate::services::{classifier, embeddings, gmail_client, gmail_triage, rules};
+use crate::services::gmail_triage::TriageActions;
+use crate::services::google_oauth::GoogleError;
 // Remove unused imports
 // use crate::models::EmailRow;
 // use sqlx::Row;
@@ -8,22 +13,53 @@ use crate::middleware::AuthenticatedUser;
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(list_emails)
        .service(get_email)
+       .service(classify_email)
+       .service(batch_email_actions)
+       .service(email_actions)
        .service(fetch_unread)
        .service(fetch_one);
 }
 
+#[derive(Deserialize)]
+struct EmailListQuery {
+    category: Option<String>,
+    needs_reply: Option<bool>,
+    min_urgency: Option<i32>,
+    /// `fetched_at` (default, newest first) or `urgency` (most urgent first)
+    sort: Option<String>,
+}
+
 #[get("/emails")]
-async fn list_emails(user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+async fn list_emails(query: web::Query<EmailListQuery>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let sort = query.sort.as_deref().unwrap_or("fetched_at");
+    if sort != "fetched_at" && sort != "urgency" {
+        return Ok(HttpResponse::BadRequest().body("sort must be fetched_at or urgency"));
+    }
+    if query.category.as_deref().is_some_and(|c| !classifier::CATEGORIES.contains(&c)) {
+        return Ok(HttpResponse::BadRequest().body(format!(
+            "category must be one of {}",
+            classifier::CATEGORIES.join(", ")
+        )));
+    }
+
     let pool = crate::db::get_pool();
     let rows = sqlx::query!(
         r#"
-        SELECT id, gmail_id, thread_id, user_email, sender, to_recipients, subject, snippet, body_text, body_html, labels, fetched_at
+        SELECT id, gmail_id, thread_id, user_email, sender, to_recipients, subject, snippet, body_text, body_html, labels, fetched_at,
+               replied_at, replied_draft_id, category, urgency, needs_reply
         FROM emails
-        WHERE user_email = $1
-        ORDER BY fetched_at DESC
+        WHERE account_id = $1
+          AND ($2::text IS NULL OR category = $2)
+          AND ($3::boolean IS NULL OR needs_reply = $3)
+          AND ($4::int IS NULL OR urgency >= $4)
+        ORDER BY CASE WHEN $5 = 'urgency' THEN urgency END DESC NULLS LAST, fetched_at DESC
         LIMIT 100
         "#,
-        user.email
+        mailbox.account_id,
+        query.category,
+        query.needs_reply,
+        query.min_urgency,
+        sort
     )
     .fetch_all(pool)
     .await
@@ -43,24 +79,43 @@ async fn list_emails(user: AuthenticatedUser) -> Result<HttpResponse, actix_web:
             "subject": r.subject,
             "snippet": r.snippet,
             "has_body": r.body_text.is_some() || r.body_html.is_some(),
+            "labels": r.labels,
+            "category": r.category,
+            "urgency": r.urgency,
+            "needs_reply": r.needs_reply,
             "fetched_at": r.fetched_at,
+            "replied_at": r.replied_at,
+            "replied_draft_id": r.replied_draft_id,
         })
     }).collect();
 
     Ok(HttpResponse::Ok().json(mapped))
 }
 
+/// Classifies (or re-classifies) a stored email
+#[post("/emails/{id}/classify")]
+async fn classify_email(path: web::Path<i32>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+
+    match classifier::classify_email(mailbox.account_id, path.into_inner()).await {
+        Ok(Some(c)) => Ok(HttpResponse::Ok().json(c)),
+        Ok(None) => Ok(HttpResponse::NotFound().body("email not found")),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(format!("classification failed: {}", e))),
+    }
+}
+
 #[get("/emails/{id}")]
-async fn get_email(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+async fn get_email(path: web::Path<i32>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
     let id = path.into_inner();
     let pool = crate::db::get_pool();
     let row = sqlx::query!(
         r#"
-        SELECT id, gmail_id, thread_id, user_email, sender, to_recipients, subject, snippet, body_text, body_html, labels, fetched_at
-        FROM emails WHERE id = $1 AND user_email = $2
+        SELECT id, gmail_id, thread_id, user_email, sender, to_recipients, subject, snippet, body_text, body_html, labels, fetched_at,
+               replied_at, replied_draft_id, category, urgency, needs_reply
+        FROM emails WHERE id = $1 AND account_id = $2
         "#,
         id,
-        user.email
+        mailbox.account_id
     )
     .fetch_optional(pool)
     .await
@@ -82,7 +137,12 @@ async fn get_email(path: web::Path<i32>, user: AuthenticatedUser) -> Result<Http
             "body_text": r.body_text,
             "body_html": r.body_html,
             "labels": r.labels,
-            "fetched_at": r.fetched_at
+            "category": r.category,
+            "urgency": r.urgency,
+            "needs_reply": r.needs_reply,
+            "fetched_at": r.fetched_at,
+            "replied_at": r.replied_at,
+            "replied_draft_id": r.replied_draft_id
         });
         Ok(HttpResponse::Ok().json(out))
     } else {
@@ -90,30 +150,109 @@ async fn get_email(path: web::Path<i32>, user: AuthenticatedUser) -> Result<Http
     }
 }
 
-#[post("/internal/fetch-unread")]
-async fn fetch_unread(user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
-    let user_email = user.email;
+/// Labels, archives, marks read/unread, stars or trashes one email
+#[post("/emails/{id}/actions")]
+async fn email_actions(path: web::Path<i32>, actions: web::Json<TriageActions>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    triage(&mailbox, &[path.into_inner()], &actions).await
+}
 
-    let access_token = match crate::services::google_oauth::refresh_access_token_for_user(&user_email).await {
-        Ok(at) => at,
-        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("token refresh failed: {}", e))),
-    };
+#[derive(Deserialize)]
+struct BatchActions {
+    email_ids: Vec<i32>,
+    #[serde(flatten)]
+    actions: TriageActions,
+}
 
-    let client = reqwest::Client::new();
-    let list_url = "https://gmail.googleapis.com/gmail/v1/users/me/messages?q=is:unread label:inbox";
-    let resp = client.get(list_url).bearer_auth(access_token)
-        .send().await
-        .map_err(|e| {
-            log::error!("http: {:?}", e);
-            actix_web::error::ErrorInternalServerError("http error")
-        })?;
+/// Applies the same triage actions to several emails with one Gmail batchModify call
+#[post("/emails/actions")]
+async fn batch_email_actions(req: web::Json<BatchActions>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let mut email_ids = req.email_ids.clone();
+    email_ids.sort();
+    email_ids.dedup();
+    if email_ids.is_empty() || email_ids.len() > 1000 {
+        return Ok(HttpResponse::BadRequest().body("email_ids must contain 1 to 1000 ids"));
+    }
+    triage(&mailbox, &email_ids, &req.actions).await
+}
 
-    let status = resp.status();
-    let text = resp.text().await
-        .map_err(|e| {
-            log::error!("text: {:?}", e);
-            actix_web::error::ErrorInternalServerError("text error")
-        })?;
+async fn triage(mailbox: &Mailbox, email_ids: &[i32], actions: &TriageActions) -> Result<HttpResponse, actix_web::Error> {
+    if actions.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("no actions given"));
+    }
+
+    let pool = crate::db::get_pool();
+    let emails = sqlx::query!(
+        "SELECT id, gmail_id FROM emails WHERE account_id = $1 AND id = ANY($2)",
+        mailbox.account_id,
+        email_ids
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+    if emails.len() != email_ids.len() {
+        return Ok(HttpResponse::NotFound().body("email not found"));
+    }
+
+    let gmail_ids: Vec<String> = emails.iter().map(|e| e.gmail_id.clone()).collect();
+    match gmail_triage::apply(mailbox.account_id, &mailbox.email, &gmail_ids, actions).await {
+        Ok(()) => {}
+        Err(e @ GoogleError::ReauthRequired(_)) => return Ok(e.error_response()),
+        Err(e) => return Ok(HttpResponse::BadGateway().body(format!("gmail modify failed: {}", e))),
+    }
+
+    for email in &emails {
+        db::audit::NewEvent::by(&mailbox.user, "email.triaged")
+            .mailbox(mailbox.account_id)
+            .target("email", email.id)
+            .metadata(serde_json::json!({ "actions": actions }))
+            .record()
+            .await;
+    }
+
+    let labels = sqlx::query!(
+        "SELECT id, labels FROM emails WHERE account_id = $1 AND id = ANY($2) ORDER BY id",
+        mailbox.account_id,
+        email_ids
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        log::error!("db error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+
+    let out: Vec<_> = labels
+        .into_iter()
+        .map(|r| serde_json::json!({ "id": r.id, "labels": r.labels }))
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "emails": out })))
+}
+
+#[post("/internal/fetch-unread")]
+async fn fetch_unread(mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let user_email = mailbox.email.clone();
+
+    // sync stays paused until the user re-consents
+    let paused = db::mailbox_accounts::needs_reauth(&user_email).await.map_err(|e| {
+        log::error!("db: {:?}", e);
+        actix_web::error::ErrorInternalServerError("db error")
+    })?;
+    if paused {
+        return Ok(GoogleError::ReauthRequired(user_email).error_response());
+    }
+
+    let list_url = format!("{}/messages?q=is:unread label:inbox", gmail_client::GMAIL_API);
+    let (status, text) = match gmail_client::send(&user_email, |c| c.get(&list_url)).await {
+        Ok(r) => r,
+        Err(e @ GoogleError::ReauthRequired(_)) => return Ok(e.error_response()),
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("gmail list failed: {}", e))),
+    };
     if !status.is_success() {
         return Ok(HttpResponse::InternalServerError().body(format!("gmail list err: {}", text)));
     }
@@ -125,15 +264,76 @@ async fn fetch_unread(user: AuthenticatedUser) -> Result<HttpResponse, actix_web
         })?;
     let messages = v["messages"].as_array().cloned().unwrap_or_default();
 
+    let mut fetched = Vec::new();
+    let mut new_emails = Vec::new();
     for m in messages.into_iter().take(20) {
         if let Some(gid) = m["id"].as_str() {
-            if let Err(e) = crate::services::gmail_fetcher::fetch_and_store_message(&user_email, gid).await {
-                log::error!("fetch store failed for {}: {:?}", gid, e);
+            match crate::services::gmail_fetcher::fetch_and_store_message(mailbox.account_id, &user_email, gid).await {
+                Ok(stored) => {
+                    fetched.push(gid.to_string());
+                    if stored.inserted {
+                        new_emails.push(stored.id);
+                    }
+                }
+                Err(e @ GoogleError::ReauthRequired(_)) => return Ok(e.error_response()),
+                Err(e) => log::error!("fetch store failed for {}: {:?}", gid, e),
             }
         }
     }
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({"fetched": true})))
+    db::audit::NewEvent::by(&mailbox.user, "emails.fetched")
+        .mailbox(mailbox.account_id)
+        .target("mailbox", mailbox.account_id)
+        .metadata(serde_json::json!({ "gmail_ids": fetched }))
+        .record()
+        .await;
+
+    let auto_drafts = process_new_emails(&mailbox, &new_emails).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({"fetched": true, "auto_drafts": auto_drafts})))
+}
+
+/// Classifies newly stored emails and runs the mailbox's rules on them. Failures are
+/// logged so one bad model or Gmail call doesn't fail the sync. Returns the ids of the
+/// drafts created.
+async fn process_new_emails(mailbox: &Mailbox, email_ids: &[i32]) -> Vec<i32> {
+    let mut drafts = Vec::new();
+    for &email_id in email_ids {
+        if let Err(e) = classifier::classify_email(mailbox.account_id, email_id).await {
+            log::error!("classification failed for email {}: {}", email_id, e);
+        }
+        if let Err(e) = embeddings::index_email(mailbox.account_id, email_id).await {
+            log::error!("embedding failed for email {}: {}", email_id, e);
+        }
+
+        let outcome = match rules::apply_to_new_email(mailbox.account_id, &mailbox.email, email_id).await {
+            Ok(Some(outcome)) => outcome,
+            Ok(None) => continue,
+            Err(e) => {
+                log::error!("rules failed for email {}: {}", email_id, e);
+                continue;
+            }
+        };
+
+        if let Some(actions) = outcome.actions {
+            db::audit::NewEvent::by(&mailbox.user, "email.triaged")
+                .mailbox(mailbox.account_id)
+                .target("email", email_id)
+                .metadata(serde_json::json!({ "rule_id": outcome.rule_id, "actions": actions }))
+                .record()
+                .await;
+        }
+        if let Some(draft_id) = outcome.draft_id {
+            db::audit::NewEvent::by(&mailbox.user, "draft.auto_generated")
+                .mailbox(mailbox.account_id)
+                .target("draft", draft_id)
+                .metadata(serde_json::json!({ "email_id": email_id, "rule_id": outcome.rule_id }))
+                .record()
+                .await;
+            drafts.push(draft_id);
+        }
+    }
+    drafts
 }
 
 #[derive(Deserialize)]
@@ -142,12 +342,23 @@ pub struct FetchOnePath {
 }
 
 #[post("/internal/fetch/{gmail_id}")]
-async fn fetch_one(path: web::Path<FetchOnePath>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+async fn fetch_one(path: web::Path<FetchOnePath>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
     let gmail_id = path.into_inner().gmail_id;
-    let user_email = user.email;
+    let user_email = &mailbox.email;
 
-    match crate::services::gmail_fetcher::fetch_and_store_message(&user_email, &gmail_id).await {
-        Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({"ok": true}))),
+    match crate::services::gmail_fetcher::fetch_and_store_message(mailbox.account_id, user_email, &gmail_id).await {
+        Ok(stored) => {
+            db::audit::NewEvent::by(&mailbox.user, "email.fetched")
+                .mailbox(mailbox.account_id)
+                .target("gmail_message", &gmail_id)
+                .record()
+                .await;
+            let new_emails = if stored.inserted { vec![stored.id] } else { Vec::new() };
+            let auto_drafts = process_new_emails(&mailbox, &new_emails).await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({"ok": true, "email_id": stored.id, "auto_drafts": auto_drafts})))
+        }
+        Err(e @ GoogleError::ReauthRequired(_)) => Ok(e.error_response()),
         Err(e) => Ok(HttpResponse::InternalServerError().body(format!("fetch failed: {}", e))),
     }
 }
\ No newline at end of file
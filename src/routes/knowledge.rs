@@ -0,0 +1,252 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use serde::Deserialize;
+use crate::db;
+use crate::db::approvals::content_hash;
+use crate::db::workspaces::Role;
+use crate::middleware::AuthenticatedUser;
+use crate::routes::workspaces::require_role;
+use crate::services::embeddings::Provider;
+use crate::services::knowledge;
+
+/// Largest document accepted, in characters
+const MAX_DOCUMENT_CHARS: usize = 1_000_000;
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    // search and reindex go before the `{doc_id}` routes they would otherwise match
+    cfg.service(search_knowledge)
+       .service(reindex_knowledge)
+       .service(list_documents)
+       .service(create_document)
+       .service(get_document)
+       .service(update_document)
+       .service(delete_document);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+#[derive(Deserialize)]
+struct DocumentInput {
+    title: String,
+    /// `markdown`, `text` (default) or `pdf` for text extracted from a PDF
+    format: Option<String>,
+    content: String,
+}
+
+impl DocumentInput {
+    /// Trimmed title and format, or a message for a 400
+    fn validate(&self) -> Result<(&str, &str), String> {
+        let title = self.title.trim();
+        if title.is_empty() {
+            return Err("title is required".to_string());
+        }
+        let format = self.format.as_deref().unwrap_or("text");
+        if !knowledge::FORMATS.contains(&format) {
+            return Err(format!("format must be one of {}", knowledge::FORMATS.join(", ")));
+        }
+        if self.content.trim().is_empty() {
+            return Err("content is required".to_string());
+        }
+        if self.content.chars().count() > MAX_DOCUMENT_CHARS {
+            return Err(format!("content is longer than {} characters", MAX_DOCUMENT_CHARS));
+        }
+        Ok((title, format))
+    }
+}
+
+/// Chunks and embeds a stored document. The document is kept when embedding fails, so
+/// the error says to retry with reindex.
+async fn index(document: &db::knowledge::Document) -> Result<usize, HttpResponse> {
+    let provider = Provider::from_env().map_err(|e| HttpResponse::InternalServerError().body(e))?;
+    knowledge::index_document(&provider, document).await.map_err(|e| {
+        HttpResponse::BadGateway().json(serde_json::json!({
+            "error": "indexing_failed",
+            "message": e,
+            "document_id": document.id
+        }))
+    })
+}
+
+#[get("/workspaces/{id}/knowledge")]
+async fn list_documents(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Viewer).await?;
+
+    let documents = db::knowledge::list(workspace_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(documents))
+}
+
+#[post("/workspaces/{id}/knowledge")]
+async fn create_document(path: web::Path<i32>, req: web::Json<DocumentInput>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Editor).await?;
+    let (title, format) = match req.validate() {
+        Ok(v) => v,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    let hash = content_hash(&req.content);
+    let document = db::knowledge::create(workspace_id, title, format, &req.content, &hash, user.user_id)
+        .await
+        .map_err(db_error)?;
+
+    db::audit::NewEvent::by(&user, "knowledge.document_created")
+        .target("knowledge_document", document.id)
+        .after(hash)
+        .metadata(serde_json::json!({ "workspace_id": workspace_id, "title": title, "format": format }))
+        .record()
+        .await;
+
+    let chunk_count = match index(&document).await {
+        Ok(n) => n,
+        Err(resp) => return Ok(resp),
+    };
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "id": document.id,
+        "title": document.title,
+        "format": document.format,
+        "chunk_count": chunk_count,
+        "created_at": document.created_at
+    })))
+}
+
+#[get("/workspaces/{id}/knowledge/{doc_id}")]
+async fn get_document(path: web::Path<(i32, i32)>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let (workspace_id, doc_id) = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Viewer).await?;
+
+    match db::knowledge::get(workspace_id, doc_id).await.map_err(db_error)? {
+        Some(document) => Ok(HttpResponse::Ok().json(document)),
+        None => Ok(HttpResponse::NotFound().body("Document not found")),
+    }
+}
+
+/// Replaces a document; it is chunked and embedded again when its content or format changed
+#[put("/workspaces/{id}/knowledge/{doc_id}")]
+async fn update_document(path: web::Path<(i32, i32)>, req: web::Json<DocumentInput>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let (workspace_id, doc_id) = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Editor).await?;
+    let (title, format) = match req.validate() {
+        Ok(v) => v,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    let previous = match db::knowledge::get(workspace_id, doc_id).await.map_err(db_error)? {
+        Some(d) => d,
+        None => return Ok(HttpResponse::NotFound().body("Document not found")),
+    };
+
+    let hash = content_hash(&req.content);
+    let document = db::knowledge::update(workspace_id, doc_id, title, format, &req.content, &hash)
+        .await
+        .map_err(db_error)?;
+    let document = match document {
+        Some(d) => d,
+        None => return Ok(HttpResponse::NotFound().body("Document not found")),
+    };
+
+    db::audit::NewEvent::by(&user, "knowledge.document_updated")
+        .target("knowledge_document", doc_id)
+        .before(previous.content_hash.clone())
+        .after(hash.clone())
+        .metadata(serde_json::json!({ "workspace_id": workspace_id, "title": title, "format": format }))
+        .record()
+        .await;
+
+    // the title is part of every chunk's embedded text, so a rename reindexes too
+    let changed = previous.content_hash != hash || previous.format != format || previous.title != title;
+    let reindexed = if changed {
+        match index(&document).await {
+            Ok(_) => true,
+            Err(resp) => return Ok(resp),
+        }
+    } else {
+        false
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": document.id,
+        "title": document.title,
+        "format": document.format,
+        "reindexed": reindexed,
+        "updated_at": document.updated_at
+    })))
+}
+
+/// Deletes a document and its chunks. Drafts keep their copies of the chunks they cited.
+#[delete("/workspaces/{id}/knowledge/{doc_id}")]
+async fn delete_document(path: web::Path<(i32, i32)>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let (workspace_id, doc_id) = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Editor).await?;
+
+    if db::knowledge::delete(workspace_id, doc_id).await.map_err(db_error)? == 0 {
+        return Ok(HttpResponse::NotFound().body("Document not found"));
+    }
+
+    db::audit::NewEvent::by(&user, "knowledge.document_deleted")
+        .target("knowledge_document", doc_id)
+        .metadata(serde_json::json!({ "workspace_id": workspace_id }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "deleted": true })))
+}
+
+#[derive(Deserialize)]
+struct KnowledgeSearch {
+    q: String,
+    limit: Option<usize>,
+    /// Lowest cosine similarity returned, 0 to 1 (default `MIN_CHUNK_SCORE`)
+    min_score: Option<f32>,
+}
+
+/// The chunks a draft for an email like `q` would be grounded on, to check coverage
+#[get("/workspaces/{id}/knowledge/search")]
+async fn search_knowledge(path: web::Path<i32>, query: web::Query<KnowledgeSearch>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Viewer).await?;
+
+    if query.q.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().body("q is required"));
+    }
+    let limit = query.limit.unwrap_or(4).clamp(1, 20);
+    let min_score = query.min_score.unwrap_or(knowledge::MIN_CHUNK_SCORE).clamp(0.0, 1.0);
+
+    match knowledge::retrieve(workspace_id, &query.q, limit, min_score).await {
+        Ok(chunks) => Ok(HttpResponse::Ok().json(serde_json::json!({ "chunks": chunks }))),
+        Err(e) => Ok(HttpResponse::BadGateway().body(e)),
+    }
+}
+
+/// Chunks and embeds documents that have no chunks for the configured embedding model,
+/// e.g. after a failed upload or a provider change
+#[post("/workspaces/{id}/knowledge/reindex")]
+async fn reindex_knowledge(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Editor).await?;
+
+    let provider = match Provider::from_env() {
+        Ok(p) => p,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(e)),
+    };
+    let documents = db::knowledge::stale_documents(workspace_id, &provider.model())
+        .await
+        .map_err(db_error)?;
+
+    let mut chunks = 0;
+    for document in &documents {
+        match knowledge::index_document(&provider, document).await {
+            Ok(n) => chunks += n,
+            Err(e) => return Ok(HttpResponse::BadGateway().body(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "model": provider.model(),
+        "documents": documents.len(),
+        "chunks": chunks
+    })))
+}
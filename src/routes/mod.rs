@@ -1,6 +1,15 @@
 pub mod auth;
 pub mod gmail;
 pub mod drafts;
-
-pub use self::auth::*;
-pub use self::gmail::*;
\ No newline at end of file
+pub mod accounts;
+pub mod workspaces;
+pub mod comments;
+pub mod audit;
+pub mod rules;
+pub mod summaries;
+pub mod search;
+pub mod embeddings;
+pub mod knowledge;
+pub mod templates;
+pub mod style;
+pub mod prompts;
@@ -0,0 +1,194 @@
+use actix_web::{get, post, put, web, HttpResponse};
+use serde::Deserialize;
+use crate::db;
+use crate::db::prompts::PromptInput;
+use crate::db::workspaces::Role;
+use crate::middleware::AuthenticatedUser;
+use crate::routes::workspaces::require_role;
+use crate::services::{groq_ai, prompts};
+
+/// Longest system message or body accepted, in characters
+const MAX_PROMPT_CHARS: usize = 20_000;
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    // `active` goes before the `{prompt_id}` route it would otherwise match
+    cfg.service(select_prompt)
+       .service(list_prompts)
+       .service(create_prompt)
+       .service(get_prompt);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+#[derive(Deserialize)]
+struct PromptRequest {
+    /// Prompt the version belongs to; only `reply` for now
+    name: String,
+    description: Option<String>,
+    system: String,
+    body: String,
+    model: Option<String>,
+    temperature: Option<f64>,
+    max_tokens: Option<i32>,
+    /// Make the workspace generate with the new version right away
+    #[serde(default)]
+    activate: bool,
+}
+
+impl PromptRequest {
+    /// Checks the request and returns it as a db input, or a 400 message
+    fn validate(&self) -> Result<PromptInput<'_>, String> {
+        prompts::validate(&self.name, &self.system, &self.body)?;
+        if self.system.chars().count() > MAX_PROMPT_CHARS || self.body.chars().count() > MAX_PROMPT_CHARS {
+            return Err(format!("system and body must be at most {} characters", MAX_PROMPT_CHARS));
+        }
+        let model = self.model.as_deref().map(str::trim).unwrap_or(groq_ai::DEFAULT_MODEL);
+        if model.is_empty() {
+            return Err("model must not be empty".to_string());
+        }
+        let temperature = self.temperature.unwrap_or(0.7);
+        if !(0.0..=2.0).contains(&temperature) {
+            return Err("temperature must be between 0 and 2".to_string());
+        }
+        let max_tokens = self.max_tokens.unwrap_or(500);
+        if !(1..=4096).contains(&max_tokens) {
+            return Err("max_tokens must be between 1 and 4096".to_string());
+        }
+
+        Ok(PromptInput {
+            name: &self.name,
+            description: self.description.as_deref().map(str::trim).filter(|d| !d.is_empty()),
+            system: &self.system,
+            body: &self.body,
+            model,
+            temperature,
+            max_tokens,
+        })
+    }
+}
+
+/// Built-in and workspace versions, flagging the ones the workspace generates with
+#[get("/workspaces/{id}/prompts")]
+async fn list_prompts(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Viewer).await?;
+
+    let versions = db::prompts::list(workspace_id).await.map_err(db_error)?;
+    let mut active = Vec::new();
+    for name in prompts::NAMES {
+        if let Some(p) = db::prompts::resolve(Some(workspace_id), name).await.map_err(db_error)? {
+            active.push(p.id);
+        }
+    }
+
+    let versions: Vec<_> = versions
+        .into_iter()
+        .map(|p| {
+            let mut value = serde_json::to_value(&p).unwrap_or_default();
+            value["builtin"] = serde_json::json!(p.workspace_id.is_none());
+            value["active"] = serde_json::json!(active.contains(&p.id));
+            value
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+/// Adds the next version of a prompt. Versions are never edited, so drafts keep pointing
+/// at the exact prompt they were written with.
+#[post("/workspaces/{id}/prompts")]
+async fn create_prompt(path: web::Path<i32>, req: web::Json<PromptRequest>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Owner).await?;
+
+    let input = match req.validate() {
+        Ok(input) => input,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let prompt = match db::prompts::create(workspace_id, &input, user.user_id).await {
+        Ok(p) => p,
+        Err(e) if db::prompts::is_duplicate_version(&e) => {
+            return Ok(HttpResponse::Conflict().body("Another version was created at the same time; try again"));
+        }
+        Err(e) => return Err(db_error(e)),
+    };
+    if req.activate {
+        db::prompts::select(workspace_id, &prompt.name, prompt.id, user.user_id)
+            .await
+            .map_err(db_error)?;
+    }
+
+    db::audit::NewEvent::by(&user, "prompt.created")
+        .target("prompt", prompt.id)
+        .metadata(serde_json::json!({
+            "workspace_id": workspace_id,
+            "name": prompt.name,
+            "version": prompt.version,
+            "model": prompt.model,
+            "activated": req.activate
+        }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Created().json(prompt))
+}
+
+#[get("/workspaces/{id}/prompts/{prompt_id}")]
+async fn get_prompt(path: web::Path<(i32, i32)>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let (workspace_id, prompt_id) = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Viewer).await?;
+
+    match db::prompts::get(workspace_id, prompt_id).await.map_err(db_error)? {
+        Some(prompt) => Ok(HttpResponse::Ok().json(prompt)),
+        None => Ok(HttpResponse::NotFound().body("Prompt not found")),
+    }
+}
+
+#[derive(Deserialize)]
+struct SelectRequest {
+    name: String,
+    /// Version to generate with; `null` goes back to the newest built-in version
+    prompt_id: Option<i32>,
+}
+
+/// Picks the version of a prompt the workspace's mailboxes generate with
+#[put("/workspaces/{id}/prompts/active")]
+async fn select_prompt(path: web::Path<i32>, req: web::Json<SelectRequest>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Owner).await?;
+
+    if !prompts::NAMES.contains(&req.name.as_str()) {
+        return Ok(HttpResponse::BadRequest().body(format!("name must be one of {}", prompts::NAMES.join(", "))));
+    }
+    match req.prompt_id {
+        Some(prompt_id) => {
+            let prompt = db::prompts::get(workspace_id, prompt_id).await.map_err(db_error)?;
+            if prompt.is_none_or(|p| p.name != req.name) {
+                return Ok(HttpResponse::NotFound().body(format!("No {} prompt with that id", req.name)));
+            }
+            db::prompts::select(workspace_id, &req.name, prompt_id, user.user_id)
+                .await
+                .map_err(db_error)?;
+        }
+        None => db::prompts::clear_selection(workspace_id, &req.name).await.map_err(db_error)?,
+    }
+
+    let active = db::prompts::resolve(Some(workspace_id), &req.name).await.map_err(db_error)?;
+
+    let mut event = db::audit::NewEvent::by(&user, "prompt.selected");
+    if let Some(prompt) = &active {
+        event = event.target("prompt", prompt.id);
+    }
+    event
+        .metadata(serde_json::json!({
+            "workspace_id": workspace_id,
+            "name": req.name,
+            "version": active.as_ref().map(|p| p.version)
+        }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(active))
+}
@@ -0,0 +1,281 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::db::rules::RuleInput;
+use crate::db::workspaces::Role;
+use crate::middleware::Mailbox;
+use crate::services::gmail_triage::TriageActions;
+use crate::services::rules::{Conditions, EmailFacts, Matcher};
+
+/// Emails looked at by a dry run unless `limit` says otherwise
+const DRY_RUN_DEFAULT_LIMIT: i64 = 200;
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_rules)
+       .service(create_rule)
+       .service(dry_run)
+       .service(get_rule)
+       .service(update_rule)
+       .service(delete_rule);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+#[derive(Deserialize)]
+struct RuleRequest {
+    name: String,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    #[serde(default = "default_priority")]
+    priority: i32,
+    conditions: Conditions,
+    #[serde(default)]
+    actions: TriageActions,
+    #[serde(default = "default_enabled")]
+    generate_draft: bool,
+    tone: Option<String>,
+    instructions: Option<String>,
+    /// Reply template to draft from
+    template_id: Option<i32>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_priority() -> i32 {
+    100
+}
+
+impl RuleRequest {
+    /// Checks the request and returns it as a db input, or a 400 message
+    fn validate(&self) -> Result<RuleInput<'_>, String> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err("name is required".to_string());
+        }
+        self.conditions.compile()?;
+        if self.actions.is_empty() && !self.generate_draft {
+            return Err("a rule needs actions or generate_draft".to_string());
+        }
+        if self.template_id.is_some() && !self.generate_draft {
+            return Err("template_id needs generate_draft".to_string());
+        }
+
+        Ok(RuleInput {
+            name,
+            enabled: self.enabled,
+            priority: self.priority,
+            conditions: &self.conditions,
+            actions: &self.actions,
+            generate_draft: self.generate_draft,
+            tone: self.tone.as_deref().map(str::trim).filter(|t| !t.is_empty()).unwrap_or("friendly"),
+            instructions: self.instructions.as_deref().map(str::trim).filter(|i| !i.is_empty()),
+            template_id: self.template_id,
+        })
+    }
+}
+
+/// A 400 when the rule names a template the mailbox doesn't have
+async fn check_template(input: &RuleInput<'_>, mailbox: &Mailbox) -> Result<Option<HttpResponse>, actix_web::Error> {
+    let template_id = match input.template_id {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    let template = db::templates::get(mailbox.account_id, template_id).await.map_err(db_error)?;
+    Ok(template.is_none().then(|| HttpResponse::BadRequest().body("Template not found")))
+}
+
+#[get("/rules")]
+async fn list_rules(mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let rules = db::rules::list(mailbox.account_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+#[get("/rules/{id}")]
+async fn get_rule(path: web::Path<i32>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    match db::rules::get(mailbox.account_id, path.into_inner()).await.map_err(db_error)? {
+        Some(rule) => Ok(HttpResponse::Ok().json(rule)),
+        None => Ok(HttpResponse::NotFound().body("Rule not found")),
+    }
+}
+
+#[post("/rules")]
+async fn create_rule(req: web::Json<RuleRequest>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let input = match req.validate() {
+        Ok(input) => input,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    if let Some(response) = check_template(&input, &mailbox).await? {
+        return Ok(response);
+    }
+
+    let id = db::rules::create(mailbox.account_id, &input, mailbox.user.user_id)
+        .await
+        .map_err(db_error)?;
+
+    db::audit::NewEvent::by(&mailbox.user, "rule.created")
+        .mailbox(mailbox.account_id)
+        .target("rule", id)
+        .metadata(serde_json::json!({ "name": input.name }))
+        .record()
+        .await;
+
+    let rule = db::rules::get(mailbox.account_id, id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[put("/rules/{id}")]
+async fn update_rule(path: web::Path<i32>, req: web::Json<RuleRequest>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let rule_id = path.into_inner();
+    let input = match req.validate() {
+        Ok(input) => input,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    if let Some(response) = check_template(&input, &mailbox).await? {
+        return Ok(response);
+    }
+
+    let updated = db::rules::update(mailbox.account_id, rule_id, &input).await.map_err(db_error)?;
+    if updated == 0 {
+        return Ok(HttpResponse::NotFound().body("Rule not found"));
+    }
+
+    db::audit::NewEvent::by(&mailbox.user, "rule.updated")
+        .mailbox(mailbox.account_id)
+        .target("rule", rule_id)
+        .record()
+        .await;
+
+    let rule = db::rules::get(mailbox.account_id, rule_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[delete("/rules/{id}")]
+async fn delete_rule(path: web::Path<i32>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let rule_id = path.into_inner();
+
+    let deleted = db::rules::delete(mailbox.account_id, rule_id).await.map_err(db_error)?;
+    if deleted == 0 {
+        return Ok(HttpResponse::NotFound().body("Rule not found"));
+    }
+
+    db::audit::NewEvent::by(&mailbox.user, "rule.deleted")
+        .mailbox(mailbox.account_id)
+        .target("rule", rule_id)
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "deleted": true
+    })))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct DryRunRequest {
+    /// Unsaved conditions to try; when absent the mailbox's saved rules are evaluated
+    conditions: Option<Conditions>,
+    /// Number of most recent emails to evaluate (max 1000)
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct MatchedEmail {
+    email_id: i32,
+    sender: Option<String>,
+    subject: Option<String>,
+    fetched_at: Option<chrono::NaiveDateTime>,
+    has_draft: bool,
+}
+
+#[derive(Serialize)]
+struct DryRunResult {
+    rule_id: Option<i32>,
+    name: Option<String>,
+    enabled: bool,
+    matches: Vec<MatchedEmail>,
+}
+
+/// Shows which of the mailbox's recent emails each rule matches, without drafting anything
+#[post("/rules/dry-run")]
+async fn dry_run(req: Option<web::Json<DryRunRequest>>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let req = req.map(|r| r.into_inner()).unwrap_or_default();
+    let limit = req.limit.unwrap_or(DRY_RUN_DEFAULT_LIMIT).clamp(1, 1000);
+
+    // (rule id, name, enabled, matcher)
+    let mut candidates: Vec<(Option<i32>, Option<String>, bool, Matcher)> = Vec::new();
+    match &req.conditions {
+        Some(conditions) => match conditions.compile() {
+            Ok(matcher) => candidates.push((None, None, true, matcher)),
+            Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+        },
+        None => {
+            for rule in db::rules::list(mailbox.account_id).await.map_err(db_error)? {
+                match rule.conditions.compile() {
+                    Ok(matcher) => candidates.push((Some(rule.id), Some(rule.name), rule.enabled, matcher)),
+                    Err(e) => log::warn!("skipping draft rule {}: {}", rule.id, e),
+                }
+            }
+        }
+    }
+
+    let emails = sqlx::query!(
+        r#"
+        SELECT e.id, e.sender, e.subject, e.body_text, e.labels, e.fetched_at,
+               EXISTS (SELECT 1 FROM drafts d WHERE d.email_id = e.id) AS "has_draft!"
+        FROM emails e
+        WHERE e.account_id = $1
+        ORDER BY e.fetched_at DESC
+        LIMIT $2
+        "#,
+        mailbox.account_id,
+        limit
+    )
+    .fetch_all(db::get_pool())
+    .await
+    .map_err(db_error)?;
+
+    let mut results: Vec<DryRunResult> = candidates
+        .iter()
+        .map(|(rule_id, name, enabled, _)| DryRunResult {
+            rule_id: *rule_id,
+            name: name.clone(),
+            enabled: *enabled,
+            matches: Vec::new(),
+        })
+        .collect();
+
+    for email in &emails {
+        let labels = email.labels.clone().unwrap_or_default();
+        let facts = EmailFacts {
+            sender: email.sender.as_deref().unwrap_or(""),
+            subject: email.subject.as_deref().unwrap_or(""),
+            body: email.body_text.as_deref().unwrap_or(""),
+            labels: &labels,
+        };
+
+        for (result, (_, _, _, matcher)) in results.iter_mut().zip(&candidates) {
+            if matcher.matches(&facts) {
+                result.matches.push(MatchedEmail {
+                    email_id: email.id,
+                    sender: email.sender.clone(),
+                    subject: email.subject.clone(),
+                    fetched_at: email.fetched_at,
+                    has_draft: email.has_draft,
+                });
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "evaluated": emails.len(),
+        "rules": results
+    })))
+}
@@ -0,0 +1,227 @@
+use actix_web::{get, web, HttpResponse};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use crate::db;
+use crate::middleware::Mailbox;
+use crate::services::search::{contains_pattern, Scope, SearchQuery};
+
+const STATUSES: [&str; 4] = ["draft", "pending_approval", "approved", "sent"];
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(search);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+/// Structured filters; each one overrides the same operator in `q`
+#[derive(Deserialize)]
+struct SearchParams {
+    /// Gmail-like query, e.g. `from:acme.com invoice after:2024/01/01`
+    q: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    after: Option<NaiveDate>,
+    before: Option<NaiveDate>,
+    label: Option<String>,
+    has_draft: Option<bool>,
+    status: Option<String>,
+    /// `all` (default), `emails` or `drafts`
+    #[serde(rename = "in")]
+    scope: Option<String>,
+    limit: Option<i64>,
+}
+
+impl SearchParams {
+    fn to_query(&self) -> Result<SearchQuery, String> {
+        let now = Utc::now().naive_utc();
+        let mut query = SearchQuery::parse(self.q.as_deref().unwrap_or(""), now)?;
+
+        if let Some(from) = &self.from {
+            query.from = Some(from.clone());
+        }
+        if let Some(to) = &self.to {
+            query.to = Some(to.clone());
+        }
+        if let Some(after) = self.after {
+            query.after = after.and_hms_opt(0, 0, 0);
+        }
+        if let Some(before) = self.before {
+            query.before = before.and_hms_opt(0, 0, 0);
+        }
+        if let Some(label) = &self.label {
+            query.labels.push(label.clone());
+        }
+        if let Some(has_draft) = self.has_draft {
+            query.has_draft = Some(has_draft);
+        }
+        if let Some(status) = &self.status {
+            query.status = Some(status.to_lowercase());
+        }
+        match self.scope.as_deref() {
+            None => {}
+            Some("all") => query.scope = Scope::All,
+            Some("emails") => query.scope = Scope::Emails,
+            Some("drafts") => query.scope = Scope::Drafts,
+            Some(_) => return Err("in must be all, emails or drafts".to_string()),
+        }
+
+        if query.status.as_deref().is_some_and(|s| !STATUSES.contains(&s)) {
+            return Err(format!("status must be one of {}", STATUSES.join(", ")));
+        }
+        Ok(query)
+    }
+}
+
+/// Full-text search over the mailbox's emails and drafts with Gmail-like operators.
+/// Results are ranked by relevance when there is free text, newest first otherwise.
+#[get("/search")]
+async fn search(params: web::Query<SearchParams>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let query = match params.to_query() {
+        Ok(q) => q,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let pool = db::get_pool();
+
+    let from = query.from.as_deref().map(contains_pattern);
+    let to = query.to.as_deref().map(contains_pattern);
+    let subject = query.subject.as_deref().map(contains_pattern);
+
+    let mut emails = Vec::new();
+    if query.scope != Scope::Drafts {
+        let rows = sqlx::query!(
+            r#"
+            SELECT e.id, e.gmail_id, e.thread_id, e.sender, e.subject, e.snippet, e.labels, e.category, e.fetched_at,
+                   EXISTS (SELECT 1 FROM drafts d WHERE d.email_id = e.id) AS "has_draft!",
+                   CASE WHEN $2::text IS NULL THEN e.snippet
+                        ELSE ts_headline('english', coalesce(e.body_text, ''), websearch_to_tsquery('english', $2),
+                                         'MaxFragments=1, MaxWords=30, MinWords=10') END AS headline,
+                   CASE WHEN $2::text IS NULL THEN 0
+                        ELSE ts_rank(e.search_vector, websearch_to_tsquery('english', $2)) END AS "rank!"
+            FROM emails e
+            WHERE e.account_id = $1
+              AND ($2::text IS NULL OR e.search_vector @@ websearch_to_tsquery('english', $2))
+              AND ($3::text IS NULL OR e.sender ILIKE $3)
+              AND ($4::text IS NULL OR e.to_recipients ILIKE $4)
+              AND ($5::text IS NULL OR e.subject ILIKE $5)
+              AND NOT EXISTS (SELECT 1 FROM unnest($6::text[]) want
+                              WHERE NOT EXISTS (SELECT 1 FROM unnest(e.labels) have WHERE lower(have) = lower(want)))
+              AND NOT EXISTS (SELECT 1 FROM unnest($7::text[]) unwanted
+                              WHERE EXISTS (SELECT 1 FROM unnest(e.labels) have WHERE lower(have) = lower(unwanted)))
+              AND ($8::timestamp IS NULL OR e.fetched_at >= $8)
+              AND ($9::timestamp IS NULL OR e.fetched_at < $9)
+              AND ($10::boolean IS NULL OR EXISTS (SELECT 1 FROM drafts d WHERE d.email_id = e.id) = $10)
+              AND ($11::text IS NULL OR EXISTS (SELECT 1 FROM drafts d
+                                                WHERE d.email_id = e.id AND COALESCE(d.status, 'draft') = $11))
+            ORDER BY "rank!" DESC, e.fetched_at DESC
+            LIMIT $12
+            "#,
+            mailbox.account_id,
+            query.text,
+            from,
+            to,
+            subject,
+            &query.labels[..],
+            &query.without_labels[..],
+            query.after,
+            query.before,
+            query.has_draft,
+            query.status,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(db_error)?;
+
+        emails = rows
+            .into_iter()
+            .map(|r| {
+                serde_json::json!({
+                    "id": r.id,
+                    "gmail_id": r.gmail_id,
+                    "thread_id": r.thread_id,
+                    "sender": r.sender,
+                    "subject": r.subject,
+                    "snippet": r.snippet,
+                    "headline": r.headline,
+                    "labels": r.labels,
+                    "category": r.category,
+                    "has_draft": r.has_draft,
+                    "fetched_at": r.fetched_at,
+                    "rank": r.rank
+                })
+            })
+            .collect();
+    }
+
+    // has:draft only narrows emails; every draft has itself
+    let mut drafts = Vec::new();
+    if query.scope != Scope::Emails && query.has_draft != Some(false) {
+        let rows = sqlx::query!(
+            r#"
+            SELECT d.id, d.email_id, COALESCE(d.status, 'draft') AS "status!", d.revision, d.created_at,
+                   e.sender, e.subject,
+                   CASE WHEN $2::text IS NULL THEN left(coalesce(d.content, ''), 200)
+                        ELSE ts_headline('english', coalesce(d.content, ''), websearch_to_tsquery('english', $2),
+                                         'MaxFragments=1, MaxWords=30, MinWords=10') END AS headline,
+                   CASE WHEN $2::text IS NULL THEN 0
+                        ELSE ts_rank(d.search_vector, websearch_to_tsquery('english', $2)) END AS "rank!"
+            FROM drafts d
+            LEFT JOIN emails e ON e.id = d.email_id
+            WHERE d.account_id = $1
+              AND ($2::text IS NULL OR d.search_vector @@ websearch_to_tsquery('english', $2))
+              AND ($3::text IS NULL OR e.sender ILIKE $3)
+              AND ($4::text IS NULL OR e.to_recipients ILIKE $4)
+              AND ($5::text IS NULL OR e.subject ILIKE $5)
+              AND NOT EXISTS (SELECT 1 FROM unnest($6::text[]) want
+                              WHERE NOT EXISTS (SELECT 1 FROM unnest(e.labels) have WHERE lower(have) = lower(want)))
+              AND NOT EXISTS (SELECT 1 FROM unnest($7::text[]) unwanted
+                              WHERE EXISTS (SELECT 1 FROM unnest(e.labels) have WHERE lower(have) = lower(unwanted)))
+              AND ($8::timestamp IS NULL OR d.created_at >= $8)
+              AND ($9::timestamp IS NULL OR d.created_at < $9)
+              AND ($10::text IS NULL OR COALESCE(d.status, 'draft') = $10)
+            ORDER BY "rank!" DESC, d.created_at DESC
+            LIMIT $11
+            "#,
+            mailbox.account_id,
+            query.text,
+            from,
+            to,
+            subject,
+            &query.labels[..],
+            &query.without_labels[..],
+            query.after,
+            query.before,
+            query.status,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(db_error)?;
+
+        drafts = rows
+            .into_iter()
+            .map(|r| {
+                serde_json::json!({
+                    "id": r.id,
+                    "email_id": r.email_id,
+                    "status": r.status,
+                    "revision": r.revision,
+                    "sender": r.sender,
+                    "subject": r.subject,
+                    "headline": r.headline,
+                    "created_at": r.created_at,
+                    "rank": r.rank
+                })
+            })
+            .collect();
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "emails": emails,
+        "drafts": drafts
+    })))
+}
@@ -0,0 +1,154 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use serde::Deserialize;
+use crate::db;
+use crate::db::style_profiles::ProfileInput;
+use crate::middleware::{AuthenticatedUser, Mailbox};
+use crate::services::{gmail_fetcher, style};
+
+/// Most sent messages read by one analysis
+const MAX_SAMPLE: usize = 200;
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_profile)
+       .service(update_profile)
+       .service(delete_profile)
+       .service(analyze_profile);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+fn blank_to_none(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|v| !v.is_empty())
+}
+
+#[derive(Deserialize)]
+struct ProfileRequest {
+    greeting: Option<String>,
+    sign_off: Option<String>,
+    typical_words: Option<i32>,
+    formality: Option<String>,
+    #[serde(default)]
+    common_phrases: Vec<String>,
+    notes: Option<String>,
+    enabled: Option<bool>,
+}
+
+impl ProfileRequest {
+    /// Checks the request and returns it as a db input, or a 400 message. Blank text
+    /// fields are stored as unset.
+    fn validate(&self) -> Result<ProfileInput<'_>, String> {
+        let formality = blank_to_none(self.formality.as_deref());
+        if formality.is_some_and(|f| !style::FORMALITIES.contains(&f)) {
+            return Err(format!("formality must be one of {}", style::FORMALITIES.join(", ")));
+        }
+        if self.typical_words.is_some_and(|w| !(1..=2000).contains(&w)) {
+            return Err("typical_words must be between 1 and 2000".to_string());
+        }
+        if self.common_phrases.len() > 20 {
+            return Err("at most 20 common_phrases".to_string());
+        }
+        if self.common_phrases.iter().any(|p| p.trim().is_empty() || p.chars().count() > 100) {
+            return Err("common_phrases must be non-empty and at most 100 characters".to_string());
+        }
+        if self.notes.as_deref().is_some_and(|n| n.chars().count() > 2000) {
+            return Err("notes must be at most 2000 characters".to_string());
+        }
+
+        Ok(ProfileInput {
+            greeting: blank_to_none(self.greeting.as_deref()),
+            sign_off: blank_to_none(self.sign_off.as_deref()),
+            typical_words: self.typical_words,
+            formality,
+            common_phrases: &self.common_phrases,
+            notes: blank_to_none(self.notes.as_deref()),
+            enabled: self.enabled.unwrap_or(true),
+        })
+    }
+}
+
+/// The user's writing style profile
+#[get("/style-profile")]
+async fn get_profile(user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    match db::style_profiles::get(user.user_id).await.map_err(db_error)? {
+        Some(profile) => Ok(HttpResponse::Ok().json(profile)),
+        None => Ok(HttpResponse::NotFound().body("No style profile yet; learn one with POST /style-profile/analyze")),
+    }
+}
+
+/// Replaces the profile with the user's edits
+#[put("/style-profile")]
+async fn update_profile(req: web::Json<ProfileRequest>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let input = match req.validate() {
+        Ok(input) => input,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    db::style_profiles::save(user.user_id, &input).await.map_err(db_error)?;
+
+    db::audit::NewEvent::by(&user, "style_profile.updated")
+        .target("user", user.user_id)
+        .metadata(serde_json::json!({ "enabled": input.enabled }))
+        .record()
+        .await;
+
+    let profile = db::style_profiles::get(user.user_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+#[delete("/style-profile")]
+async fn delete_profile(user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    if db::style_profiles::delete(user.user_id).await.map_err(db_error)? == 0 {
+        return Ok(HttpResponse::NotFound().body("No style profile"));
+    }
+
+    db::audit::NewEvent::by(&user, "style_profile.deleted")
+        .target("user", user.user_id)
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "deleted": true })))
+}
+
+#[derive(Deserialize)]
+struct AnalyzeRequest {
+    /// Sent messages to read, newest first (default `DEFAULT_SAMPLE`)
+    limit: Option<usize>,
+}
+
+/// Learns the profile from the selected mailbox's sent mail. Only the user's own mailboxes
+/// count: mail someone else sent says nothing about how this user writes.
+#[post("/style-profile/analyze")]
+async fn analyze_profile(req: Option<web::Json<AnalyzeRequest>>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let owned = db::mailbox_accounts::get_for_user(mailbox.user.user_id, mailbox.account_id)
+        .await
+        .map_err(db_error)?;
+    if owned.is_none() {
+        return Ok(HttpResponse::Forbidden().body("A style profile is learned from your own mailboxes only"));
+    }
+
+    let limit = req
+        .and_then(|r| r.limit)
+        .unwrap_or(style::DEFAULT_SAMPLE)
+        .clamp(style::MIN_SAMPLE, MAX_SAMPLE);
+    let bodies = gmail_fetcher::fetch_sent_bodies(&mailbox.email, limit).await?;
+
+    let analysis = match style::analyze(&bodies) {
+        Ok(a) => a,
+        Err(e) => return Ok(HttpResponse::UnprocessableEntity().body(e)),
+    };
+    db::style_profiles::save_analysis(mailbox.user.user_id, mailbox.account_id, &analysis)
+        .await
+        .map_err(db_error)?;
+
+    db::audit::NewEvent::by(&mailbox.user, "style_profile.analyzed")
+        .mailbox(mailbox.account_id)
+        .target("user", mailbox.user.user_id)
+        .metadata(serde_json::json!({ "messages_read": bodies.len(), "sample_count": analysis.sample_count }))
+        .record()
+        .await;
+
+    let profile = db::style_profiles::get(mailbox.user.user_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(profile))
+}
@@ -0,0 +1,134 @@
+use actix_web::{post, web, HttpResponse};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::db::workspaces::Role;
+use crate::middleware::Mailbox;
+use crate::services::summarizer::{self, Message, Summary};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(summarize_thread)
+       .service(summarize_email);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+#[derive(Deserialize)]
+struct SummaryQuery {
+    /// Regenerate even when the cached summary is still current
+    #[serde(default)]
+    refresh: bool,
+}
+
+#[derive(Serialize)]
+struct SummaryResponse {
+    scope: &'static str,
+    id: String,
+    message_count: i32,
+    #[serde(flatten)]
+    summary: Summary,
+    cached: bool,
+    generated_at: NaiveDateTime,
+}
+
+/// Summarizes the stored messages of a Gmail thread, oldest first
+#[post("/threads/{thread_id}/summary")]
+async fn summarize_thread(path: web::Path<String>, query: web::Query<SummaryQuery>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let thread_id = path.into_inner();
+
+    let rows = sqlx::query!(
+        "SELECT id, sender, subject, body_text FROM emails
+         WHERE account_id = $1 AND thread_id = $2
+         ORDER BY fetched_at, id",
+        mailbox.account_id,
+        thread_id
+    )
+    .fetch_all(db::get_pool())
+    .await
+    .map_err(db_error)?;
+
+    if rows.is_empty() {
+        return Ok(HttpResponse::NotFound().body("thread not found"));
+    }
+
+    let messages: Vec<Message> = rows
+        .into_iter()
+        .map(|r| Message {
+            id: r.id,
+            sender: r.sender.unwrap_or_default(),
+            subject: r.subject.unwrap_or_default(),
+            body: r.body_text.unwrap_or_default(),
+        })
+        .collect();
+
+    respond(&mailbox, "thread", thread_id, &messages, query.refresh).await
+}
+
+#[post("/emails/{id}/summary")]
+async fn summarize_email(path: web::Path<i32>, query: web::Query<SummaryQuery>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let email_id = path.into_inner();
+
+    let row = sqlx::query!(
+        "SELECT id, sender, subject, body_text FROM emails WHERE id = $1 AND account_id = $2",
+        email_id,
+        mailbox.account_id
+    )
+    .fetch_optional(db::get_pool())
+    .await
+    .map_err(db_error)?;
+
+    let message = match row {
+        Some(r) => Message {
+            id: r.id,
+            sender: r.sender.unwrap_or_default(),
+            subject: r.subject.unwrap_or_default(),
+            body: r.body_text.unwrap_or_default(),
+        },
+        None => return Ok(HttpResponse::NotFound().body("email not found")),
+    };
+
+    respond(&mailbox, "email", email_id.to_string(), &[message], query.refresh).await
+}
+
+/// Serves the cached summary while it matches the messages, otherwise generates and stores a new one
+async fn respond(mailbox: &Mailbox, scope: &'static str, target: String, messages: &[Message], refresh: bool) -> Result<HttpResponse, actix_web::Error> {
+    let source_hash = summarizer::source_hash(messages);
+
+    if !refresh {
+        let cached = db::summaries::get(mailbox.account_id, scope, &target).await.map_err(db_error)?;
+        if let Some(c) = cached.filter(|c| c.source_hash == source_hash) {
+            return Ok(HttpResponse::Ok().json(SummaryResponse {
+                scope,
+                id: target,
+                message_count: c.message_count,
+                summary: c.summary,
+                cached: true,
+                generated_at: c.created_at,
+            }));
+        }
+    }
+
+    let summary = match summarizer::summarize(messages).await {
+        Ok(s) => s,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("summary failed: {}", e))),
+    };
+
+    let message_count = messages.len() as i32;
+    let generated_at = db::summaries::put(mailbox.account_id, scope, &target, &source_hash, message_count, &summary)
+        .await
+        .map_err(db_error)?;
+
+    Ok(HttpResponse::Ok().json(SummaryResponse {
+        scope,
+        id: target,
+        message_count,
+        summary,
+        cached: false,
+        generated_at,
+    }))
+}
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::db::templates::{Template, TemplateInput};
+use crate::db::workspaces::Role;
+use crate::middleware::Mailbox;
+use crate::routes::drafts::{claim_conflict, claimed_by_other, set_content};
+use crate::services::guardrails;
+use crate::services::templates::{self, AdaptPrompt, Field, Rendered};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_templates)
+       .service(list_folders)
+       .service(create_template)
+       .service(get_template)
+       .service(update_template)
+       .service(delete_template)
+       .service(render_template)
+       .service(draft_from_template)
+       .service(insert_template);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+#[derive(Deserialize)]
+struct TemplateRequest {
+    /// Slash-separated folder path; empty or absent for the top level
+    folder: Option<String>,
+    name: String,
+    body: String,
+    #[serde(default)]
+    fields: Vec<Field>,
+}
+
+impl TemplateRequest {
+    /// Checks the request and returns it as a db input, or a 400 message
+    fn validate(&self) -> Result<TemplateInput<'_>, String> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err("name is required".to_string());
+        }
+        if self.body.trim().is_empty() {
+            return Err("body is required".to_string());
+        }
+        let folder = self.folder.as_deref().map(|f| f.trim().trim_matches('/')).filter(|f| !f.is_empty());
+        if folder.is_some_and(|f| f.split('/').any(|part| part.trim().is_empty())) {
+            return Err("folder must not contain empty path segments".to_string());
+        }
+        templates::validate(&self.body, &self.fields)?;
+
+        Ok(TemplateInput {
+            folder,
+            name,
+            body: &self.body,
+            fields: &self.fields,
+        })
+    }
+}
+
+/// A template with the variables its body uses
+#[derive(Serialize)]
+struct TemplateResponse {
+    #[serde(flatten)]
+    template: Template,
+    variables: Vec<String>,
+}
+
+impl From<Template> for TemplateResponse {
+    fn from(template: Template) -> TemplateResponse {
+        TemplateResponse {
+            variables: templates::variables(&template.body),
+            template,
+        }
+    }
+}
+
+fn duplicate_name() -> HttpResponse {
+    HttpResponse::Conflict().body("A template with this name already exists in the folder")
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    /// Folder and its subfolders; empty for top-level templates only
+    folder: Option<String>,
+    /// Text to find in the name or body
+    q: Option<String>,
+}
+
+#[get("/templates")]
+async fn list_templates(query: web::Query<ListQuery>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let folder = query.folder.as_deref().map(|f| f.trim().trim_matches('/'));
+    let q = query
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(crate::services::search::contains_pattern);
+
+    let list = db::templates::list(mailbox.account_id, folder, q.as_deref()).await.map_err(db_error)?;
+    let list: Vec<TemplateResponse> = list.into_iter().map(TemplateResponse::from).collect();
+    Ok(HttpResponse::Ok().json(list))
+}
+
+#[get("/templates/folders")]
+async fn list_folders(mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let folders = db::templates::folders(mailbox.account_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(folders))
+}
+
+#[get("/templates/{id}")]
+async fn get_template(path: web::Path<i32>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    match db::templates::get(mailbox.account_id, path.into_inner()).await.map_err(db_error)? {
+        Some(template) => Ok(HttpResponse::Ok().json(TemplateResponse::from(template))),
+        None => Ok(HttpResponse::NotFound().body("Template not found")),
+    }
+}
+
+#[post("/templates")]
+async fn create_template(req: web::Json<TemplateRequest>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let input = match req.validate() {
+        Ok(input) => input,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    let id = match db::templates::create(mailbox.account_id, &input, mailbox.user.user_id).await {
+        Ok(id) => id,
+        Err(e) if db::templates::is_duplicate_name(&e) => return Ok(duplicate_name()),
+        Err(e) => return Err(db_error(e)),
+    };
+
+    db::audit::NewEvent::by(&mailbox.user, "template.created")
+        .mailbox(mailbox.account_id)
+        .target("template", id)
+        .metadata(serde_json::json!({ "folder": input.folder, "name": input.name }))
+        .record()
+        .await;
+
+    let template = db::templates::get(mailbox.account_id, id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(template.map(TemplateResponse::from)))
+}
+
+#[put("/templates/{id}")]
+async fn update_template(path: web::Path<i32>, req: web::Json<TemplateRequest>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let template_id = path.into_inner();
+    let input = match req.validate() {
+        Ok(input) => input,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    let updated = match db::templates::update(mailbox.account_id, template_id, &input).await {
+        Ok(n) => n,
+        Err(e) if db::templates::is_duplicate_name(&e) => return Ok(duplicate_name()),
+        Err(e) => return Err(db_error(e)),
+    };
+    if updated == 0 {
+        return Ok(HttpResponse::NotFound().body("Template not found"));
+    }
+
+    db::audit::NewEvent::by(&mailbox.user, "template.updated")
+        .mailbox(mailbox.account_id)
+        .target("template", template_id)
+        .record()
+        .await;
+
+    let template = db::templates::get(mailbox.account_id, template_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(template.map(TemplateResponse::from)))
+}
+
+#[delete("/templates/{id}")]
+async fn delete_template(path: web::Path<i32>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let template_id = path.into_inner();
+
+    let deleted = db::templates::delete(mailbox.account_id, template_id).await.map_err(db_error)?;
+    if deleted == 0 {
+        return Ok(HttpResponse::NotFound().body("Template not found"));
+    }
+
+    db::audit::NewEvent::by(&mailbox.user, "template.deleted")
+        .mailbox(mailbox.account_id)
+        .target("template", template_id)
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "deleted": true
+    })))
+}
+
+/// The email a template is filled in for
+struct EmailContext {
+    sender: Option<String>,
+    subject: Option<String>,
+    body: Option<String>,
+}
+
+async fn email_context(account_id: i32, email_id: i32) -> Result<Option<EmailContext>, actix_web::Error> {
+    let row = sqlx::query_as!(
+        EmailContext,
+        "SELECT sender, subject, body_text AS body FROM emails WHERE id = $1 AND account_id = $2",
+        email_id,
+        account_id
+    )
+    .fetch_optional(db::get_pool())
+    .await
+    .map_err(db_error)?;
+
+    Ok(row)
+}
+
+/// Fills in a template for an email, if any
+fn fill(template: &Template, email: Option<&EmailContext>, mailbox: &Mailbox, given: &HashMap<String, String>) -> Rendered {
+    let builtins = templates::builtin_values(
+        email.and_then(|e| e.sender.as_deref()),
+        email.and_then(|e| e.subject.as_deref()),
+        &mailbox.email,
+        Utc::now().date_naive(),
+    );
+    templates::render(&template.body, &template.fields, &builtins, given)
+}
+
+fn missing_fields(missing: &[String]) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": "missing_fields",
+        "message": format!("No value for {}; pass them in fields or use adapt", missing.join(", ")),
+        "missing": missing
+    }))
+}
+
+#[derive(Deserialize)]
+struct RenderRequest {
+    /// Email whose sender and subject fill the built-in variables
+    email_id: Option<i32>,
+    /// Values of custom fields, overriding defaults
+    #[serde(default)]
+    fields: HashMap<String, String>,
+}
+
+/// Previews a filled-in template without creating anything
+#[post("/templates/{id}/render")]
+async fn render_template(path: web::Path<i32>, req: web::Json<RenderRequest>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    let template = match db::templates::get(mailbox.account_id, path.into_inner()).await.map_err(db_error)? {
+        Some(t) => t,
+        None => return Ok(HttpResponse::NotFound().body("Template not found")),
+    };
+    let email = match req.email_id {
+        Some(id) => match email_context(mailbox.account_id, id).await? {
+            Some(e) => Some(e),
+            None => return Ok(HttpResponse::NotFound().body("Email not found")),
+        },
+        None => None,
+    };
+
+    Ok(HttpResponse::Ok().json(fill(&template, email.as_ref(), &mailbox, &req.fields)))
+}
+
+#[derive(Deserialize)]
+struct FromTemplateRequest {
+    template_id: i32,
+    email_id: i32,
+    #[serde(default)]
+    fields: HashMap<String, String>,
+    /// Have the model tailor the filled-in template to the email
+    #[serde(default)]
+    adapt: bool,
+    /// Tone used when adapting (default "friendly")
+    tone: Option<String>,
+}
+
+/// Creates a draft reply to an email from a template, filled in as is or adapted by the model
+#[post("/drafts/from-template")]
+async fn draft_from_template(req: web::Json<FromTemplateRequest>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+
+    let template = match db::templates::get(mailbox.account_id, req.template_id).await.map_err(db_error)? {
+        Some(t) => t,
+        None => return Ok(HttpResponse::NotFound().body("Template not found")),
+    };
+    let email = match email_context(mailbox.account_id, req.email_id).await? {
+        Some(e) => e,
+        None => return Ok(HttpResponse::NotFound().body("Email not found")),
+    };
+
+    let rendered = fill(&template, Some(&email), &mailbox, &req.fields);
+    let tone = req.tone.as_deref().map(str::trim).filter(|t| !t.is_empty()).unwrap_or("friendly");
+
+    let content = if req.adapt {
+        let prompt = AdaptPrompt {
+            template: &rendered.content,
+            sender: email.sender.as_deref().unwrap_or(""),
+            subject: email.subject.as_deref().unwrap_or(""),
+            email_body: email.body.as_deref().unwrap_or(""),
+            tone,
+        };
+        match templates::adapt(&prompt).await {
+            Ok(c) => c,
+            Err(e) => return Ok(HttpResponse::InternalServerError().body(e)),
+        }
+    } else if !rendered.missing.is_empty() {
+        return Ok(missing_fields(&rendered.missing));
+    } else {
+        rendered.content
+    };
+
+    let warnings = guardrails::check(
+        &content,
+        &guardrails::Context {
+            email_body: email.body.as_deref().unwrap_or(""),
+            knowledge: &[],
+            instructions: None,
+        },
+    );
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO drafts (email_id, user_email, content, tone, account_id, created_by, template_id, warnings)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING id
+        "#,
+        req.email_id,
+        mailbox.email,
+        content,
+        req.adapt.then_some(tone),
+        mailbox.account_id,
+        mailbox.user.user_id,
+        template.id,
+        serde_json::json!(warnings)
+    )
+    .fetch_one(db::get_pool())
+    .await
+    .map_err(db_error)?;
+
+    db::audit::NewEvent::by(&mailbox.user, "draft.generated")
+        .mailbox(mailbox.account_id)
+        .target("draft", row.id)
+        .after(db::approvals::content_hash(&content))
+        .metadata(serde_json::json!({
+            "email_id": req.email_id,
+            "template_id": template.id,
+            "adapted": req.adapt
+        }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "draft_id": row.id,
+        "content": content,
+        "template_id": template.id,
+        "adapted": req.adapt,
+        "warnings": warnings
+    })))
+}
+
+#[derive(Deserialize)]
+struct InsertRequest {
+    template_id: i32,
+    #[serde(default)]
+    fields: HashMap<String, String>,
+    /// `replace` the content, or `append` (default) / `prepend` the template to it
+    mode: Option<String>,
+}
+
+/// Puts a filled-in template into an existing draft. Like any edit, a change starts a new
+/// revision and sends an approved draft back to review.
+#[post("/drafts/{id}/insert-template")]
+async fn insert_template(path: web::Path<i32>, req: web::Json<InsertRequest>, mailbox: Mailbox) -> Result<HttpResponse, actix_web::Error> {
+    mailbox.require(Role::Editor)?;
+    let draft_id = path.into_inner();
+
+    let mode = req.mode.as_deref().unwrap_or("append");
+    if !["replace", "append", "prepend"].contains(&mode) {
+        return Ok(HttpResponse::BadRequest().body("mode must be replace, append or prepend"));
+    }
+
+    let draft = sqlx::query!(
+        "SELECT email_id, content FROM drafts WHERE id = $1 AND account_id = $2",
+        draft_id,
+        mailbox.account_id
+    )
+    .fetch_optional(db::get_pool())
+    .await
+    .map_err(db_error)?;
+    let draft = match draft {
+        Some(d) => d,
+        None => return Ok(HttpResponse::NotFound().body("Draft not found")),
+    };
+
+    if let Some(email) = claimed_by_other(draft_id, mailbox.account_id, mailbox.user.user_id).await {
+        return Ok(claim_conflict(&email));
+    }
+
+    let template = match db::templates::get(mailbox.account_id, req.template_id).await.map_err(db_error)? {
+        Some(t) => t,
+        None => return Ok(HttpResponse::NotFound().body("Template not found")),
+    };
+    let email = match draft.email_id {
+        Some(id) => email_context(mailbox.account_id, id).await?,
+        None => None,
+    };
+
+    let rendered = fill(&template, email.as_ref(), &mailbox, &req.fields);
+    if !rendered.missing.is_empty() {
+        return Ok(missing_fields(&rendered.missing));
+    }
+
+    let current = draft.content.unwrap_or_default();
+    let content = match (mode, current.trim().is_empty()) {
+        ("replace", _) | (_, true) => rendered.content,
+        ("prepend", false) => format!("{}\n\n{}", rendered.content.trim_end(), current.trim_start()),
+        _ => format!("{}\n\n{}", current.trim_end(), rendered.content.trim_start()),
+    };
+
+    let (revision, previous_content, warnings) = match set_content(draft_id, &mailbox, &content).await {
+        Some(r) => r,
+        None => return Ok(HttpResponse::NotFound().body("Draft not found")),
+    };
+
+    db::audit::NewEvent::by(&mailbox.user, "draft.edited")
+        .mailbox(mailbox.account_id)
+        .target("draft", draft_id)
+        .before(db::approvals::content_hash(previous_content.as_deref().unwrap_or("")))
+        .after(db::approvals::content_hash(&content))
+        .metadata(serde_json::json!({ "revision": revision, "template_id": template.id, "mode": mode }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "updated": true,
+        "revision": revision,
+        "content": content,
+        "warnings": warnings
+    })))
+}
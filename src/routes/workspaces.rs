@@ -0,0 +1,303 @@
+use actix_web::{delete, get, patch, post, web, HttpResponse};
+use serde::Deserialize;
+use crate::db;
+use crate::db::workspaces::Role;
+use crate::middleware::AuthenticatedUser;
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_workspace)
+       .service(list_workspaces)
+       .service(list_members)
+       .service(update_member)
+       .service(remove_member)
+       .service(create_invitation)
+       .service(list_invitations)
+       .service(revoke_invitation)
+       .service(accept_invitation)
+       .service(share_mailbox)
+       .service(unshare_mailbox);
+}
+
+fn db_error(e: sqlx::Error) -> actix_web::Error {
+    log::error!("db error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("db error")
+}
+
+/// Looks up the user's role in the workspace: 404 for non-members, 403 below `required`
+pub(crate) async fn require_role(workspace_id: i32, user_id: i32, required: Role) -> Result<Role, actix_web::Error> {
+    match db::workspaces::member_role(workspace_id, user_id).await.map_err(db_error)? {
+        Some(role) if role >= required => Ok(role),
+        Some(_) => Err(actix_web::error::ErrorForbidden(format!(
+            "This action requires the {} role",
+            required.as_str()
+        ))),
+        None => Err(actix_web::error::ErrorNotFound("Workspace not found")),
+    }
+}
+
+fn parse_role(role: &str) -> Result<Role, actix_web::Error> {
+    Role::parse(role).ok_or_else(|| {
+        actix_web::error::ErrorBadRequest("role must be one of owner, editor, approver, viewer")
+    })
+}
+
+#[derive(Deserialize)]
+struct CreateWorkspace {
+    name: String,
+}
+
+#[post("/workspaces")]
+async fn create_workspace(user: AuthenticatedUser, req: web::Json<CreateWorkspace>) -> Result<HttpResponse, actix_web::Error> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("name is required"));
+    }
+
+    let id = db::workspaces::create(name, user.user_id).await.map_err(db_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": id,
+        "name": name,
+        "role": Role::Owner
+    })))
+}
+
+#[get("/workspaces")]
+async fn list_workspaces(user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspaces = db::workspaces::list_for_user(user.user_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(workspaces))
+}
+
+#[get("/workspaces/{id}/members")]
+async fn list_members(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Viewer).await?;
+
+    let members = db::workspaces::list_members(workspace_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(members))
+}
+
+#[derive(Deserialize)]
+struct MemberUpdate {
+    role: String,
+}
+
+#[patch("/workspaces/{id}/members/{user_id}")]
+async fn update_member(path: web::Path<(i32, i32)>, req: web::Json<MemberUpdate>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let (workspace_id, member_id) = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Owner).await?;
+    let role = parse_role(&req.role)?;
+
+    let current = db::workspaces::member_role(workspace_id, member_id).await.map_err(db_error)?;
+    let current = match current {
+        Some(r) => r,
+        None => return Ok(HttpResponse::NotFound().body("Member not found")),
+    };
+
+    // a workspace always keeps at least one owner
+    if current == Role::Owner && role != Role::Owner
+        && db::workspaces::owner_count(workspace_id).await.map_err(db_error)? <= 1
+    {
+        return Ok(HttpResponse::Conflict().body("A workspace needs at least one owner"));
+    }
+
+    db::workspaces::set_member_role(workspace_id, member_id, role).await.map_err(db_error)?;
+
+    db::audit::NewEvent::by(&user, "workspace.member_role_changed")
+        .target("workspace", workspace_id)
+        .metadata(serde_json::json!({ "user_id": member_id, "from": current, "to": role }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": member_id,
+        "role": role
+    })))
+}
+
+/// Removes a member; owners can remove anyone and members can remove themselves
+#[delete("/workspaces/{id}/members/{user_id}")]
+async fn remove_member(path: web::Path<(i32, i32)>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let (workspace_id, member_id) = path.into_inner();
+    let required = if member_id == user.user_id { Role::Viewer } else { Role::Owner };
+    require_role(workspace_id, user.user_id, required).await?;
+
+    let current = db::workspaces::member_role(workspace_id, member_id).await.map_err(db_error)?;
+    if current.is_none() {
+        return Ok(HttpResponse::NotFound().body("Member not found"));
+    }
+
+    if current == Some(Role::Owner) && db::workspaces::owner_count(workspace_id).await.map_err(db_error)? <= 1 {
+        return Ok(HttpResponse::Conflict().body("A workspace needs at least one owner"));
+    }
+
+    db::workspaces::remove_member(workspace_id, member_id).await.map_err(db_error)?;
+
+    db::audit::NewEvent::by(&user, "workspace.member_removed")
+        .target("workspace", workspace_id)
+        .metadata(serde_json::json!({ "user_id": member_id, "role": current }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "removed": true
+    })))
+}
+
+#[derive(Deserialize)]
+struct InvitationRequest {
+    email: String,
+    role: String,
+}
+
+/// Invites someone by email. The token is returned once and is only valid for
+/// a user whose login email matches the invitation.
+#[post("/workspaces/{id}/invitations")]
+async fn create_invitation(path: web::Path<i32>, req: web::Json<InvitationRequest>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Owner).await?;
+    let role = parse_role(&req.role)?;
+
+    let email = req.email.trim();
+    if !email.contains('@') {
+        return Ok(HttpResponse::BadRequest().body("A valid email is required"));
+    }
+
+    let (id, token) = db::workspaces::create_invitation(workspace_id, email, role, user.user_id)
+        .await
+        .map_err(db_error)?;
+
+    db::audit::NewEvent::by(&user, "workspace.invitation_created")
+        .target("workspace", workspace_id)
+        .metadata(serde_json::json!({ "invitation_id": id, "email": email, "role": role }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": id,
+        "email": email,
+        "role": role,
+        "token": token
+    })))
+}
+
+#[get("/workspaces/{id}/invitations")]
+async fn list_invitations(path: web::Path<i32>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Owner).await?;
+
+    let invitations = db::workspaces::list_invitations(workspace_id).await.map_err(db_error)?;
+    Ok(HttpResponse::Ok().json(invitations))
+}
+
+#[delete("/workspaces/{id}/invitations/{invitation_id}")]
+async fn revoke_invitation(path: web::Path<(i32, i32)>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let (workspace_id, invitation_id) = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Owner).await?;
+
+    let revoked = db::workspaces::revoke_invitation(workspace_id, invitation_id).await.map_err(db_error)?;
+    if revoked == 0 {
+        return Ok(HttpResponse::NotFound().body("Invitation not found"));
+    }
+
+    db::audit::NewEvent::by(&user, "workspace.invitation_revoked")
+        .target("workspace", workspace_id)
+        .metadata(serde_json::json!({ "invitation_id": invitation_id }))
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "revoked": true
+    })))
+}
+
+#[derive(Deserialize)]
+struct AcceptInvitation {
+    token: String,
+}
+
+#[post("/invitations/accept")]
+async fn accept_invitation(user: AuthenticatedUser, req: web::Json<AcceptInvitation>) -> Result<HttpResponse, actix_web::Error> {
+    let accepted = db::workspaces::accept_invitation(&req.token, user.user_id, &user.email)
+        .await
+        .map_err(db_error)?;
+
+    match accepted {
+        Some((workspace_id, role)) => {
+            db::audit::NewEvent::by(&user, "workspace.invitation_accepted")
+                .target("workspace", workspace_id)
+                .metadata(serde_json::json!({ "role": role }))
+                .record()
+                .await;
+
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "workspace_id": workspace_id,
+                "role": role
+            })))
+        }
+        None => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_invitation",
+            "message": "Invitation is invalid, expired, already used or addressed to another email"
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+struct ShareMailbox {
+    account_id: i32,
+}
+
+/// Shares one of the caller's own mailboxes with a workspace they own
+#[post("/workspaces/{id}/mailboxes")]
+async fn share_mailbox(path: web::Path<i32>, req: web::Json<ShareMailbox>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let workspace_id = path.into_inner();
+    require_role(workspace_id, user.user_id, Role::Owner).await?;
+
+    let shared = db::workspaces::share_mailbox(workspace_id, req.account_id, user.user_id)
+        .await
+        .map_err(db_error)?;
+    if shared == 0 {
+        return Ok(HttpResponse::NotFound().body("Mailbox account not found"));
+    }
+
+    db::audit::NewEvent::by(&user, "mailbox.shared")
+        .mailbox(req.account_id)
+        .target("workspace", workspace_id)
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "shared": true,
+        "account_id": req.account_id,
+        "workspace_id": workspace_id
+    })))
+}
+
+/// Stops sharing a mailbox; allowed for workspace owners and the mailbox's owner
+#[delete("/workspaces/{id}/mailboxes/{account_id}")]
+async fn unshare_mailbox(path: web::Path<(i32, i32)>, user: AuthenticatedUser) -> Result<HttpResponse, actix_web::Error> {
+    let (workspace_id, account_id) = path.into_inner();
+
+    let owns_mailbox = db::mailbox_accounts::get_for_user(user.user_id, account_id)
+        .await
+        .map_err(db_error)?
+        .is_some();
+    if !owns_mailbox {
+        require_role(workspace_id, user.user_id, Role::Owner).await?;
+    }
+
+    let unshared = db::workspaces::unshare_mailbox(workspace_id, account_id).await.map_err(db_error)?;
+    if unshared == 0 {
+        return Ok(HttpResponse::NotFound().body("Mailbox account not found"));
+    }
+
+    db::audit::NewEvent::by(&user, "mailbox.unshared")
+        .mailbox(account_id)
+        .target("workspace", workspace_id)
+        .record()
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "unshared": true
+    })))
+}
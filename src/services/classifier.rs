@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::services::groq_ai;
+
+pub const CATEGORIES: [&str; 6] = ["support", "sales", "billing", "personal", "newsletter", "spam"];
+
+/// Characters of the body sent to the model; enough to judge intent
+const BODY_CHARS: usize = 4000;
+
+#[derive(Serialize, Deserialize)]
+pub struct Classification {
+    /// One of `CATEGORIES`
+    pub category: String,
+    /// 0 (can wait indefinitely) to 100 (needs attention now)
+    pub urgency: i32,
+    pub needs_reply: bool,
+}
+
+/// Asks the model for the email's category, urgency and whether it needs a reply
+pub async fn classify(sender: &str, subject: &str, body: &str) -> Result<Classification, String> {
+    let body: String = body.chars().take(BODY_CHARS).collect();
+    let prompt = format!(
+        r#"Classify this email.
+
+From: {}
+Subject: {}
+Body: {}
+
+Answer with a JSON object only, no other text:
+{{"category": one of "support", "sales", "billing", "personal", "newsletter", "spam",
+ "urgency": integer from 0 (can wait) to 100 (needs attention right now),
+ "needs_reply": true if the sender expects an answer, otherwise false}}
+
+Use "spam" for unsolicited or suspicious mail and "newsletter" for bulk mail and notifications."#,
+        sender,
+        if subject.is_empty() { "No subject" } else { subject },
+        body
+    );
+
+    let mut c: Classification =
+        groq_ai::complete_json("You triage incoming email. You answer with JSON only.", &prompt, 100, 0.0).await?;
+
+    c.category = c.category.trim().to_lowercase();
+    if c.category == "spam-ish" {
+        c.category = "spam".to_string();
+    }
+    if !CATEGORIES.contains(&c.category.as_str()) {
+        return Err(format!("unknown category {}", c.category));
+    }
+    c.urgency = c.urgency.clamp(0, 100);
+    Ok(c)
+}
+
+/// Classifies a stored email and saves the result on it
+pub async fn classify_email(account_id: i32, email_id: i32) -> Result<Option<Classification>, String> {
+    let pool = db::get_pool();
+
+    let email = sqlx::query!(
+        "SELECT sender, subject, body_text FROM emails WHERE id = $1 AND account_id = $2",
+        email_id,
+        account_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    let email = match email {
+        Some(e) => e,
+        None => return Ok(None),
+    };
+
+    let c = classify(
+        email.sender.as_deref().unwrap_or(""),
+        email.subject.as_deref().unwrap_or(""),
+        email.body_text.as_deref().unwrap_or(""),
+    )
+    .await?;
+
+    sqlx::query!(
+        "UPDATE emails SET category = $1, urgency = $2, needs_reply = $3, classified_at = NOW() WHERE id = $4",
+        c.category,
+        c.urgency,
+        c.needs_reply,
+        email_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("db update error: {:?}", e))?;
+
+    Ok(Some(c))
+}
@@ -0,0 +1,221 @@
+use crate::config;
+use crate::db;
+use crate::services::{embeddings, guardrails, knowledge, style};
+use crate::services::groq_ai::{self, ReplyPrompt, Usage};
+use serde::Serialize;
+
+/// Characters of each example reply put in the prompt
+const EXAMPLE_CHARS: usize = 1500;
+
+/// How a draft should be written
+pub struct DraftOptions<'a> {
+    pub tone: &'a str,
+    pub instructions: Option<&'a str>,
+    /// User who asked for the draft; `None` for drafts written by automation
+    pub created_by: Option<i32>,
+    /// Rule that triggered an automatic draft
+    pub rule_id: Option<i32>,
+    /// Whether sent replies to similar emails are added to the prompt as examples
+    pub use_examples: bool,
+    /// Whether excerpts from the workspace knowledge base are added to the prompt
+    pub use_knowledge: bool,
+    /// Whether the writing style profile of `created_by` (or the mailbox owner) is followed
+    pub use_style: bool,
+}
+
+pub struct GeneratedDraft {
+    pub id: i32,
+    pub content: String,
+    /// Sent drafts used as examples
+    pub example_draft_ids: Vec<i32>,
+    /// Knowledge base chunks the draft was grounded on, as stored in `draft_sources`
+    pub sources: Vec<knowledge::RetrievedChunk>,
+    /// Whether the prompt described a writing style profile
+    pub style_applied: bool,
+    pub generation: Generation,
+    /// Guardrail findings on the stored reply
+    pub warnings: Vec<guardrails::Warning>,
+    /// Replies generated, including retries after high-severity findings
+    pub attempts: u32,
+}
+
+/// The prompt version and model settings a draft was written with, as stored on the draft
+#[derive(Serialize)]
+pub struct Generation {
+    pub prompt_id: i32,
+    pub prompt_name: String,
+    pub prompt_version: i32,
+    pub model: String,
+    pub temperature: f64,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+/// Generates a reply to one of the mailbox's emails and stores it as a draft.
+/// Returns `Ok(None)` when the email doesn't belong to the mailbox.
+pub async fn generate_for_email(account_id: i32, email_id: i32, options: &DraftOptions<'_>) -> Result<Option<GeneratedDraft>, String> {
+    let pool = db::get_pool();
+
+    let email = sqlx::query!(
+        r#"
+        SELECT e.body_text, e.sender, e.subject, a.email AS mailbox_email, a.workspace_id
+        FROM emails e
+        JOIN mailbox_accounts a ON a.id = e.account_id
+        WHERE e.id = $1 AND e.account_id = $2
+        "#,
+        email_id,
+        account_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    let email = match email {
+        Some(e) => e,
+        None => return Ok(None),
+    };
+
+    let body = email.body_text.as_deref().unwrap_or("");
+    let subject = email.subject.as_deref().unwrap_or("");
+
+    // examples only improve the draft, so a failed lookup doesn't stop generation
+    let similar = if options.use_examples {
+        let text = embeddings::email_text(subject, body);
+        embeddings::similar_replies(account_id, &text, Some(email_id), config::similar_reply_examples(), embeddings::MIN_SIMILAR_SCORE)
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("similar replies for email {} failed: {}", email_id, e);
+                Vec::new()
+            })
+    } else {
+        Vec::new()
+    };
+    let examples: Vec<String> = similar.iter().map(|s| s.reply.chars().take(EXAMPLE_CHARS).collect()).collect();
+
+    // only mailboxes shared into a workspace have a knowledge base
+    let sources = match email.workspace_id.filter(|_| options.use_knowledge) {
+        Some(workspace_id) => {
+            let text = embeddings::email_text(subject, body);
+            knowledge::retrieve(workspace_id, &text, config::knowledge_chunks(), knowledge::MIN_CHUNK_SCORE)
+                .await
+                .unwrap_or_else(|e| {
+                    log::warn!("knowledge lookup for email {} failed: {}", email_id, e);
+                    Vec::new()
+                })
+        }
+        None => Vec::new(),
+    };
+    let excerpts: Vec<String> = sources.iter().map(knowledge::prompt_excerpt).collect();
+
+    let signature = db::signatures::active(account_id)
+        .await
+        .map_err(|e| format!("db fetch error: {:?}", e))?
+        .is_some();
+
+    let profile = if options.use_style {
+        db::style_profiles::for_generation(options.created_by, account_id)
+            .await
+            .map_err(|e| format!("db fetch error: {:?}", e))?
+    } else {
+        None
+    };
+    let style_guide = profile
+        .as_ref()
+        .map(|p| style::prompt_section(p, !signature))
+        .filter(|s| !s.is_empty());
+
+    let template = db::prompts::resolve(email.workspace_id, "reply")
+        .await
+        .map_err(|e| format!("db fetch error: {:?}", e))?
+        .ok_or_else(|| "no reply prompt configured".to_string())?;
+
+    let prompt = ReplyPrompt {
+        email_body: body,
+        sender: email.sender.as_deref().unwrap_or(""),
+        subject,
+        tone: options.tone,
+        instructions: options.instructions,
+        examples: &examples,
+        knowledge: &excerpts,
+        style: style_guide.as_deref(),
+        signature,
+    };
+    let context = guardrails::Context {
+        email_body: body,
+        knowledge: &excerpts,
+        instructions: options.instructions,
+    };
+
+    let mut reply = groq_ai::generate_reply(&template, &prompt).await?;
+    let mut warnings = guardrails::check(&reply.content, &context);
+    let mut usage = std::mem::take(&mut reply.usage);
+    let mut attempts = 1;
+
+    // retry with what to avoid, keeping whichever reply has the fewest high-severity findings
+    while guardrails::high_count(&warnings) > 0 && attempts <= config::guardrail_retries() {
+        let instructions = guardrails::retry_instructions(options.instructions, &warnings);
+        let retry = match groq_ai::generate_reply(&template, &ReplyPrompt { instructions: Some(&instructions), ..prompt }).await {
+            Ok(r) => r,
+            Err(e) => {
+                log::warn!("guardrail retry for email {} failed: {}", email_id, e);
+                break;
+            }
+        };
+        attempts += 1;
+        usage.add(&retry.usage);
+
+        let retry_warnings = guardrails::check(&retry.content, &context);
+        if guardrails::high_count(&retry_warnings) < guardrails::high_count(&warnings) {
+            reply = retry;
+            warnings = retry_warnings;
+        }
+    }
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO drafts
+            (email_id, user_email, content, tone, account_id, created_by, rule_id,
+             prompt_id, model, temperature, prompt_tokens, completion_tokens, warnings)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+        RETURNING id
+        "#,
+        email_id,
+        email.mailbox_email,
+        reply.content,
+        options.tone,
+        account_id,
+        options.created_by,
+        options.rule_id,
+        template.id,
+        template.model,
+        template.temperature,
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        serde_json::json!(warnings)
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("db insert error: {:?}", e))?;
+
+    if let Err(e) = db::knowledge::save_sources(row.id, &sources).await {
+        log::error!("saving sources of draft {} failed: {:?}", row.id, e);
+    }
+
+    Ok(Some(GeneratedDraft {
+        id: row.id,
+        content: reply.content,
+        example_draft_ids: similar.iter().map(|s| s.draft_id).collect(),
+        sources,
+        style_applied: style_guide.is_some(),
+        generation: Generation {
+            prompt_id: template.id,
+            prompt_name: template.name,
+            prompt_version: template.version,
+            model: template.model,
+            temperature: template.temperature,
+            usage,
+        },
+        warnings,
+        attempts,
+    }))
+}
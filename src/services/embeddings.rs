@@ -0,0 +1,283 @@
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::json;
+use crate::config;
+use crate::db;
+use crate::db::approvals::content_hash;
+
+/// Dimensions of the local hashing embedding
+const LOCAL_DIMS: usize = 384;
+/// Characters of an email or draft that get embedded
+const EMBED_CHARS: usize = 8000;
+/// Most recent vectors compared per search; the scan is brute force
+const SCAN_LIMIT: i64 = 5000;
+/// How close a past email must be for its reply to count as similar
+pub const MIN_SIMILAR_SCORE: f32 = 0.3;
+
+const STOP_WORDS: [&str; 32] = [
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "has", "have", "i", "in", "is",
+    "it", "me", "my", "of", "on", "or", "our", "so", "that", "the", "this", "to", "we", "with", "you", "your",
+];
+
+/// Where embeddings come from, chosen with `EMBEDDING_PROVIDER`
+pub enum Provider {
+    /// Feature-hashed bag of words and word pairs, computed in process. No API key
+    /// needed; good at near-identical wording, weaker at paraphrases.
+    Local,
+    /// OpenAI's embeddings API
+    OpenAi { api_key: String, model: String },
+}
+
+impl Provider {
+    pub fn from_env() -> Result<Provider, String> {
+        match config::embedding_provider().as_str() {
+            "local" => Ok(Provider::Local),
+            "openai" => Ok(Provider::OpenAi {
+                api_key: std::env::var("OPENAI_API_KEY").map_err(|_| "Missing OPENAI_API_KEY".to_string())?,
+                model: config::embedding_model(),
+            }),
+            other => Err(format!("unknown EMBEDDING_PROVIDER {}", other)),
+        }
+    }
+
+    /// Identifies the vector space; vectors of different models are never compared
+    pub fn model(&self) -> String {
+        match self {
+            // bump the suffix whenever local_embedding changes so old vectors get reindexed
+            Provider::Local => format!("local-hash-{}-v2", LOCAL_DIMS),
+            Provider::OpenAi { model, .. } => format!("openai/{}", model),
+        }
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        let text: String = text.chars().take(EMBED_CHARS).collect();
+        match self {
+            Provider::Local => Ok(local_embedding(&text)),
+            Provider::OpenAi { api_key, model } => openai_embedding(api_key, model, &text).await,
+        }
+    }
+}
+
+/// 32-bit FNV-1a, stable across builds unlike the std hasher
+fn fnv1a(s: &str) -> u32 {
+    s.bytes().fold(0x811c9dc5, |h, b| (h ^ b as u32).wrapping_mul(0x01000193))
+}
+
+/// Crude English stemming so `refund`, `refunds` and `refunded` share a feature
+fn stem(word: &str) -> &str {
+    ["ing", "ies", "ed", "es", "s"]
+        .iter()
+        .filter_map(|suffix| word.strip_suffix(suffix))
+        .find(|stem| stem.len() >= 3 && !stem.ends_with('s'))
+        .unwrap_or(word)
+}
+
+fn local_embedding(text: &str) -> Vec<f32> {
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 1 && !STOP_WORDS.contains(w))
+        .map(stem)
+        .collect();
+
+    let mut vector = vec![0f32; LOCAL_DIMS];
+    let mut add = |feature: &str, weight: f32| {
+        let h = fnv1a(feature);
+        // the top bit picks the sign so collisions cancel out instead of piling up
+        let sign = if h & 0x8000_0000 == 0 { 1.0 } else { -1.0 };
+        vector[(h as usize) % LOCAL_DIMS] += sign * weight;
+    };
+    for w in &words {
+        add(w, 1.0);
+    }
+    for pair in words.windows(2) {
+        add(&format!("{} {}", pair[0], pair[1]), 0.5);
+    }
+
+    // dampen repeated words, then normalize so a dot product is the cosine
+    for v in vector.iter_mut() {
+        *v = v.signum() * (1.0 + v.abs()).ln();
+    }
+    normalize(&mut vector);
+    vector
+}
+
+async fn openai_embedding(api_key: &str, model: &str, text: &str) -> Result<Vec<f32>, String> {
+    let resp = Client::new()
+        .post("https://api.openai.com/v1/embeddings")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&json!({ "model": model, "input": text }))
+        .send()
+        .await
+        .map_err(|e| format!("Request error: {:?}", e))?;
+
+    let status = resp.status();
+    let text = resp.text().await.map_err(|e| format!("Read error: {:?}", e))?;
+    if !status.is_success() {
+        return Err(format!("embedding api error {}: {}", status, text));
+    }
+
+    let json_resp: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("JSON error: {:?}", e))?;
+    let mut vector: Vec<f32> = json_resp["data"][0]["embedding"]
+        .as_array()
+        .ok_or_else(|| "embedding missing in response".to_string())?
+        .iter()
+        .filter_map(|v| v.as_f64().map(|f| f as f32))
+        .collect();
+    normalize(&mut vector);
+    Ok(vector)
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+/// Cosine similarity of two normalized vectors
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Text embedded for an email
+pub fn email_text(subject: &str, body: &str) -> String {
+    format!("{}\n\n{}", subject, body)
+}
+
+/// Embeds an email or draft and stores the vector. Unchanged content isn't embedded again.
+pub async fn index(provider: &Provider, account_id: i32, source_type: &str, source_id: i32, text: &str) -> Result<(), String> {
+    let model = provider.model();
+    let hash = content_hash(text);
+
+    let current = db::embeddings::content_hash_of(source_type, source_id, &model)
+        .await
+        .map_err(|e| format!("db fetch error: {:?}", e))?;
+    if current.as_deref() == Some(hash.as_str()) {
+        return Ok(());
+    }
+
+    let vector = provider.embed(text).await?;
+    db::embeddings::upsert(account_id, source_type, source_id, &model, &hash, &vector)
+        .await
+        .map_err(|e| format!("db insert error: {:?}", e))
+}
+
+pub async fn index_email(account_id: i32, email_id: i32) -> Result<(), String> {
+    let email = sqlx::query!(
+        "SELECT subject, body_text FROM emails WHERE id = $1 AND account_id = $2",
+        email_id,
+        account_id
+    )
+    .fetch_optional(db::get_pool())
+    .await
+    .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    match email {
+        Some(e) => {
+            let text = email_text(e.subject.as_deref().unwrap_or(""), e.body_text.as_deref().unwrap_or(""));
+            index(&Provider::from_env()?, account_id, "email", email_id, &text).await
+        }
+        None => Ok(()),
+    }
+}
+
+pub async fn index_draft(account_id: i32, draft_id: i32, content: &str) -> Result<(), String> {
+    index(&Provider::from_env()?, account_id, "draft", draft_id, content).await
+}
+
+/// A sent reply to an email similar to the one being answered
+#[derive(Serialize)]
+pub struct SimilarReply {
+    pub email_id: i32,
+    pub subject: Option<String>,
+    pub sender: Option<String>,
+    pub draft_id: i32,
+    pub reply: String,
+    pub score: f32,
+}
+
+/// Sent replies whose source email is closest to `text`, best first. `exclude_email_id`
+/// keeps the email being answered out of its own examples.
+pub async fn similar_replies(account_id: i32, text: &str, exclude_email_id: Option<i32>, limit: usize, min_score: f32) -> Result<Vec<SimilarReply>, String> {
+    let provider = Provider::from_env()?;
+    let query = provider.embed(text).await?;
+
+    let candidates = sqlx::query!(
+        r#"
+        SELECT e.id AS email_id, e.subject, e.sender, d.id AS "draft_id!", d.content AS "reply!", v.vector
+        FROM embeddings v
+        JOIN emails e ON e.id = v.source_id
+        JOIN LATERAL (
+            SELECT id, content FROM drafts
+            WHERE email_id = e.id AND status = 'sent' AND content IS NOT NULL
+            ORDER BY updated_at DESC NULLS LAST, id DESC
+            LIMIT 1
+        ) d ON TRUE
+        WHERE v.account_id = $1 AND v.source_type = 'email' AND v.model = $2
+          AND ($3::int IS NULL OR e.id <> $3)
+        ORDER BY v.id DESC
+        LIMIT $4
+        "#,
+        account_id,
+        provider.model(),
+        exclude_email_id,
+        SCAN_LIMIT
+    )
+    .fetch_all(db::get_pool())
+    .await
+    .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    let mut matches: Vec<SimilarReply> = candidates
+        .into_iter()
+        .map(|c| SimilarReply {
+            score: similarity(&query, &c.vector),
+            email_id: c.email_id,
+            subject: c.subject,
+            sender: c.sender,
+            draft_id: c.draft_id,
+            reply: c.reply,
+        })
+        .filter(|m| m.score >= min_score)
+        .collect();
+
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    matches.truncate(limit);
+    Ok(matches)
+}
+
+/// An email or draft close to a free-text query
+#[derive(Serialize)]
+pub struct SemanticMatch {
+    pub source_type: String,
+    pub id: i32,
+    pub score: f32,
+}
+
+/// Emails and/or drafts of the mailbox ranked by closeness to `text`
+pub async fn semantic_search(account_id: i32, text: &str, source_type: Option<&str>, limit: usize) -> Result<Vec<SemanticMatch>, String> {
+    let provider = Provider::from_env()?;
+    let query = provider.embed(text).await?;
+
+    let candidates = db::embeddings::scan(account_id, &provider.model(), source_type, SCAN_LIMIT)
+        .await
+        .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    let mut matches: Vec<SemanticMatch> = candidates
+        .into_iter()
+        .map(|(source_type, id, vector)| SemanticMatch {
+            score: similarity(&query, &vector),
+            source_type,
+            id,
+        })
+        .filter(|m| m.score > 0.0)
+        .collect();
+
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    matches.truncate(limit);
+    Ok(matches)
+}
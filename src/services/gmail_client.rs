@@ -0,0 +1,41 @@
+use once_cell::sync::Lazy;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use crate::services::google_oauth::{self, GoogleError};
+
+pub const GMAIL_API: &str = "https://gmail.googleapis.com/gmail/v1/users/me";
+
+static CLIENT: Lazy<Client> = Lazy::new(Client::new);
+
+/// Sends a Gmail API request authorized with the user's cached access token and
+/// returns the status and body. If Gmail answers 401 the cached token is dropped
+/// and the request is retried once with a freshly refreshed one.
+pub async fn send<F>(user_email: &str, build: F) -> Result<(StatusCode, String), GoogleError>
+where
+    F: Fn(&Client) -> RequestBuilder,
+{
+    let (status, text) = send_once(user_email, &build).await?;
+    if status != StatusCode::UNAUTHORIZED {
+        return Ok((status, text));
+    }
+
+    log::warn!("Gmail rejected cached access token for {}, refreshing", user_email);
+    google_oauth::invalidate_access_token(user_email);
+    send_once(user_email, &build).await
+}
+
+async fn send_once<F>(user_email: &str, build: &F) -> Result<(StatusCode, String), GoogleError>
+where
+    F: Fn(&Client) -> RequestBuilder,
+{
+    let access_token = google_oauth::access_token_for_user(user_email).await?;
+
+    let resp = build(&CLIENT)
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .map_err(|e| format!("http error: {:?}", e))?;
+
+    let status = resp.status();
+    let text = resp.text().await.map_err(|e| format!("text err: {:?}", e))?;
+    Ok((status, text))
+}
@@ -1,27 +1,23 @@
 use crate::db;
-use reqwest::Client;
 use serde_json::Value;
-use crate::services::google_oauth;
+use crate::services::gmail_client;
+use crate::services::google_oauth::GoogleError;
 use sqlx::Row;
 use chrono::Utc;
 
-pub async fn fetch_and_store_message(user_email: &str, gmail_id: &str) -> Result<(), String> {
-    let access_token = google_oauth::refresh_access_token_for_user(user_email).await?;
-    let client = Client::new();
+/// Row written by `fetch_and_store_message`
+pub struct StoredEmail {
+    pub id: i32,
+    /// false when the message was already stored and only got refreshed
+    pub inserted: bool,
+}
 
+pub async fn fetch_and_store_message(account_id: i32, user_email: &str, gmail_id: &str) -> Result<StoredEmail, GoogleError> {
     // fetch full message
-    let url = format!("https://gmail.googleapis.com/gmail/v1/users/me/messages/{}?format=full", gmail_id);
-    let resp = client
-        .get(&url)
-        .bearer_auth(access_token)
-        .send()
-        .await
-        .map_err(|e| format!("http error: {:?}", e))?;
-
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("text err: {:?}", e))?;
+    let url = format!("{}/messages/{}?format=full", gmail_client::GMAIL_API, gmail_id);
+    let (status, text) = gmail_client::send(user_email, |c| c.get(&url)).await?;
     if !status.is_success() {
-        return Err(format!("gmail api error {} : {}", status, text));
+        return Err(format!("gmail api error {} : {}", status, text).into());
     }
 
     let json: Value = serde_json::from_str(&text).map_err(|e| format!("json parse: {:?}", e))?;
@@ -51,28 +47,6 @@ pub async fn fetch_and_store_message(user_email: &str, gmail_id: &str) -> Result
     let mut body_text: Option<String> = None;
     let mut body_html: Option<String> = None;
 
-    fn extract_parts(part: &Value, bt: &mut Option<String>, bh: &mut Option<String>) {
-        if let Some(mime) = part["mimeType"].as_str() {
-            if mime == "text/plain" {
-                if let Some(data) = part["body"]["data"].as_str() {
-                    let decoded = base64_engine_decode(data);
-                    *bt = Some(decoded);
-                }
-            } else if mime == "text/html" {
-                if let Some(data) = part["body"]["data"].as_str() {
-                    let decoded = base64_engine_decode(data);
-                    *bh = Some(decoded);
-                }
-            } else if mime.starts_with("multipart/") {
-                if let Some(parts) = part["parts"].as_array() {
-                    for p in parts {
-                        extract_parts(p, bt, bh);
-                    }
-                }
-            }
-        }
-    }
-
     extract_parts(&json["payload"], &mut body_text, &mut body_html);
 
     // If no plain text but have html, convert
@@ -87,11 +61,11 @@ pub async fn fetch_and_store_message(user_email: &str, gmail_id: &str) -> Result
 
     // upsert into emails table
     let pool = db::get_pool();
-    sqlx::query!(
+    let row = sqlx::query!(
         r#"
-        INSERT INTO emails (gmail_id, thread_id, user_email, sender, to_recipients, subject, snippet, body_text, body_html, labels, fetched_at)
-        VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11)
-        ON CONFLICT (gmail_id) DO UPDATE SET
+        INSERT INTO emails (gmail_id, thread_id, user_email, sender, to_recipients, subject, snippet, body_text, body_html, labels, fetched_at, account_id)
+        VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12)
+        ON CONFLICT (account_id, gmail_id) DO UPDATE SET
           thread_id = EXCLUDED.thread_id,
           sender = EXCLUDED.sender,
           subject = EXCLUDED.subject,
@@ -100,6 +74,7 @@ pub async fn fetch_and_store_message(user_email: &str, gmail_id: &str) -> Result
           body_html = EXCLUDED.body_html,
           labels = EXCLUDED.labels,
           fetched_at = EXCLUDED.fetched_at
+        RETURNING id, (xmax = 0) AS "inserted!"
         "#,
         gmail_id,
         thread_id,
@@ -111,13 +86,78 @@ pub async fn fetch_and_store_message(user_email: &str, gmail_id: &str) -> Result
         body_text,
         body_html,
         &labels[..],
-        Utc::now().naive_utc()
+        Utc::now().naive_utc(),
+        account_id
     )
-    .execute(pool)
+    .fetch_one(pool)
     .await
     .map_err(|e| format!("db insert error: {:?}", e))?;
 
-    Ok(())
+    // a new message makes the thread's cached summary stale
+    if let (true, Some(thread_id)) = (row.inserted, thread_id.as_deref()) {
+        db::summaries::invalidate_thread(account_id, thread_id)
+            .await
+            .map_err(|e| format!("db delete error: {:?}", e))?;
+    }
+
+    Ok(StoredEmail { id: row.id, inserted: row.inserted })
+}
+
+fn extract_parts(part: &Value, bt: &mut Option<String>, bh: &mut Option<String>) {
+    if let Some(mime) = part["mimeType"].as_str() {
+        if mime == "text/plain" {
+            if let Some(data) = part["body"]["data"].as_str() {
+                let decoded = base64_engine_decode(data);
+                *bt = Some(decoded);
+            }
+        } else if mime == "text/html" {
+            if let Some(data) = part["body"]["data"].as_str() {
+                let decoded = base64_engine_decode(data);
+                *bh = Some(decoded);
+            }
+        } else if mime.starts_with("multipart/") {
+            if let Some(parts) = part["parts"].as_array() {
+                for p in parts {
+                    extract_parts(p, bt, bh);
+                }
+            }
+        }
+    }
+}
+
+/// Plain-text bodies of the mailbox's most recent sent messages, newest first. They are
+/// only read, not stored.
+pub async fn fetch_sent_bodies(user_email: &str, limit: usize) -> Result<Vec<String>, GoogleError> {
+    let list_url = format!("{}/messages?labelIds=SENT&maxResults={}", gmail_client::GMAIL_API, limit);
+    let (status, text) = gmail_client::send(user_email, |c| c.get(&list_url)).await?;
+    if !status.is_success() {
+        return Err(format!("gmail list err {} : {}", status, text).into());
+    }
+
+    let json: Value = serde_json::from_str(&text).map_err(|e| format!("json parse: {:?}", e))?;
+    let ids: Vec<String> = json["messages"]
+        .as_array()
+        .map(|arr| arr.iter().filter_map(|m| m["id"].as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default();
+
+    let mut bodies = Vec::new();
+    for id in ids.iter().take(limit) {
+        let url = format!("{}/messages/{}?format=full", gmail_client::GMAIL_API, id);
+        let (status, text) = gmail_client::send(user_email, |c| c.get(&url)).await?;
+        if !status.is_success() {
+            log::warn!("gmail get {} failed: {} {}", id, status, text);
+            continue;
+        }
+        let json: Value = serde_json::from_str(&text).map_err(|e| format!("json parse: {:?}", e))?;
+
+        let mut body_text = None;
+        let mut body_html = None;
+        extract_parts(&json["payload"], &mut body_text, &mut body_html);
+        if let Some(body) = body_text.or_else(|| body_html.as_deref().map(html_to_text)) {
+            bodies.push(body);
+        }
+    }
+    Ok(bodies)
 }
 
 fn base64_engine_decode(s: &str) -> String {
@@ -130,7 +170,7 @@ fn base64_engine_decode(s: &str) -> String {
     String::from_utf8(bytes).unwrap_or_default()
 }
 
-fn html_to_text(html: &str) -> String {
+pub fn html_to_text(html: &str) -> String {
     // sanitize and convert to plain text
     let cleaned = ammonia::Builder::new().clean(html).to_string();
     html2text::from_read(cleaned.as_bytes(), 1024)
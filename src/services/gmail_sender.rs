@@ -1,55 +1,58 @@
-use reqwest::Client;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
-use crate::services::google_oauth;
-use crate::db;
+use crate::services::gmail_client;
+use crate::services::google_oauth::GoogleError;
 use serde_json::json;
 
+/// Sends a reply in the thread. With an HTML version the message is multipart/alternative
+/// so clients that show HTML get e.g. a formatted signature.
 pub async fn send_reply(
     user_email: &str,
     to: &str,
     subject: &str,
     thread_id: &str,
     message_body: &str,
-) -> Result<String, String> {
-    let access_token = google_oauth::refresh_access_token_for_user(user_email).await?;
+    html_body: Option<&str>,
+) -> Result<String, GoogleError> {
+    let content = match html_body {
+        Some(html) => {
+            let boundary = format!("drafly-{}", uuid::Uuid::new_v4().simple());
+            format!(
+                "MIME-Version: 1.0\r\nContent-Type: multipart/alternative; boundary=\"{b}\"\r\n\r\n--{b}\r\nContent-Type: text/plain; charset=\"UTF-8\"\r\n\r\n{}\r\n--{b}\r\nContent-Type: text/html; charset=\"UTF-8\"\r\n\r\n{}\r\n--{b}--",
+                message_body,
+                html,
+                b = boundary
+            )
+        }
+        None => format!("Content-Type: text/plain; charset=\"UTF-8\"\r\n\r\n{}", message_body),
+    };
 
     // Build MIME message
     let mime = format!(
-        "From: {}\r\nTo: {}\r\nSubject: Re: {}\r\nIn-Reply-To: {}\r\nReferences: {}\r\nContent-Type: text/plain; charset=\"UTF-8\"\r\n\r\n{}",
+        "From: {}\r\nTo: {}\r\nSubject: Re: {}\r\nIn-Reply-To: {}\r\nReferences: {}\r\n{}",
         user_email,
         to,
         subject,
         thread_id,
         thread_id,
-        message_body
+        content
     );
 
     // Gmail API requires base64url encoding
     let encoded = URL_SAFE_NO_PAD.encode(mime.as_bytes());
 
-    let client = Client::new();
-
-    let url = "https://gmail.googleapis.com/gmail/v1/users/me/messages/send";
+    let url = format!("{}/messages/send", gmail_client::GMAIL_API);
 
     let payload = json!({
         "raw": encoded,
         "threadId": thread_id
     });
 
-    let resp = client
-        .post(url)
-        .bearer_auth(access_token)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(|e| format!("HTTP error: {:?}", e))?;
-let status = resp.status();
-let txt = resp.text().await.unwrap();
-println!("\n📨 GMAIL SEND RESPONSE:\n{}\n", txt);
-
-if !status.is_success() {
-    return Err(format!("Send failed: {}", txt));
-}
+    let (status, txt) = gmail_client::send(user_email, |c| c.post(&url).json(&payload)).await?;
+    log::debug!("Gmail send response: {}", txt);
+
+    if !status.is_success() {
+        return Err(format!("Send failed: {}", txt).into());
+    }
 
     let json: serde_json::Value =
         serde_json::from_str(&txt).map_err(|e| format!("JSON decode error: {:?}", e))?;
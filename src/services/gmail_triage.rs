@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::db;
+use crate::services::gmail_client;
+use crate::services::google_oauth::GoogleError;
+
+/// Gmail accepts at most this many ids per `messages.batchModify` call
+const BATCH_MODIFY_LIMIT: usize = 1000;
+
+/// Triage changes to apply to one or more messages. Labels may be given by id
+/// (`Label_12`, `IMPORTANT`) or by name; missing user labels are created when added.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct TriageActions {
+    pub add_labels: Vec<String>,
+    pub remove_labels: Vec<String>,
+    /// Removes the message from the inbox
+    pub archive: bool,
+    /// `true` marks read, `false` unread
+    pub read: Option<bool>,
+    /// `true` stars, `false` unstars
+    pub star: Option<bool>,
+    /// Moves the message to the trash
+    pub trash: bool,
+}
+
+impl TriageActions {
+    pub fn is_empty(&self) -> bool {
+        self.add_labels.is_empty()
+            && self.remove_labels.is_empty()
+            && !self.archive
+            && self.read.is_none()
+            && self.star.is_none()
+            && !self.trash
+    }
+
+    /// Label ids or names to add and remove, with the flag actions mapped to system labels
+    fn label_changes(&self) -> (Vec<String>, Vec<String>) {
+        let mut add = self.add_labels.clone();
+        let mut remove = self.remove_labels.clone();
+
+        if self.archive {
+            remove.push("INBOX".to_string());
+        }
+        match self.read {
+            Some(true) => remove.push("UNREAD".to_string()),
+            Some(false) => add.push("UNREAD".to_string()),
+            None => {}
+        }
+        match self.star {
+            Some(true) => add.push("STARRED".to_string()),
+            Some(false) => remove.push("STARRED".to_string()),
+            None => {}
+        }
+        if self.trash {
+            add.push("TRASH".to_string());
+            remove.push("INBOX".to_string());
+        }
+
+        (add, remove)
+    }
+}
+
+/// Applies `actions` to the mailbox's messages in Gmail and mirrors the label changes in
+/// `emails.labels`. Several messages go through a single `batchModify` call.
+pub async fn apply(account_id: i32, user_email: &str, gmail_ids: &[String], actions: &TriageActions) -> Result<(), GoogleError> {
+    if gmail_ids.is_empty() || actions.is_empty() {
+        return Ok(());
+    }
+
+    let (add, remove) = actions.label_changes();
+    let add = resolve_label_ids(user_email, &add, true).await?;
+    let remove = resolve_label_ids(user_email, &remove, false).await?;
+    // TRASH is applied through messages.trash, which Gmail requires instead of modify
+    let modify_add: Vec<String> = add.iter().filter(|l| l.as_str() != "TRASH").cloned().collect();
+
+    if !modify_add.is_empty() || !remove.is_empty() {
+        if let [gmail_id] = gmail_ids {
+            let url = format!("{}/messages/{}/modify", gmail_client::GMAIL_API, gmail_id);
+            let payload = json!({ "addLabelIds": modify_add, "removeLabelIds": remove });
+            let (status, text) = gmail_client::send(user_email, |c| c.post(&url).json(&payload)).await?;
+            if !status.is_success() {
+                return Err(format!("gmail modify failed {} : {}", status, text).into());
+            }
+        } else {
+            let url = format!("{}/messages/batchModify", gmail_client::GMAIL_API);
+            for chunk in gmail_ids.chunks(BATCH_MODIFY_LIMIT) {
+                let payload = json!({ "ids": chunk, "addLabelIds": modify_add, "removeLabelIds": remove });
+                let (status, text) = gmail_client::send(user_email, |c| c.post(&url).json(&payload)).await?;
+                if !status.is_success() {
+                    return Err(format!("gmail batchModify failed {} : {}", status, text).into());
+                }
+            }
+        }
+    }
+
+    if actions.trash {
+        for gmail_id in gmail_ids {
+            let url = format!("{}/messages/{}/trash", gmail_client::GMAIL_API, gmail_id);
+            let (status, text) = gmail_client::send(user_email, |c| c.post(&url)).await?;
+            if !status.is_success() {
+                return Err(format!("gmail trash failed {} : {}", status, text).into());
+            }
+        }
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE emails
+        SET labels = ARRAY(
+            SELECT DISTINCT l FROM unnest(COALESCE(labels, '{}') || $3::text[]) AS l
+            WHERE l <> ALL($4::text[])
+            ORDER BY l
+        )
+        WHERE account_id = $1 AND gmail_id = ANY($2)
+        "#,
+        account_id,
+        gmail_ids,
+        &add[..],
+        &remove[..]
+    )
+    .execute(db::get_pool())
+    .await
+    .map_err(|e| format!("db update error: {:?}", e))?;
+
+    Ok(())
+}
+
+/// Maps label ids or names to Gmail label ids. Unknown names are created when `create`
+/// is set and skipped otherwise.
+async fn resolve_label_ids(user_email: &str, labels: &[String], create: bool) -> Result<Vec<String>, GoogleError> {
+    let mut ids: Vec<String> = Vec::new();
+    let mut unresolved: Vec<&str> = Vec::new();
+    for label in labels {
+        let label = label.trim();
+        if label.is_empty() {
+            continue;
+        }
+        if is_system_label(label) {
+            ids.push(label.to_string());
+        } else {
+            unresolved.push(label);
+        }
+    }
+
+    if !unresolved.is_empty() {
+        let existing = list_labels(user_email).await?;
+        for label in unresolved {
+            let found = existing
+                .iter()
+                .find(|(id, name)| id == label || name.eq_ignore_ascii_case(label));
+            match found {
+                Some((id, _)) => ids.push(id.clone()),
+                None if create => ids.push(create_label(user_email, label).await?),
+                None => {}
+            }
+        }
+    }
+
+    ids.sort();
+    ids.dedup();
+    Ok(ids)
+}
+
+/// Gmail's built-in labels, whose ids are also their names
+fn is_system_label(label: &str) -> bool {
+    matches!(label, "INBOX" | "UNREAD" | "STARRED" | "IMPORTANT" | "TRASH" | "SPAM" | "SENT" | "DRAFT")
+        || label.starts_with("CATEGORY_")
+}
+
+/// The mailbox's labels as (id, name)
+async fn list_labels(user_email: &str) -> Result<Vec<(String, String)>, GoogleError> {
+    let url = format!("{}/labels", gmail_client::GMAIL_API);
+    let (status, text) = gmail_client::send(user_email, |c| c.get(&url)).await?;
+    if !status.is_success() {
+        return Err(format!("gmail labels failed {} : {}", status, text).into());
+    }
+
+    let json: Value = serde_json::from_str(&text).map_err(|e| format!("json parse: {:?}", e))?;
+    Ok(json["labels"]
+        .as_array()
+        .map(|labels| {
+            labels
+                .iter()
+                .filter_map(|l| Some((l["id"].as_str()?.to_string(), l["name"].as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+async fn create_label(user_email: &str, name: &str) -> Result<String, GoogleError> {
+    let url = format!("{}/labels", gmail_client::GMAIL_API);
+    let payload = json!({
+        "name": name,
+        "labelListVisibility": "labelShow",
+        "messageListVisibility": "show"
+    });
+    let (status, text) = gmail_client::send(user_email, |c| c.post(&url).json(&payload)).await?;
+    if !status.is_success() {
+        return Err(format!("gmail create label failed {} : {}", status, text).into());
+    }
+
+    let json: Value = serde_json::from_str(&text).map_err(|e| format!("json parse: {:?}", e))?;
+    json["id"]
+        .as_str()
+        .map(|id| id.to_string())
+        .ok_or_else(|| "gmail create label: missing id".to_string().into())
+}
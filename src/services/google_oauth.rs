@@ -3,7 +3,13 @@ use jsonwebtoken::{encode, EncodingKey, Header};
 use serde::{Deserialize,Serialize};
 use crate::config;
 use reqwest::Client;
-use crate::db::get_pool;
+use crate::db;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::fmt;
+use std::time::{Duration, Instant};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 
 
 #[derive(Deserialize)]
@@ -11,6 +17,8 @@ pub struct TokenResponse {
     pub access_token: Option<String>,
     pub refresh_token: Option<String>,
     pub expires_in: Option<i64>,
+    /// Only present on the authorization-code exchange, not on refreshes
+    #[serde(default)]
     pub id_token: String,
     pub token_type: Option<String>,
     pub scope: Option<String>,
@@ -36,24 +44,63 @@ pub fn generate_jwt(email: String) -> String {
     .unwrap()
 }
 
-pub fn build_auth_url(state: String) -> String {
+/// Scopes drafly needs for full functionality
+pub const REQUIRED_SCOPES: &[&str] = &[
+    "https://www.googleapis.com/auth/gmail.readonly",
+    "https://www.googleapis.com/auth/gmail.send",
+    "https://www.googleapis.com/auth/gmail.modify",
+];
+
+/// Expands short scope names like `gmail.modify` to their full URL form
+pub fn normalize_scope(scope: &str) -> String {
+    let scope = scope.trim();
+    if scope.starts_with("https://") || matches!(scope, "openid" | "email" | "profile") {
+        scope.to_string()
+    } else {
+        format!("https://www.googleapis.com/auth/{}", scope)
+    }
+}
+
+/// Parses a comma-separated scope list from a query string
+pub fn parse_scope_list(scopes: &str) -> Vec<String> {
+    scopes
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(normalize_scope)
+        .collect()
+}
+
+/// Required scopes that are not in the space-separated `granted` list
+pub fn missing_scopes(granted: &str) -> Vec<String> {
+    let granted: Vec<&str> = granted.split_whitespace().collect();
+    REQUIRED_SCOPES
+        .iter()
+        .filter(|s| !granted.contains(s))
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Builds the consent URL. With `scopes` set only those are requested on top of
+/// the identity scopes, and `include_granted_scopes` keeps what the user already granted.
+pub fn build_auth_url(state: String, scopes: Option<Vec<String>>, login_hint: Option<&str>) -> String {
     let client_id = config::google_client_id();
     let binding = config::google_redirect_uri();
     let redirect_uri = urlencoding::encode(&binding);
 
-    let scope = urlencoding::encode(
-        "openid email profile \
-        https://www.googleapis.com/auth/gmail.readonly \
-        https://www.googleapis.com/auth/gmail.send \
-        https://www.googleapis.com/auth/gmail.modify"
-    );
+    let requested = scopes.unwrap_or_else(|| REQUIRED_SCOPES.iter().map(|s| s.to_string()).collect());
+    let scope_list = format!("openid email profile {}", requested.join(" "));
+    let scope = urlencoding::encode(&scope_list);
+
+    let hint = login_hint
+        .map(|h| format!("&login_hint={}", urlencoding::encode(h)))
+        .unwrap_or_default();
 
     // USE THE STATE PASSED FROM THE ROUTE
     format!(
         "https://accounts.google.com/o/oauth2/v2/auth?\
         client_id={}&redirect_uri={}&response_type=code&\
-        scope={}&access_type=offline&prompt=consent&state={}",
-        client_id, redirect_uri, scope, state
+        scope={}&access_type=offline&prompt=consent&include_granted_scopes=true&state={}{}",
+        client_id, redirect_uri, scope, state, hint
     )
 }
 
@@ -76,8 +123,6 @@ pub async fn exchange_code_for_tokens(code: String) -> Result<TokenResponse, Str
     let status = resp.status();
     let text = resp.text().await.unwrap();
 
-    println!("\n🔍 GOOGLE TOKEN RESPONSE:\n{}\n", text);
-
     if !status.is_success() {
         return Err(format!("Google returned error: {}", text));
     }
@@ -95,17 +140,129 @@ pub struct Tokens {
 }
 
 
-pub async fn refresh_access_token_for_user(user_email: &str) -> Result<String, String> {
-    // lookup stored refresh token
-    let pool = get_pool();
-    let rec = sqlx::query!("SELECT refresh_token FROM user_tokens WHERE email = $1 LIMIT 1", user_email)
-        .fetch_optional(pool)
-        .await
-        .map_err(|e| format!("db fetch error: {:?}", e))?;
+/// Error from a call that needs the user's Google grant
+#[derive(Debug)]
+pub enum GoogleError {
+    /// The grant was revoked or expired; the user has to go through consent again
+    ReauthRequired(String),
+    Other(String),
+}
+
+impl fmt::Display for GoogleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoogleError::ReauthRequired(email) => write!(f, "Google access for {} must be re-authorized", email),
+            GoogleError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<String> for GoogleError {
+    fn from(e: String) -> Self {
+        GoogleError::Other(e)
+    }
+}
+
+impl ResponseError for GoogleError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            GoogleError::ReauthRequired(_) => StatusCode::FORBIDDEN,
+            GoogleError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            GoogleError::ReauthRequired(email) => HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "reauth_required",
+                "message": self.to_string(),
+                "reauth_url": format!("/auth/google/start?login_hint={}", urlencoding::encode(email))
+            })),
+            GoogleError::Other(e) => HttpResponse::InternalServerError().body(e.clone()),
+        }
+    }
+}
+
+/// Access tokens are treated as expired this long before Google says they are
+const EXPIRY_SKEW_SECONDS: i64 = 60;
+
+/// Used when Google omits `expires_in` (it normally sends 3599)
+const DEFAULT_EXPIRES_IN: i64 = 3600;
+
+struct CachedToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+static TOKEN_CACHE: Lazy<Mutex<HashMap<String, CachedToken>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// One lock per user so concurrent requests share a single refresh
+static REFRESH_LOCKS: Lazy<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn cached_access_token(user_email: &str) -> Option<String> {
+    let cache = TOKEN_CACHE.lock().unwrap();
+    cache
+        .get(user_email)
+        .filter(|t| t.expires_at > Instant::now())
+        .map(|t| t.access_token.clone())
+}
 
-    let refresh_token = match rec {
-        Some(r) => r.refresh_token,
-        None => return Err("no refresh token stored for user".into()),
+fn refresh_lock(user_email: &str) -> Arc<tokio::sync::Mutex<()>> {
+    REFRESH_LOCKS
+        .lock()
+        .unwrap()
+        .entry(user_email.to_string())
+        .or_default()
+        .clone()
+}
+
+/// Returns a valid Google access token for the user, refreshing it only when the
+/// cached one is missing or about to expire
+pub async fn access_token_for_user(user_email: &str) -> Result<String, GoogleError> {
+    if let Some(token) = cached_access_token(user_email) {
+        return Ok(token);
+    }
+
+    let lock = refresh_lock(user_email);
+    let _guard = lock.lock().await;
+
+    // another request may have refreshed while we were waiting
+    if let Some(token) = cached_access_token(user_email) {
+        return Ok(token);
+    }
+
+    let (access_token, expires_in) = refresh_access_token_for_user(user_email).await?;
+    let ttl = (expires_in - EXPIRY_SKEW_SECONDS).max(0) as u64;
+
+    TOKEN_CACHE.lock().unwrap().insert(
+        user_email.to_string(),
+        CachedToken {
+            access_token: access_token.clone(),
+            expires_at: Instant::now() + Duration::from_secs(ttl),
+        },
+    );
+
+    Ok(access_token)
+}
+
+/// Drops the cached access token, e.g. after Gmail rejected it with a 401
+pub fn invalidate_access_token(user_email: &str) {
+    TOKEN_CACHE.lock().unwrap().remove(user_email);
+}
+
+/// Exchanges the stored refresh token for a new access token, returning it with its lifetime in seconds.
+/// Callers should normally go through `access_token_for_user` instead.
+pub async fn refresh_access_token_for_user(user_email: &str) -> Result<(String, i64), GoogleError> {
+    // don't keep hammering Google for a grant we already know is dead
+    if db::mailbox_accounts::needs_reauth(user_email).await? {
+        return Err(GoogleError::ReauthRequired(user_email.to_string()));
+    }
+
+    // lookup stored refresh token (decrypted transparently)
+    let refresh_token = match db::mailbox_accounts::get_refresh_token(user_email).await? {
+        Some(t) => t,
+        None => return Err(GoogleError::ReauthRequired(user_email.to_string())),
     };
 
     let client_id = crate::config::google_client_id();
@@ -129,9 +286,48 @@ pub async fn refresh_access_token_for_user(user_email: &str) -> Result<String, S
     let status = resp.status();
     let text = resp.text().await.map_err(|e| format!("read text err: {:?}", e))?;
     if !status.is_success() {
-        return Err(format!("token refresh failed: {} => {}", status, text));
+        let error = serde_json::from_str::<serde_json::Value>(&text)
+            .ok()
+            .and_then(|v| v["error"].as_str().map(|s| s.to_string()));
+
+        if error.as_deref() == Some("invalid_grant") {
+            log::warn!("Google grant for {} was revoked or expired: {}", user_email, text);
+            invalidate_access_token(user_email);
+            db::mailbox_accounts::mark_needs_reauth(user_email, &text).await?;
+            return Err(GoogleError::ReauthRequired(user_email.to_string()));
+        }
+
+        return Err(format!("token refresh failed: {} => {}", status, text).into());
     }
 
     let tok: TokenResponse = serde_json::from_str(&text).map_err(|e| format!("json decode: {:?}", e))?;
-    tok.access_token.ok_or_else(|| "no access token in response".to_string())
-}
\ No newline at end of file
+    let access_token = tok.access_token.ok_or_else(|| "no access token in response".to_string())?;
+
+    Ok((access_token, tok.expires_in.unwrap_or(DEFAULT_EXPIRES_IN)))
+}
+
+/// Revokes drafly's grant for the mailbox at Google and drops its cached access token
+pub async fn revoke_grant(user_email: &str) -> Result<(), GoogleError> {
+    invalidate_access_token(user_email);
+
+    let refresh_token = match db::mailbox_accounts::get_refresh_token(user_email).await? {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+
+    let resp = Client::new()
+        .post("https://oauth2.googleapis.com/revoke")
+        .form(&[("token", refresh_token)])
+        .send()
+        .await
+        .map_err(|e| format!("reqwest error: {:?}", e))?;
+
+    let status = resp.status();
+    // 400 invalid_token means the grant was already revoked on Google's side
+    if !status.is_success() && status != reqwest::StatusCode::BAD_REQUEST {
+        let text = resp.text().await.unwrap_or_default();
+        return Err(format!("token revoke failed: {} => {}", status, text).into());
+    }
+
+    Ok(())
+}
@@ -1,50 +1,168 @@
+use std::collections::HashMap;
 use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::json;
+use crate::db::prompts::Prompt;
+use crate::services::prompts;
 
-pub async fn generate_reply(email_body: &str, sender: &str, subject: &str, tone: &str) -> Result<String, String> {
-    let api_key = std::env::var("GROQ_API_KEY")
-        .map_err(|_| "Missing GROQ_API_KEY".to_string())?;
+/// Model used by prompts that aren't in the registry
+pub const DEFAULT_MODEL: &str = "llama-3.3-70b-versatile";
+
+/// What the model gets to write a reply from
+#[derive(Clone, Copy)]
+pub struct ReplyPrompt<'a> {
+    pub email_body: &'a str,
+    pub sender: &'a str,
+    pub subject: &'a str,
+    pub tone: &'a str,
+    /// Extra guidance, e.g. from the rule that triggered the draft
+    pub instructions: Option<&'a str>,
+    /// Past replies to similar emails, shown as examples of how the mailbox answers
+    pub examples: &'a [String],
+    /// Knowledge base excerpts, numbered in order; the only source for product facts
+    pub knowledge: &'a [String],
+    /// How the person replying writes, from their style profile
+    pub style: Option<&'a str>,
+    /// Whether the mailbox's signature is appended on send, so the reply must not sign off
+    pub signature: bool,
+}
+
+/// A generated reply and what it cost
+pub struct Reply {
+    pub content: String,
+    pub usage: Usage,
+}
+
+/// Writes a reply with a version of the `reply` prompt from the registry
+pub async fn generate_reply(template: &Prompt, reply: &ReplyPrompt<'_>) -> Result<Reply, String> {
+    let (system, prompt) = reply_messages(template, reply);
+    let completion = complete_with(
+        &template.model,
+        &system,
+        &prompt,
+        template.max_tokens as u32,
+        template.temperature,
+    )
+    .await?;
+
+    Ok(Reply {
+        content: completion.text.unwrap_or_else(|| "Unable to generate draft.".to_string()),
+        usage: completion.usage,
+    })
+}
+
+/// The system message and user prompt a version of the `reply` prompt fills in to
+pub fn reply_messages(template: &Prompt, reply: &ReplyPrompt<'_>) -> (String, String) {
+    let ReplyPrompt { email_body, sender, subject, tone, instructions, examples, knowledge, style, signature } = *reply;
 
-    // Extract sender name from email address if possible
-    let sender_name = if let Some(at_pos) = sender.find('@') {
-        sender[..at_pos].split('.').next().unwrap_or(&sender[..at_pos])
-    } else {
-        sender
+    let mut rules = match style {
+        // the profile decides greeting, length and register instead of the generic defaults
+        Some(_) => format!("- Follow the writing style below for the greeting, sign-off, length and formality, staying {} in tone", tone),
+        None => format!("- Keep it concise (2-4 sentences typically)\n- Be professional, polite, and {} in tone", tone),
     };
+    if signature {
+        rules.push_str("\n- End with the last sentence of the message: no sign-off (like \"Best,\" or \"Thanks,\") and no name, because a signature is added when the email is sent");
+    }
+    let assistant = match style {
+        Some(_) => "You are an email assistant writing in the voice of the person who sends the reply.",
+        None => "You are a professional email assistant.",
+    };
+
+    let values = HashMap::from([
+        ("assistant", assistant.to_string()),
+        ("tone", tone.to_string()),
+        ("rules", rules),
+        ("sender", sender.to_string()),
+        ("subject", if subject.is_empty() { "No subject" } else { subject }.to_string()),
+        ("email_body", email_body.to_string()),
+        (
+            "instructions",
+            instructions
+                .filter(|i| !i.trim().is_empty())
+                .map(|i| format!("Additional instructions:\n{}\n\n", i.trim()))
+                .unwrap_or_default(),
+        ),
+        ("style", style.unwrap_or("").to_string()),
+        ("knowledge", knowledge_section(knowledge)),
+        ("examples", examples_section(examples)),
+    ]);
+
+    (prompts::render(&template.system, &values), prompts::render(&template.body, &values))
+}
+
+/// Lists knowledge base excerpts and tells the model to state facts from them only
+fn knowledge_section(knowledge: &[String]) -> String {
+    if knowledge.is_empty() {
+        return String::new();
+    }
+    let mut section = String::from(
+        "Knowledge base excerpts. Take facts about products, prices, policies and procedures only from these. \
+         If they don't answer something the sender asks, don't guess; say you will check and follow up. \
+         Don't mention the excerpts or their numbers in the reply:\n",
+    );
+    for (i, excerpt) in knowledge.iter().enumerate() {
+        section.push_str(&format!("[{}] {}\n", i + 1, excerpt.trim()));
+    }
+    section.push('\n');
+    section
+}
 
-    let prompt = format!(
-        r#"You are writing a professional email reply. Write a complete, ready-to-send email reply in a {} tone.
-
-IMPORTANT INSTRUCTIONS:
-- Write a complete email reply - do NOT use placeholders like [Name], [topic], [Your Name], etc.
-- Use the actual sender's name or email address from the context
-- Reference the original email subject naturally
-- Keep it concise (2-4 sentences typically)
-- Be professional, polite, and {} in tone
-- Write as if you are directly replying to the sender
-- Do not include email headers (To, From, Subject) - just the reply body text
-
-Original Email:
-From: {}
-Subject: {}
-Body: {}
-
-Write your complete email reply (body text only, no placeholders):"#,
-        tone,
-        tone,
-        sender,
-        if subject.is_empty() { "No subject" } else { subject },
-        email_body
+/// Lists past replies for the model to match in style and substance, not to copy
+fn examples_section(examples: &[String]) -> String {
+    if examples.is_empty() {
+        return String::new();
+    }
+    let mut section = String::from(
+        "Replies previously sent to similar emails. Match their style and reuse facts that still apply, but answer this email:\n",
     );
+    for (i, example) in examples.iter().enumerate() {
+        section.push_str(&format!("--- Example {} ---\n{}\n", i + 1, example.trim()));
+    }
+    section.push('\n');
+    section
+}
+
+/// Tokens a completion used, as reported by Groq
+#[derive(Default, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+}
+
+impl Usage {
+    /// Counts the tokens of another completion too, e.g. a retry
+    pub fn add(&mut self, other: &Usage) {
+        let sum = |a: Option<i32>, b: Option<i32>| a.zip(b).map(|(a, b)| a + b).or(a).or(b);
+        self.prompt_tokens = sum(self.prompt_tokens, other.prompt_tokens);
+        self.completion_tokens = sum(self.completion_tokens, other.completion_tokens);
+    }
+}
+
+/// The text of the first choice and the tokens used
+pub struct Completion {
+    pub text: Option<String>,
+    pub usage: Usage,
+}
+
+/// Sends one system + user message pair to Groq and returns the text of the first choice
+pub async fn complete(system: &str, prompt: &str, max_tokens: u32, temperature: f32) -> Result<Option<String>, String> {
+    Ok(complete_with(DEFAULT_MODEL, system, prompt, max_tokens, temperature.into()).await?.text)
+}
+
+/// Like `complete` with a given model, also returning token usage
+pub async fn complete_with(model: &str, system: &str, prompt: &str, max_tokens: u32, temperature: f64) -> Result<Completion, String> {
+    let api_key = std::env::var("GROQ_API_KEY")
+        .map_err(|_| "Missing GROQ_API_KEY".to_string())?;
 
     let body = json!({
-        "model": "llama-3.3-70b-versatile",
+        "model": model,
         "messages": [
-            { "role": "system", "content": "You are a professional email assistant. Write complete, ready-to-send email replies without any placeholders or variables." },
+            { "role": "system", "content": system },
             { "role": "user", "content": prompt }
         ],
-        "max_tokens": 500,
-        "temperature": 0.7
+        "max_tokens": max_tokens,
+        "temperature": temperature
     });
 
     let client = Client::new();
@@ -57,16 +175,34 @@ Write your complete email reply (body text only, no placeholders):"#,
         .await
         .map_err(|e| format!("Request error: {:?}", e))?;
 
-    let txt = resp.text().await.unwrap();
-    println!("🔍 GROQ RAW RESPONSE:\n{}\n", txt);
+    let txt = resp.text().await.map_err(|e| format!("Read error: {:?}", e))?;
+    log::debug!("groq response: {}", txt);
 
     let json_resp: serde_json::Value =
         serde_json::from_str(&txt).map_err(|e| format!("JSON error: {:?}", e))?;
 
-    let reply = json_resp["choices"][0]["message"]["content"]
-        .as_str()
-        .unwrap_or("Unable to generate draft.")
-        .to_string();
+    let tokens = |key: &str| json_resp["usage"][key].as_i64().map(|n| n as i32);
+    Ok(Completion {
+        text: json_resp["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|s| s.to_string()),
+        usage: Usage {
+            prompt_tokens: tokens("prompt_tokens"),
+            completion_tokens: tokens("completion_tokens"),
+        },
+    })
+}
+
+/// Like `complete`, for prompts that ask for a JSON object. Text the model puts around
+/// the object is ignored.
+pub async fn complete_json<T: DeserializeOwned>(system: &str, prompt: &str, max_tokens: u32, temperature: f32) -> Result<T, String> {
+    let answer = complete(system, prompt, max_tokens, temperature)
+        .await?
+        .ok_or_else(|| "empty model answer".to_string())?;
 
-    Ok(reply)
+    let json = match (answer.find('{'), answer.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &answer[start..=end],
+        _ => return Err(format!("no JSON object in model answer: {}", answer)),
+    };
+    serde_json::from_str(json).map_err(|e| format!("unexpected model answer {}: {:?}", answer, e))
 }
@@ -0,0 +1,330 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::services::quality;
+
+/// Findings that block approval unless the approver overrides them
+pub const HIGH: &str = "high";
+pub const MEDIUM: &str = "medium";
+
+/// Something in a draft a reviewer should look at before it goes out
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Warning {
+    /// `placeholder`, `unsupported_promise`, `email_header` or `language`
+    pub kind: String,
+    /// `high` or `medium`
+    pub severity: String,
+    pub message: String,
+    /// The text the finding is about
+    pub excerpt: Option<String>,
+}
+
+impl Warning {
+    fn new(kind: &str, severity: &str, message: String, excerpt: Option<&str>) -> Warning {
+        Warning {
+            kind: kind.to_string(),
+            severity: severity.to_string(),
+            message,
+            excerpt: excerpt.map(str::to_string),
+        }
+    }
+}
+
+/// What a reply may rely on for facts and commitments
+pub struct Context<'a> {
+    pub email_body: &'a str,
+    /// Knowledge base excerpts the reply was grounded on
+    pub knowledge: &'a [String],
+    /// Instructions the reply was written with, e.g. from a rule
+    pub instructions: Option<&'a str>,
+}
+
+impl Context<'_> {
+    /// Text a price or date in the reply may come from
+    fn everything(&self) -> String {
+        let mut text = format!("{}\n{}", self.email_body, self.knowledge.join("\n"));
+        if let Some(instructions) = self.instructions {
+            text.push('\n');
+            text.push_str(instructions);
+        }
+        text
+    }
+
+    /// Text that can allow the reply to commit to something. The email can't: a customer
+    /// asking for a refund doesn't mean the reply may promise one.
+    fn policy(&self) -> String {
+        format!("{}\n{}", self.knowledge.join("\n"), self.instructions.unwrap_or(""))
+    }
+}
+
+/// Checks a reply for placeholders, promises nothing supports, email headers and the wrong
+/// language
+pub fn check(reply: &str, context: &Context) -> Vec<Warning> {
+    let mut warnings: Vec<Warning> = quality::placeholders(reply)
+        .iter()
+        .map(|p| Warning::new("placeholder", HIGH, format!("Placeholder {} was never filled in", p), Some(p)))
+        .collect();
+
+    let header = Regex::new(r"(?im)^[ \t]*(?:from|to|cc|bcc|subject|date|sent|reply-to)[ \t]*:[ \t]*\S.*$").expect("valid regex");
+    warnings.extend(header.find_iter(reply).map(|m| {
+        Warning::new("email_header", HIGH, "The reply contains an email header line".to_string(), Some(m.as_str().trim()))
+    }));
+
+    warnings.extend(unsupported_promises(reply, context));
+
+    let languages = quality::detect_language(context.email_body).zip(quality::detect_language(reply));
+    if let Some((expected, found)) = languages.filter(|(expected, found)| expected != found) {
+        warnings.push(Warning::new(
+            "language",
+            MEDIUM,
+            format!("The reply is in {} but the email is in {}", found, expected),
+            None,
+        ));
+    }
+
+    warnings
+}
+
+/// Refunds, prices and dates the reply commits to without the email, the knowledge base or
+/// the instructions mentioning them
+fn unsupported_promises(reply: &str, context: &Context) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let everything = context.everything().to_lowercase().replace(',', "");
+
+    // a commitment right before the refund, or a refund said to be under way
+    let promised = Regex::new(
+        r"(?i)\b(?:will|'ll|going to|can|am|are|has been|have been|is being|are being)\b(?:\s+[\w']+){0,4}?\s+(?:refund\w*|reimburs\w*|money back|store credit|compensat\w*)(\s+(?:request|question|inquiry|policy|process))?|\b(?:refund|reimbursement|credit|compensation)\b(?:\s+[\w']+){0,3}?\s+(?:has|have|was|were|is|are|will)\s+(?:been\s+|be\s+)?(?:issued|processed|approved|sent|credited)",
+    )
+    .expect("valid regex");
+    let negation = Regex::new(r"(?i)\b(?:not|unable|cannot|can't|won't|don't)\b").expect("valid regex");
+    let refund_policy = Regex::new(r"(?i)\b(?:refund|reimburs|money back|store credit|compensat)").expect("valid regex");
+    if !refund_policy.is_match(&context.policy()) {
+        for sentence in sentences(reply) {
+            // talking about the customer's request isn't a promise
+            let promise = promised.captures_iter(sentence).any(|caps| caps.get(1).is_none());
+            if promise && !negation.is_match(sentence) && !sentence.ends_with('?') {
+                warnings.push(Warning::new(
+                    "unsupported_promise",
+                    HIGH,
+                    "The reply commits to a refund or compensation that the knowledge base and instructions don't cover".to_string(),
+                    Some(sentence),
+                ));
+            }
+        }
+    }
+
+    let price = Regex::new(
+        r"(?i)[$€£]\s?(\d[\d,]*(?:\.\d{1,2})?)|\b(\d[\d,]*(?:\.\d{1,2})?)\s?(?:usd|eur|gbp|dollars?|euros?|pounds?)\b",
+    )
+    .expect("valid regex");
+    for caps in price.captures_iter(reply) {
+        let amount = caps.get(1).or_else(|| caps.get(2)).map_or("", |m| m.as_str()).replace(',', "");
+        let mentioned = Regex::new(&format!(r"(?:^|[^\d.]){}(?:$|[^\d])", regex::escape(&amount)))
+            .expect("valid regex")
+            .is_match(&everything);
+        if !mentioned {
+            warnings.push(Warning::new(
+                "unsupported_promise",
+                HIGH,
+                "The reply states a price the email, knowledge base and instructions don't mention".to_string(),
+                Some(&caps[0]),
+            ));
+        }
+    }
+
+    let date = Regex::new(
+        r"(?i)\b(?:by|on|before|until|within|no later than)\s+((?:(?:this|next)\s+)?(?:monday|tuesday|wednesday|thursday|friday|saturday|sunday|week|month)|tomorrow|tonight|today|(?:the\s+)?end of (?:the\s+)?(?:day|week|month)|\d{1,3}\s+(?:business\s+|working\s+)?(?:hours?|days?|weeks?)|(?:jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec)[a-z]*\.?\s+\d{1,2}(?:st|nd|rd|th)?|\d{1,2}(?:st|nd|rd|th)?\s+(?:of\s+)?(?:jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec)[a-z]*|\d{1,2}[/.]\d{1,2}(?:[/.]\d{2,4})?)\b",
+    )
+    .expect("valid regex");
+    for caps in date.captures_iter(reply) {
+        if !everything.contains(&caps[1].to_lowercase()) {
+            warnings.push(Warning::new(
+                "unsupported_promise",
+                MEDIUM,
+                "The reply commits to a date or deadline the email, knowledge base and instructions don't mention".to_string(),
+                Some(&caps[0]),
+            ));
+        }
+    }
+
+    warnings
+}
+
+fn sentences(text: &str) -> Vec<&str> {
+    let end = Regex::new(r"[.!?]+(?:\s+|$)|\n").expect("valid regex");
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for m in end.find_iter(text) {
+        sentences.push(text[start..m.end()].trim());
+        start = m.end();
+    }
+    sentences.push(text[start..].trim());
+    sentences.retain(|s| !s.is_empty());
+    sentences
+}
+
+pub fn high_count(warnings: &[Warning]) -> usize {
+    warnings.iter().filter(|w| w.severity == HIGH).count()
+}
+
+/// Instructions for another attempt at a reply that had high-severity findings: the original
+/// instructions followed by what to fix
+pub fn retry_instructions(instructions: Option<&str>, warnings: &[Warning]) -> String {
+    let mut fixes: Vec<&str> = Vec::new();
+    for w in warnings.iter().filter(|w| w.severity == HIGH) {
+        let fix = match w.kind.as_str() {
+            "placeholder" => "Do not leave placeholders in brackets or braces; use names and details from the email, or leave the detail out.",
+            "email_header" => "Write only the body of the reply, without From, To, Subject or other header lines.",
+            "unsupported_promise" => "Do not promise refunds, compensation or prices, and do not commit to dates, unless the email, the knowledge base excerpts or these instructions state them.",
+            _ => continue,
+        };
+        if !fixes.contains(&fix) {
+            fixes.push(fix);
+        }
+    }
+
+    let mut text = instructions.map(|i| format!("{}\n", i.trim())).unwrap_or_default();
+    text.push_str("A previous attempt at this reply had problems. Avoid them:");
+    for fix in fixes {
+        text.push_str("\n- ");
+        text.push_str(fix);
+    }
+    text
+}
+
+/// Checks a draft's current content again and stores the findings. Called whenever the
+/// content changes, so approval is decided on what would be sent.
+pub async fn recheck(draft_id: i32) -> Result<Vec<Warning>, String> {
+    let pool = db::get_pool();
+    let draft = sqlx::query!(
+        r#"
+        SELECT d.content, e.body_text AS "email_body?", r.instructions AS "instructions?"
+        FROM drafts d
+        LEFT JOIN emails e ON e.id = d.email_id
+        LEFT JOIN draft_rules r ON r.id = d.rule_id
+        WHERE d.id = $1
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("db fetch error: {:?}", e))?
+    .ok_or_else(|| format!("draft {} not found", draft_id))?;
+
+    let knowledge: Vec<String> = db::knowledge::sources(draft_id)
+        .await
+        .map_err(|e| format!("db fetch error: {:?}", e))?
+        .into_iter()
+        .map(|s| s.content)
+        .collect();
+    let warnings = check(
+        draft.content.as_deref().unwrap_or(""),
+        &Context {
+            email_body: draft.email_body.as_deref().unwrap_or(""),
+            knowledge: &knowledge,
+            instructions: draft.instructions.as_deref(),
+        },
+    );
+
+    sqlx::query!(
+        "UPDATE drafts SET warnings = $1 WHERE id = $2",
+        serde_json::json!(warnings),
+        draft_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("db update error: {:?}", e))?;
+
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMAIL: &str = "Hi, the blender I ordered from you arrived broken and the jug is cracked. Can I get my money back? Thanks, Carl";
+
+    fn findings(reply: &str, knowledge: &[String]) -> Vec<(String, String)> {
+        let context = Context { email_body: EMAIL, knowledge, instructions: None };
+        check(reply, &context).into_iter().map(|w| (w.kind, w.severity)).collect()
+    }
+
+    fn kinds(reply: &str) -> Vec<String> {
+        findings(reply, &[]).into_iter().map(|(kind, _)| kind).collect()
+    }
+
+    #[test]
+    fn flags_placeholders() {
+        assert_eq!(findings("Hi [Name], sorry about the blender.", &[]), vec![("placeholder".to_string(), HIGH.to_string())]);
+        assert_eq!(kinds("Hi {{first_name}}, sorry about the blender."), vec!["placeholder"]);
+    }
+
+    #[test]
+    fn flags_header_lines() {
+        assert_eq!(kinds("Subject: Re: Broken blender\n\nSorry about the blender, Carl."), vec!["email_header"]);
+        assert!(kinds("Sorry about the blender, Carl. Subject to stock, we have more.").is_empty());
+    }
+
+    #[test]
+    fn flags_refund_promises() {
+        assert_eq!(kinds("Sorry about that, Carl. We will refund you in full."), vec!["unsupported_promise"]);
+        assert_eq!(kinds("Your refund has been processed."), vec!["unsupported_promise"]);
+    }
+
+    #[test]
+    fn talking_about_refunds_is_not_a_promise() {
+        assert!(kinds("We received your refund request and will look into it.").is_empty());
+        assert!(kinds("Unfortunately we cannot refund opened items.").is_empty());
+        assert!(kinds("Would you like us to refund the order?").is_empty());
+    }
+
+    #[test]
+    fn refunds_covered_by_knowledge_are_fine() {
+        let knowledge = ["Refunds are available within 30 days of purchase.".to_string()];
+        assert!(findings("We will refund you in full.", &knowledge).is_empty());
+    }
+
+    #[test]
+    fn flags_prices_nobody_mentioned() {
+        assert_eq!(findings("A replacement is $49.", &[]), vec![("unsupported_promise".to_string(), HIGH.to_string())]);
+        let knowledge = ["The replacement jug costs $49.99.".to_string()];
+        assert!(findings("The replacement jug is $49.99.", &knowledge).is_empty());
+        assert_eq!(kinds("The replacement jug is $49."), vec!["unsupported_promise"]);
+    }
+
+    #[test]
+    fn flags_dates_nobody_mentioned() {
+        assert_eq!(findings("A new one will ship by Friday.", &[]), vec![("unsupported_promise".to_string(), MEDIUM.to_string())]);
+        let context = Context { email_body: "Can you ship it by Friday?", knowledge: &[], instructions: None };
+        assert!(check("Yes, it ships by Friday.", &context).is_empty());
+    }
+
+    #[test]
+    fn flags_a_reply_in_another_language() {
+        let reply = "Gracias por su mensaje, lo siento mucho por el problema con la licuadora.";
+        assert_eq!(findings(reply, &[]), vec![("language".to_string(), MEDIUM.to_string())]);
+    }
+
+    #[test]
+    fn clean_reply_has_no_findings() {
+        let reply = "Hi Carl,\n\nSorry the blender arrived broken. Could you send a photo of the damage so we can sort this out?\n\nBest";
+        assert!(kinds(reply).is_empty());
+    }
+
+    #[test]
+    fn splits_sentences_outside_prices() {
+        assert_eq!(sentences("It is $49.99. Thanks!\nBest"), vec!["It is $49.99.", "Thanks!", "Best"]);
+    }
+
+    #[test]
+    fn retry_instructions_list_each_fix_once() {
+        let warnings = [
+            Warning::new("placeholder", HIGH, String::new(), Some("[Name]")),
+            Warning::new("placeholder", HIGH, String::new(), Some("[date]")),
+            Warning::new("language", MEDIUM, String::new(), None),
+        ];
+        let text = retry_instructions(Some("Offer a call. "), &warnings);
+        assert!(text.starts_with("Offer a call.\n"));
+        assert_eq!(text.matches("\n- ").count(), 1);
+    }
+}
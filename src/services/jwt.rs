@@ -5,18 +5,33 @@ use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 
 #[derive(Serialize, Deserialize)]
 pub struct Claims {
+    /// drafly user id
     pub sub: String,
+    /// Session the token was issued for; revoking it invalidates the token early
+    pub sid: String,
+    pub iat: usize,
     pub exp: usize,
 }
 
-pub fn create_jwt(email: &str) -> String {
-    let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::days(7))
+/// Lifetime of an access JWT; clients renew it through `POST /auth/refresh`
+pub const ACCESS_TOKEN_MINUTES: i64 = 15;
+
+/// Max-Age for cookies carrying an access JWT, matching the token's `exp`
+pub fn access_token_max_age() -> actix_web::cookie::time::Duration {
+    actix_web::cookie::time::Duration::minutes(ACCESS_TOKEN_MINUTES)
+}
+
+pub fn create_jwt(user_id: i32, session_id: &str) -> String {
+    let now = chrono::Utc::now();
+    let expiration = now
+        .checked_add_signed(chrono::Duration::minutes(ACCESS_TOKEN_MINUTES))
         .unwrap()
         .timestamp() as usize;
 
     let claims = Claims {
-        sub: email.to_string(),
+        sub: user_id.to_string(),
+        sid: session_id.to_string(),
+        iat: now.timestamp() as usize,
         exp: expiration,
     };
 
@@ -0,0 +1,261 @@
+use regex::Regex;
+use serde::Serialize;
+use crate::db;
+use crate::services::embeddings::{self, Provider};
+
+pub const FORMATS: [&str; 3] = ["markdown", "text", "pdf"];
+
+/// Target size of a chunk; paragraphs are packed until the next one would overflow it
+const CHUNK_CHARS: usize = 1200;
+/// Characters of the incoming email used to look up chunks
+const QUERY_CHARS: usize = 4000;
+/// How close a chunk must be to the email to be put in the prompt
+pub const MIN_CHUNK_SCORE: f32 = 0.15;
+/// Chunks compared per lookup; the scan is brute force
+const SCAN_LIMIT: i64 = 10000;
+
+/// A piece of a document that is embedded, retrieved and cited on its own
+pub struct Chunk {
+    /// Markdown heading path the chunk sits under, e.g. `Pricing > Discounts`
+    pub heading: Option<String>,
+    pub content: String,
+}
+
+/// Undoes the layout of text extracted from a PDF: page breaks become paragraph breaks,
+/// words hyphenated across lines are joined and wrapped lines are unwrapped.
+fn normalize_pdf_text(text: &str) -> String {
+    let text = text.replace("\r\n", "\n").replace('\u{c}', "\n\n");
+    let hyphenated = Regex::new(r"(\w)-\n(\w)").expect("valid regex");
+    let text = hyphenated.replace_all(&text, "$1$2");
+
+    text.split("\n\n")
+        .map(|p| p.lines().map(str::trim).filter(|l| !l.is_empty()).collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Splits text into paragraphs at blank lines
+fn paragraphs(text: &str) -> Vec<String> {
+    text.split("\n\n")
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Groups Markdown into `(heading path, paragraphs)` sections. Headings inside code
+/// fences are left alone.
+fn markdown_sections(text: &str) -> Vec<(Option<String>, Vec<String>)> {
+    let heading_re = Regex::new(r"^(#{1,6})\s+(.+?)\s*#*\s*$").expect("valid regex");
+    let mut sections = Vec::new();
+    let mut path: Vec<(usize, String)> = Vec::new();
+    let mut body = String::new();
+    let mut in_fence = false;
+
+    let heading_of = |path: &[(usize, String)]| {
+        (!path.is_empty()).then(|| path.iter().map(|(_, t)| t.as_str()).collect::<Vec<_>>().join(" > "))
+    };
+
+    for line in text.replace("\r\n", "\n").lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        let heading = if in_fence { None } else { heading_re.captures(line) };
+        match heading {
+            Some(caps) => {
+                sections.push((heading_of(&path), paragraphs(&body)));
+                body.clear();
+                let level = caps[1].len();
+                path.retain(|(l, _)| *l < level);
+                path.push((level, caps[2].to_string()));
+            }
+            None => {
+                body.push_str(line);
+                body.push('\n');
+            }
+        }
+    }
+    sections.push((heading_of(&path), paragraphs(&body)));
+
+    sections.into_iter().filter(|(_, p)| !p.is_empty()).collect()
+}
+
+/// Cuts a paragraph longer than a chunk at sentence ends, or anywhere if a sentence is
+/// too long as well
+fn split_long(paragraph: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+
+    for sentence in paragraph.split_inclusive(['.', '!', '?']) {
+        if !current.is_empty() && current.len() + sentence.len() > CHUNK_CHARS {
+            pieces.push(std::mem::take(&mut current).trim().to_string());
+        }
+        current.push_str(sentence);
+        while current.len() > CHUNK_CHARS {
+            let mut cut = CHUNK_CHARS;
+            while !current.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            let rest = current.split_off(cut);
+            pieces.push(current.trim().to_string());
+            current = rest;
+        }
+    }
+    if !current.trim().is_empty() {
+        pieces.push(current.trim().to_string());
+    }
+    pieces
+}
+
+/// Splits a document into chunks of about `CHUNK_CHARS`, never across Markdown sections
+pub fn chunk(format: &str, content: &str) -> Vec<Chunk> {
+    let sections = match format {
+        "markdown" => markdown_sections(content),
+        "pdf" => vec![(None, paragraphs(&normalize_pdf_text(content)))],
+        _ => vec![(None, paragraphs(&content.replace("\r\n", "\n")))],
+    };
+
+    let mut chunks = Vec::new();
+    for (heading, paragraphs) in sections {
+        let mut current = String::new();
+        for paragraph in paragraphs.iter().flat_map(|p| split_long(p)) {
+            if !current.is_empty() && current.len() + paragraph.len() + 2 > CHUNK_CHARS {
+                chunks.push(Chunk { heading: heading.clone(), content: std::mem::take(&mut current) });
+            }
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(&paragraph);
+        }
+        if !current.is_empty() {
+            chunks.push(Chunk { heading: heading.clone(), content: current });
+        }
+    }
+    chunks
+}
+
+/// Text embedded for a chunk; the title and heading help match short chunks
+fn chunk_text(title: &str, chunk: &Chunk) -> String {
+    match &chunk.heading {
+        Some(h) => format!("{} > {}\n\n{}", title, h, chunk.content),
+        None => format!("{}\n\n{}", title, chunk.content),
+    }
+}
+
+/// Chunks and embeds a document, replacing its previous chunks. Returns the chunk count.
+pub async fn index_document(provider: &Provider, document: &db::knowledge::Document) -> Result<usize, String> {
+    let chunks = chunk(&document.format, &document.content);
+
+    let mut rows = Vec::with_capacity(chunks.len());
+    for c in &chunks {
+        let vector = provider.embed(&chunk_text(&document.title, c)).await?;
+        rows.push((c.heading.as_deref(), c.content.as_str(), vector));
+    }
+
+    db::knowledge::replace_chunks(document.id, document.workspace_id, &provider.model(), &rows)
+        .await
+        .map_err(|e| format!("db insert error: {:?}", e))?;
+    Ok(chunks.len())
+}
+
+/// A chunk retrieved for a prompt
+#[derive(Serialize)]
+pub struct RetrievedChunk {
+    pub chunk_id: i32,
+    pub document_id: i32,
+    pub document_title: String,
+    pub heading: Option<String>,
+    pub content: String,
+    pub score: f32,
+}
+
+/// The workspace's chunks closest to `text`, best first
+pub async fn retrieve(workspace_id: i32, text: &str, limit: usize, min_score: f32) -> Result<Vec<RetrievedChunk>, String> {
+    let provider = Provider::from_env()?;
+    let text: String = text.chars().take(QUERY_CHARS).collect();
+    let query = provider.embed(&text).await?;
+
+    let candidates = db::knowledge::scan_chunks(workspace_id, &provider.model(), SCAN_LIMIT)
+        .await
+        .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    let mut matches: Vec<RetrievedChunk> = candidates
+        .into_iter()
+        .map(|c| RetrievedChunk {
+            score: embeddings::similarity(&query, &c.vector),
+            chunk_id: c.id,
+            document_id: c.document_id,
+            document_title: c.document_title,
+            heading: c.heading,
+            content: c.content,
+        })
+        .filter(|m| m.score >= min_score)
+        .collect();
+
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    matches.truncate(limit);
+    Ok(matches)
+}
+
+/// How a retrieved chunk is shown to the model
+pub fn prompt_excerpt(chunk: &RetrievedChunk) -> String {
+    match &chunk.heading {
+        Some(h) => format!("{} > {}\n{}", chunk.document_title, h, chunk.content),
+        None => format!("{}\n{}", chunk.document_title, chunk.content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_chunks_carry_their_heading_path() {
+        let doc = "Intro text.\n\n# Pricing\n\nPlans start at $10.\n\n## Discounts\n\nNonprofits get 20% off.\n\n# Support\n\nEmail us.";
+        let chunks = chunk("markdown", doc);
+        let got: Vec<(Option<&str>, &str)> = chunks.iter().map(|c| (c.heading.as_deref(), c.content.as_str())).collect();
+        assert_eq!(
+            got,
+            vec![
+                (None, "Intro text."),
+                (Some("Pricing"), "Plans start at $10."),
+                (Some("Pricing > Discounts"), "Nonprofits get 20% off."),
+                (Some("Support"), "Email us."),
+            ]
+        );
+    }
+
+    #[test]
+    fn headings_in_code_fences_are_content() {
+        let chunks = chunk("markdown", "# Setup\n\n```\n# not a heading\n```");
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].heading.as_deref(), Some("Setup"));
+        assert!(chunks[0].content.contains("# not a heading"));
+    }
+
+    #[test]
+    fn short_paragraphs_are_packed_and_long_ones_split() {
+        let chunks = chunk("text", "One.\n\nTwo.\r\n\r\nThree.");
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].content, "One.\n\nTwo.\n\nThree.");
+
+        let long = "This sentence is about forty characters. ".repeat(100);
+        let chunks = chunk("text", &long);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.content.len() <= CHUNK_CHARS));
+        assert!(chunks.iter().all(|c| c.content.ends_with('.')));
+    }
+
+    #[test]
+    fn unbroken_text_is_cut_on_char_boundaries() {
+        let chunks = chunk("text", &"é".repeat(CHUNK_CHARS));
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|c| c.content.len() <= CHUNK_CHARS));
+    }
+
+    #[test]
+    fn pdf_layout_is_undone() {
+        let chunks = chunk("pdf", "Refunds are avail-\nable within 30\ndays.\u{c}Next page.");
+        assert_eq!(chunks[0].content, "Refunds are available within 30 days.\n\nNext page.");
+    }
+}
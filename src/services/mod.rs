@@ -4,3 +4,20 @@ pub mod gmail_fetcher;
 pub mod ai_service;
 pub mod groq_ai;
 pub mod gmail_sender;
+pub mod session;
+pub mod token_crypto;
+pub mod gmail_client;
+pub mod draft_generator;
+pub mod rules;
+pub mod gmail_triage;
+pub mod classifier;
+pub mod summarizer;
+pub mod search;
+pub mod embeddings;
+pub mod knowledge;
+pub mod templates;
+pub mod style;
+pub mod signatures;
+pub mod prompts;
+pub mod quality;
+pub mod guardrails;
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use crate::services::templates;
+
+static PLACEHOLDER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").expect("valid regex"));
+
+/// Prompts the registry serves
+pub const NAMES: [&str; 1] = ["reply"];
+
+/// Variables a `reply` prompt can use. Sections (`instructions`, `style`, `knowledge`,
+/// `examples`) are empty or end with a blank line, so they can be written back to back.
+pub const REPLY_VARIABLES: [&str; 10] = [
+    "assistant", "tone", "rules", "sender", "subject", "email_body", "instructions", "style", "knowledge", "examples",
+];
+
+/// Checks that a template uses only the variables of its prompt and shows the model the email
+pub fn validate(name: &str, system: &str, body: &str) -> Result<(), String> {
+    if !NAMES.contains(&name) {
+        return Err(format!("name must be one of {}", NAMES.join(", ")));
+    }
+    let unknown: Vec<String> = templates::variables(system)
+        .into_iter()
+        .chain(templates::variables(body))
+        .filter(|v| !REPLY_VARIABLES.contains(&v.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "unknown variables: {} (available: {})",
+            unknown.join(", "),
+            REPLY_VARIABLES.join(", ")
+        ));
+    }
+    if !templates::variables(body).iter().any(|v| v == "email_body") {
+        return Err("body must include {{email_body}}".to_string());
+    }
+    Ok(())
+}
+
+/// Fills in a prompt template. Values are inserted as is, so text in the email that looks
+/// like a variable is never expanded.
+pub fn render(template: &str, values: &HashMap<&str, String>) -> String {
+    PLACEHOLDER
+        .replace_all(template, |caps: &regex::Captures| {
+            values.get(&caps[1]).cloned().unwrap_or_default()
+        })
+        .into_owned()
+}
@@ -0,0 +1,84 @@
+use regex::Regex;
+
+/// Languages `detect_language` tells apart, with words common in any text written in them
+const LANGUAGES: [(&str, &[&str]); 7] = [
+    ("en", &["the", "and", "you", "to", "is", "for", "that", "with", "have", "this", "are", "be", "we", "your", "will", "please", "thanks", "it", "of", "on"]),
+    ("es", &["el", "la", "los", "las", "que", "de", "y", "en", "por", "para", "con", "una", "es", "su", "gracias", "usted", "lo", "del", "muy", "pero"]),
+    ("fr", &["le", "la", "les", "et", "vous", "de", "des", "est", "pour", "que", "une", "dans", "pas", "nous", "avec", "merci", "sur", "du", "au", "je"]),
+    ("de", &["der", "die", "das", "und", "ist", "sie", "nicht", "mit", "ich", "wir", "für", "ein", "eine", "zu", "den", "auf", "danke", "ihre", "bitte", "von"]),
+    ("pt", &["o", "os", "que", "de", "e", "em", "para", "com", "uma", "não", "você", "obrigado", "obrigada", "do", "da", "por", "mais", "seu", "sua", "muito"]),
+    ("it", &["il", "che", "di", "e", "per", "una", "non", "sono", "con", "della", "grazie", "gli", "lei", "del", "ho", "suo", "questo", "anche", "nel", "mi"]),
+    ("nl", &["de", "het", "een", "en", "van", "ik", "je", "niet", "dat", "met", "voor", "zijn", "wij", "u", "bedankt", "op", "ook", "maar", "graag", "uw"]),
+];
+
+/// Fewest common words before a language is named
+const MIN_LANGUAGE_HITS: usize = 3;
+
+/// Text left for someone to fill in: `[Name]`, `{{first_name}}`, `<Company>`, `XX/XX`
+pub fn placeholders(text: &str) -> Vec<String> {
+    let patterns = Regex::new(
+        r"\[\s*[A-Za-z][A-Za-z0-9 ._'/-]{0,38}\]|\{\{[^{}\n]{0,40}\}\}|\{[A-Za-z_][A-Za-z0-9_ ]{0,30}\}|<[A-Z][A-Za-z ]{1,30}>|\bX{2,}\b",
+    )
+    .expect("valid regex");
+
+    let mut found: Vec<String> = Vec::new();
+    for m in patterns.find_iter(text) {
+        if !found.iter().any(|f| f == m.as_str()) {
+            found.push(m.as_str().to_string());
+        }
+    }
+    found
+}
+
+pub fn word_count(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+/// Best guess at the language of a text, as an ISO 639-1 code. `None` when the text is too
+/// short or doesn't use enough common words of any known language.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower.split(|c: char| !c.is_alphabetic()).filter(|w| !w.is_empty()).collect();
+
+    let mut scores: Vec<(&str, usize)> = LANGUAGES
+        .iter()
+        .map(|(code, common)| (*code, words.iter().filter(|w| common.contains(w)).count()))
+        .collect();
+    scores.sort_by_key(|s| std::cmp::Reverse(s.1));
+
+    // a tie means the words shared between languages decided it, which says nothing
+    match scores.as_slice() {
+        [(code, best), (_, second), ..] if *best >= MIN_LANGUAGE_HITS && best > second => Some(code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_placeholders_once() {
+        let text = "Hi [Name], thanks {{first_name}}. <Company> will call on XX/XX. Hi [Name]";
+        assert_eq!(placeholders(text), vec!["[Name]", "{{first_name}}", "<Company>", "XX"]);
+    }
+
+    #[test]
+    fn ordinary_text_has_no_placeholders() {
+        assert!(placeholders("Your order #1234 ships today (see [1] below). 2 < 3 and {} is empty.").is_empty());
+    }
+
+    #[test]
+    fn detects_languages() {
+        assert_eq!(detect_language("Thanks for your message, we will have the order ready for you this week."), Some("en"));
+        assert_eq!(detect_language("Gracias por su mensaje, el pedido está listo para usted en la tienda."), Some("es"));
+        assert_eq!(detect_language("Merci pour votre message, nous avons reçu la commande et vous répondrons dans la journée."), Some("fr"));
+        assert_eq!(detect_language("Danke für Ihre Nachricht, wir haben die Bestellung und ich melde mich bitte bald."), Some("de"));
+    }
+
+    #[test]
+    fn short_text_has_no_language() {
+        assert_eq!(detect_language("OK"), None);
+        assert_eq!(detect_language(""), None);
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use chrono::Utc;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::db::approvals::sender_address;
+use crate::db::rules::Rule;
+use crate::db::templates::Template;
+use crate::services::draft_generator::{self, DraftOptions};
+use crate::services::templates::{self, AdaptPrompt};
+use crate::services::{gmail_triage, guardrails};
+
+/// Conditions of a draft rule. Every condition that is set must hold; within a list
+/// any entry may match.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Conditions {
+    /// Sender addresses, compared case-insensitively
+    pub senders: Vec<String>,
+    /// Sender domains, subdomains included
+    pub domains: Vec<String>,
+    /// Case-insensitive regex on the subject
+    pub subject_regex: Option<String>,
+    /// Gmail label ids that must all be present
+    pub labels: Vec<String>,
+    /// Words or phrases looked up in the subject and body
+    pub keywords: Vec<String>,
+}
+
+/// The parts of an email rules look at
+pub struct EmailFacts<'a> {
+    pub sender: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub labels: &'a [String],
+}
+
+/// Conditions with the subject regex compiled
+pub struct Matcher {
+    conditions: Conditions,
+    subject_regex: Option<Regex>,
+}
+
+impl Conditions {
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+            && self.domains.is_empty()
+            && self.subject_regex.is_none()
+            && self.labels.is_empty()
+            && self.keywords.is_empty()
+    }
+
+    /// Validates the conditions and compiles them for matching
+    pub fn compile(&self) -> Result<Matcher, String> {
+        if self.is_empty() {
+            return Err("a rule needs at least one condition".to_string());
+        }
+
+        let subject_regex = match self.subject_regex.as_deref() {
+            Some(pattern) => Some(
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .size_limit(1 << 20)
+                    .build()
+                    .map_err(|e| format!("invalid subject_regex: {}", e))?,
+            ),
+            None => None,
+        };
+
+        Ok(Matcher {
+            conditions: self.clone(),
+            subject_regex,
+        })
+    }
+}
+
+impl Matcher {
+    pub fn matches(&self, email: &EmailFacts) -> bool {
+        let c = &self.conditions;
+        let address = sender_address(email.sender);
+        let domain = address.rsplit_once('@').map(|(_, d)| d).unwrap_or("");
+
+        if !c.senders.is_empty() && !c.senders.iter().any(|s| s.trim().eq_ignore_ascii_case(&address)) {
+            return false;
+        }
+
+        if !c.domains.is_empty()
+            && !c.domains.iter().any(|d| {
+                let d = d.trim().trim_start_matches('@').to_lowercase();
+                domain == d || domain.ends_with(&format!(".{}", d))
+            })
+        {
+            return false;
+        }
+
+        if self.subject_regex.as_ref().is_some_and(|re| !re.is_match(email.subject)) {
+            return false;
+        }
+
+        if !c.labels.iter().all(|l| email.labels.iter().any(|have| have.eq_ignore_ascii_case(l))) {
+            return false;
+        }
+
+        if !c.keywords.is_empty() {
+            let haystack = format!("{}\n{}", email.subject, email.body).to_lowercase();
+            if !c.keywords.iter().any(|k| haystack.contains(&k.trim().to_lowercase())) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// What the first matching rule did to a freshly synced email
+pub struct RuleOutcome {
+    pub rule_id: i32,
+    /// Triage actions applied in Gmail, if the rule has any
+    pub actions: Option<gmail_triage::TriageActions>,
+    pub draft_id: Option<i32>,
+}
+
+/// Runs the mailbox's enabled rules against a freshly synced email. The first rule that
+/// matches applies its triage actions and drafts a reply, from its template if it has one;
+/// emails that already have a draft don't get another one.
+pub async fn apply_to_new_email(account_id: i32, user_email: &str, email_id: i32) -> Result<Option<RuleOutcome>, String> {
+    let pool = db::get_pool();
+
+    let email = sqlx::query!(
+        r#"
+        SELECT gmail_id, sender, subject, body_text, labels,
+               EXISTS (SELECT 1 FROM drafts d WHERE d.email_id = emails.id) AS "has_draft!"
+        FROM emails
+        WHERE id = $1 AND account_id = $2
+        "#,
+        email_id,
+        account_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    let email = match email {
+        Some(e) => e,
+        None => return Ok(None),
+    };
+
+    let labels = email.labels.unwrap_or_default();
+    let facts = EmailFacts {
+        sender: email.sender.as_deref().unwrap_or(""),
+        subject: email.subject.as_deref().unwrap_or(""),
+        body: email.body_text.as_deref().unwrap_or(""),
+        labels: &labels,
+    };
+
+    let rules = db::rules::list_enabled(account_id)
+        .await
+        .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    for rule in rules {
+        let matcher = match rule.conditions.compile() {
+            Ok(m) => m,
+            Err(e) => {
+                log::warn!("skipping draft rule {}: {}", rule.id, e);
+                continue;
+            }
+        };
+        if !matcher.matches(&facts) {
+            continue;
+        }
+
+        let mut outcome = RuleOutcome { rule_id: rule.id, actions: None, draft_id: None };
+
+        if !rule.actions.is_empty() {
+            gmail_triage::apply(account_id, user_email, std::slice::from_ref(&email.gmail_id), &rule.actions)
+                .await
+                .map_err(|e| e.to_string())?;
+            outcome.actions = Some(rule.actions.clone());
+        }
+
+        if rule.generate_draft && !email.has_draft {
+            // a deleted template leaves the rule generating replies from scratch
+            let template = match rule.template_id {
+                Some(id) => db::templates::get(account_id, id)
+                    .await
+                    .map_err(|e| format!("db fetch error: {:?}", e))?,
+                None => None,
+            };
+            outcome.draft_id = match template {
+                Some(template) => Some(template_draft(user_email, email_id, &facts, &rule, &template).await?),
+                None => {
+                    let options = DraftOptions {
+                        tone: &rule.tone,
+                        instructions: rule.instructions.as_deref(),
+                        created_by: None,
+                        rule_id: Some(rule.id),
+                        use_examples: true,
+                        use_knowledge: true,
+                        use_style: true,
+                    };
+                    let draft = draft_generator::generate_for_email(account_id, email_id, &options).await?;
+                    draft.map(|d| d.id)
+                }
+            };
+        }
+
+        return Ok(Some(outcome));
+    }
+
+    Ok(None)
+}
+
+/// Drafts a reply from the rule's template, filled in from the email. When some variable has
+/// no value the model adapts the template in the rule's tone, filling it from the email.
+async fn template_draft(user_email: &str, email_id: i32, email: &EmailFacts<'_>, rule: &Rule, template: &Template) -> Result<i32, String> {
+    let builtins = templates::builtin_values(
+        Some(email.sender),
+        Some(email.subject).filter(|s| !s.is_empty()),
+        user_email,
+        Utc::now().date_naive(),
+    );
+    let rendered = templates::render(&template.body, &template.fields, &builtins, &HashMap::new());
+
+    let adapted = !rendered.missing.is_empty();
+    let content = if adapted {
+        let prompt = AdaptPrompt {
+            template: &rendered.content,
+            sender: email.sender,
+            subject: email.subject,
+            email_body: email.body,
+            tone: &rule.tone,
+        };
+        templates::adapt(&prompt).await?
+    } else {
+        rendered.content
+    };
+
+    let warnings = guardrails::check(
+        &content,
+        &guardrails::Context {
+            email_body: email.body,
+            knowledge: &[],
+            instructions: rule.instructions.as_deref(),
+        },
+    );
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO drafts (email_id, user_email, content, tone, account_id, rule_id, template_id, warnings)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING id
+        "#,
+        email_id,
+        user_email,
+        content,
+        adapted.then_some(rule.tone.as_str()),
+        rule.account_id,
+        rule.id,
+        template.id,
+        serde_json::json!(warnings)
+    )
+    .fetch_one(db::get_pool())
+    .await
+    .map_err(|e| format!("db insert error: {:?}", e))?;
+
+    Ok(row.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email<'a>(sender: &'a str, subject: &'a str, labels: &'a [String]) -> EmailFacts<'a> {
+        EmailFacts {
+            sender,
+            subject,
+            body: "Hello, could you send me a quote for 20 chairs?",
+            labels,
+        }
+    }
+
+    fn matches(conditions: Conditions, email: &EmailFacts) -> bool {
+        conditions.compile().unwrap().matches(email)
+    }
+
+    #[test]
+    fn compile_rejects_empty_and_bad_regex() {
+        assert!(Conditions::default().compile().is_err());
+        let bad = Conditions { subject_regex: Some("(unclosed".to_string()), ..Default::default() };
+        assert!(bad.compile().is_err());
+    }
+
+    #[test]
+    fn senders_and_domains() {
+        let e = email("Jane Doe <Jane@Sales.Acme.com>", "Quote", &[]);
+        assert!(matches(Conditions { senders: vec!["jane@sales.acme.com".to_string()], ..Default::default() }, &e));
+        assert!(!matches(Conditions { senders: vec!["bob@acme.com".to_string()], ..Default::default() }, &e));
+        assert!(matches(Conditions { domains: vec!["@acme.com".to_string()], ..Default::default() }, &e));
+        assert!(!matches(Conditions { domains: vec!["me.com".to_string()], ..Default::default() }, &e));
+    }
+
+    #[test]
+    fn subject_regex_is_case_insensitive() {
+        let e = email("jane@acme.com", "RE: Quote request #42", &[]);
+        assert!(matches(Conditions { subject_regex: Some(r"quote request #\d+".to_string()), ..Default::default() }, &e));
+        assert!(!matches(Conditions { subject_regex: Some("^invoice".to_string()), ..Default::default() }, &e));
+    }
+
+    #[test]
+    fn labels_must_all_be_present() {
+        let labels = ["INBOX".to_string(), "IMPORTANT".to_string()];
+        let e = email("jane@acme.com", "Quote", &labels);
+        assert!(matches(Conditions { labels: vec!["inbox".to_string(), "important".to_string()], ..Default::default() }, &e));
+        assert!(!matches(Conditions { labels: vec!["INBOX".to_string(), "STARRED".to_string()], ..Default::default() }, &e));
+    }
+
+    #[test]
+    fn any_keyword_in_subject_or_body() {
+        let e = email("jane@acme.com", "Hello", &[]);
+        assert!(matches(Conditions { keywords: vec!["invoice".to_string(), " QUOTE ".to_string()], ..Default::default() }, &e));
+        assert!(!matches(Conditions { keywords: vec!["invoice".to_string()], ..Default::default() }, &e));
+    }
+
+    #[test]
+    fn every_condition_must_hold() {
+        let e = email("jane@acme.com", "Quote", &[]);
+        let conditions = Conditions {
+            domains: vec!["acme.com".to_string()],
+            keywords: vec!["invoice".to_string()],
+            ..Default::default()
+        };
+        assert!(!matches(conditions, &e));
+    }
+}
@@ -0,0 +1,291 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+/// Which kinds of records a search returns
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scope {
+    All,
+    Emails,
+    Drafts,
+}
+
+/// A parsed search. Free text is kept in `websearch_to_tsquery` syntax; the operators
+/// become structured filters.
+pub struct SearchQuery {
+    pub text: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub subject: Option<String>,
+    /// Labels that must all be present, compared case-insensitively
+    pub labels: Vec<String>,
+    /// Labels that must be absent
+    pub without_labels: Vec<String>,
+    pub after: Option<NaiveDateTime>,
+    pub before: Option<NaiveDateTime>,
+    pub has_draft: Option<bool>,
+    /// Draft status: `draft`, `pending_approval`, `approved` or `sent`
+    pub status: Option<String>,
+    pub scope: Scope,
+}
+
+struct Token {
+    negated: bool,
+    key: Option<String>,
+    value: String,
+    quoted: bool,
+}
+
+/// Splits a query into words, `"quoted phrases"`, `key:value` and `key:"quoted value"`
+/// operators, each optionally negated with a leading `-`
+fn tokenize(q: &str) -> Vec<Token> {
+    let chars: Vec<char> = q.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let negated = chars[i] == '-';
+        if negated {
+            i += 1;
+        }
+
+        let mut key = None;
+        let mut value = String::new();
+        let mut quoted = false;
+
+        while i < chars.len() && !chars[i].is_whitespace() {
+            match chars[i] {
+                '"' => {
+                    quoted = true;
+                    i += 1;
+                    while i < chars.len() && chars[i] != '"' {
+                        value.push(chars[i]);
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                ':' if key.is_none() && !quoted && !value.is_empty() => {
+                    key = Some(std::mem::take(&mut value).to_lowercase());
+                    i += 1;
+                }
+                c => {
+                    value.push(c);
+                    i += 1;
+                }
+            }
+        }
+
+        if !value.is_empty() {
+            tokens.push(Token { negated, key, value, quoted });
+        }
+    }
+
+    tokens
+}
+
+/// Parses a date from `after:`/`before:` as `YYYY/MM/DD` or `YYYY-MM-DD`
+fn parse_date(value: &str) -> Result<NaiveDateTime, String> {
+    NaiveDate::parse_from_str(value, "%Y/%m/%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+        .map(|d| d.and_hms_opt(0, 0, 0).unwrap_or_default())
+        .map_err(|_| format!("invalid date {}, use YYYY/MM/DD", value))
+}
+
+/// Parses a relative age from `newer_than:`/`older_than:` such as `7d`, `2m` or `1y` and
+/// returns the time that long before `now`
+fn parse_age(value: &str, now: NaiveDateTime) -> Result<NaiveDateTime, String> {
+    let invalid = || format!("invalid age {}, use e.g. 7d, 2m or 1y", value);
+    let (split, unit) = value.char_indices().last().ok_or_else(invalid)?;
+    let n: i64 = value[..split].parse().map_err(|_| invalid())?;
+    let days = match unit {
+        'd' => Some(n),
+        'm' => n.checked_mul(30),
+        'y' => n.checked_mul(365),
+        _ => None,
+    };
+    days.and_then(Duration::try_days)
+        .and_then(|age| now.checked_sub_signed(age))
+        .ok_or_else(invalid)
+}
+
+/// Label id Gmail uses for `is:`/`in:` shortcuts
+fn system_label(value: &str) -> Option<&'static str> {
+    match value {
+        "unread" => Some("UNREAD"),
+        "starred" => Some("STARRED"),
+        "important" => Some("IMPORTANT"),
+        "inbox" => Some("INBOX"),
+        "sent" => Some("SENT"),
+        "spam" => Some("SPAM"),
+        "trash" => Some("TRASH"),
+        _ => None,
+    }
+}
+
+impl SearchQuery {
+    /// Parses Gmail-like syntax, e.g.
+    /// `from:acme.com subject:"renewal" after:2024/01/01 -label:newsletter has:draft invoice OR quote`.
+    /// `now` anchors `newer_than:`/`older_than:`.
+    pub fn parse(q: &str, now: NaiveDateTime) -> Result<SearchQuery, String> {
+        let mut query = SearchQuery {
+            text: None,
+            from: None,
+            to: None,
+            subject: None,
+            labels: Vec::new(),
+            without_labels: Vec::new(),
+            after: None,
+            before: None,
+            has_draft: None,
+            status: None,
+            scope: Scope::All,
+        };
+        let mut text: Vec<String> = Vec::new();
+
+        for token in tokenize(q) {
+            let Token { negated, key, value, quoted } = token;
+            let key = match key {
+                Some(k) => k,
+                None => {
+                    let term = if quoted { format!("\"{}\"", value) } else { value };
+                    text.push(if negated { format!("-{}", term) } else { term });
+                    continue;
+                }
+            };
+
+            let lower = value.to_lowercase();
+            match (key.as_str(), negated) {
+                ("from", false) => query.from = Some(value),
+                ("to", false) => query.to = Some(value),
+                ("subject", false) => query.subject = Some(value),
+                ("label", false) => query.labels.push(value),
+                ("label", true) => query.without_labels.push(value),
+                ("is", _) if lower == "read" => {
+                    let labels = if negated { &mut query.labels } else { &mut query.without_labels };
+                    labels.push("UNREAD".to_string());
+                }
+                ("is" | "in", _) if system_label(&lower).is_some() => {
+                    let label = system_label(&lower).unwrap_or_default().to_string();
+                    if negated {
+                        query.without_labels.push(label);
+                    } else {
+                        query.labels.push(label);
+                    }
+                }
+                ("in", false) if lower == "drafts" => query.scope = Scope::Drafts,
+                ("in", false) if lower == "emails" => query.scope = Scope::Emails,
+                ("has", _) if lower == "draft" => query.has_draft = Some(!negated),
+                ("status", false) => query.status = Some(lower),
+                ("after", false) => query.after = Some(parse_date(&value)?),
+                ("before", false) => query.before = Some(parse_date(&value)?),
+                ("newer_than", false) => query.after = Some(parse_age(&lower, now)?),
+                ("older_than", false) => query.before = Some(parse_age(&lower, now)?),
+                (
+                    "from" | "to" | "subject" | "status" | "after" | "before" | "newer_than" | "older_than",
+                    true,
+                ) => return Err(format!("-{}: is not supported", key)),
+                ("is" | "in" | "has", _) => return Err(format!("unknown {}:{}", key, value)),
+                // not an operator we know, e.g. a time like 10:30; search it as text
+                _ => text.push(format!("\"{}:{}\"", key, value)),
+            }
+        }
+
+        if !text.is_empty() {
+            query.text = Some(text.join(" "));
+        }
+        Ok(query)
+    }
+}
+
+/// `ILIKE` pattern matching `value` anywhere
+pub fn contains_pattern(value: &str) -> String {
+    format!(
+        "%{}%",
+        value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, 15).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    fn parse(q: &str) -> SearchQuery {
+        SearchQuery::parse(q, now()).unwrap()
+    }
+
+    #[test]
+    fn operators_become_filters_and_the_rest_text() {
+        let query = parse("from:acme.com subject:renewal invoice OR quote");
+        assert_eq!(query.from.as_deref(), Some("acme.com"));
+        assert_eq!(query.subject.as_deref(), Some("renewal"));
+        assert_eq!(query.text.as_deref(), Some("invoice OR quote"));
+    }
+
+    #[test]
+    fn quoted_values_keep_their_spaces() {
+        let query = parse(r#"subject:"contract renewal" "next week""#);
+        assert_eq!(query.subject.as_deref(), Some("contract renewal"));
+        assert_eq!(query.text.as_deref(), Some(r#""next week""#));
+    }
+
+    #[test]
+    fn negation() {
+        let query = parse("-label:newsletter -spam -has:draft");
+        assert_eq!(query.without_labels, vec!["newsletter"]);
+        assert_eq!(query.text.as_deref(), Some("-spam"));
+        assert_eq!(query.has_draft, Some(false));
+        assert!(SearchQuery::parse("-from:acme.com", now()).is_err());
+    }
+
+    #[test]
+    fn read_and_unread() {
+        let query = parse("is:read");
+        assert_eq!(query.without_labels, vec!["UNREAD"]);
+        assert!(query.labels.is_empty());
+
+        let query = parse("-is:unread");
+        assert_eq!(query.without_labels, vec!["UNREAD"]);
+
+        let query = parse("is:unread in:inbox");
+        assert_eq!(query.labels, vec!["UNREAD", "INBOX"]);
+    }
+
+    #[test]
+    fn unknown_operators_are_searched_as_text() {
+        let query = parse("meeting 10:30");
+        assert_eq!(query.text.as_deref(), Some(r#"meeting "10:30""#));
+        assert!(SearchQuery::parse("is:whatever", now()).is_err());
+    }
+
+    #[test]
+    fn dates_and_ages() {
+        let query = parse("after:2024/01/01 before:2024-02-01");
+        assert_eq!(query.after, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 0, 0));
+        assert_eq!(query.before, NaiveDate::from_ymd_opt(2024, 2, 1).unwrap().and_hms_opt(0, 0, 0));
+
+        let query = parse("newer_than:7d older_than:1y");
+        assert_eq!(query.after, Some(now() - Duration::days(7)));
+        assert_eq!(query.before, Some(now() - Duration::days(365)));
+    }
+
+    #[test]
+    fn bad_dates_and_ages_are_errors() {
+        for q in ["after:2024/13/01", "before:yesterday", "newer_than:7w", "newer_than:d", "older_than:7"] {
+            assert!(SearchQuery::parse(q, now()).is_err(), "{}", q);
+        }
+    }
+
+    #[test]
+    fn ages_that_used_to_panic_are_errors() {
+        for q in ["newer_than:7é", "newer_than:99999999999d", "older_than:999999999999999999y", "newer_than:é"] {
+            assert!(SearchQuery::parse(q, now()).is_err(), "{}", q);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use actix_web::cookie::{time::Duration, Cookie, SameSite};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use crate::config;
+
+/// Name of the HttpOnly cookie carrying the session JWT
+pub const SESSION_COOKIE: &str = "drafly_session";
+
+/// Name of the HttpOnly cookie carrying the refresh token, only sent to `/auth`
+pub const REFRESH_COOKIE: &str = "drafly_refresh";
+
+/// Generates a random opaque token (login codes, refresh tokens, ...)
+pub fn new_opaque_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// SHA-256 hex digest used to store opaque tokens without keeping the raw value
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn same_site() -> SameSite {
+    match config::session_cookie_same_site().to_lowercase().as_str() {
+        "strict" => SameSite::Strict,
+        "none" => SameSite::None,
+        _ => SameSite::Lax,
+    }
+}
+
+fn build_cookie(name: &'static str, value: String, path: &'static str, max_age: Duration) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .path(path)
+        .http_only(true)
+        .secure(config::session_cookie_secure())
+        .same_site(same_site())
+        .max_age(max_age)
+        .finish()
+}
+
+/// Builds the HttpOnly session cookie for a freshly issued JWT
+pub fn session_cookie(jwt: &str, max_age: Duration) -> Cookie<'static> {
+    build_cookie(SESSION_COOKIE, jwt.to_string(), "/", max_age)
+}
+
+/// Builds the HttpOnly refresh cookie, scoped to the auth endpoints
+pub fn refresh_cookie(refresh_token: &str, max_age: Duration) -> Cookie<'static> {
+    build_cookie(REFRESH_COOKIE, refresh_token.to_string(), "/auth", max_age)
+}
+
+/// Expired copies of both cookies, used on logout
+pub fn cleared_cookies() -> [Cookie<'static>; 2] {
+    [
+        build_cookie(SESSION_COOKIE, String::new(), "/", Duration::ZERO),
+        build_cookie(REFRESH_COOKIE, String::new(), "/auth", Duration::ZERO),
+    ]
+}
@@ -0,0 +1,72 @@
+use serde_json::Value;
+use crate::db::signatures::Signature;
+use crate::services::gmail_client;
+use crate::services::gmail_fetcher::html_to_text;
+use crate::services::google_oauth::GoogleError;
+
+/// Put before the signature in plain text so mail clients recognise and fold it
+const PLAIN_SEPARATOR: &str = "\n\n-- \n";
+
+/// A reply body ready to send, in plain text and, when the signature has HTML, in HTML
+pub struct Body {
+    pub plain: String,
+    pub html: Option<String>,
+}
+
+/// Strips scripts, styles and event handlers from signature HTML
+pub fn sanitize_html(html: &str) -> String {
+    ammonia::clean(html).trim().to_string()
+}
+
+/// Plain-text version of an HTML signature
+pub fn plain_from_html(html: &str) -> String {
+    html_to_text(html).trim_end().to_string()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Appends the signature to a draft's content
+pub fn append(content: &str, signature: &Signature) -> Body {
+    let content = content.trim_end();
+    let plain = match signature.plain.as_deref() {
+        Some(sig) => format!("{}{}{}", content, PLAIN_SEPARATOR, sig.trim_end()),
+        None => content.to_string(),
+    };
+    let html = signature.html.as_deref().map(|sig| {
+        format!(
+            "<div dir=\"ltr\">{}</div><br><div class=\"gmail_signature\">{}</div>",
+            escape_html(content).replace('\n', "<br>"),
+            sig
+        )
+    });
+    Body { plain, html }
+}
+
+/// The HTML signature Gmail adds for the mailbox's own address (or its default sendAs
+/// alias); `None` when it has none
+pub async fn fetch_from_gmail(user_email: &str) -> Result<Option<String>, GoogleError> {
+    let url = format!("{}/settings/sendAs", gmail_client::GMAIL_API);
+    let (status, text) = gmail_client::send(user_email, |c| c.get(&url)).await?;
+    if !status.is_success() {
+        return Err(format!("gmail sendAs error {} : {}", status, text).into());
+    }
+
+    let json: Value = serde_json::from_str(&text).map_err(|e| format!("json parse: {:?}", e))?;
+    let aliases = json["sendAs"].as_array().cloned().unwrap_or_default();
+    let alias = aliases
+        .iter()
+        .find(|a| a["sendAsEmail"].as_str().is_some_and(|e| e.eq_ignore_ascii_case(user_email)))
+        .or_else(|| aliases.iter().find(|a| a["isDefault"].as_bool() == Some(true)))
+        .or_else(|| aliases.iter().find(|a| a["isPrimary"].as_bool() == Some(true)));
+
+    Ok(alias
+        .and_then(|a| a["signature"].as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string()))
+}
@@ -0,0 +1,391 @@
+use std::collections::{HashMap, HashSet};
+use regex::Regex;
+use crate::db::style_profiles::StyleProfile;
+
+pub const FORMALITIES: [&str; 3] = ["formal", "neutral", "casual"];
+
+/// Sent messages read when learning a profile, unless the request says otherwise
+pub const DEFAULT_SAMPLE: usize = 50;
+/// Fewer usable messages than this say too little about how someone writes
+pub const MIN_SAMPLE: usize = 3;
+
+/// Phrases kept in a profile
+const MAX_PHRASES: usize = 8;
+
+/// Openers recognised as a greeting; longer ones first so `good morning` wins over `morning`
+const GREETINGS: [&str; 10] = [
+    "good afternoon", "good evening", "good morning", "greetings", "hello", "hiya", "morning", "dear", "hey", "hi",
+];
+
+/// Closers recognised as a sign-off; longer ones first so `best regards` wins over `best`
+const SIGN_OFFS: [&str; 18] = [
+    "yours sincerely", "best regards", "kind regards", "warm regards", "best wishes", "many thanks",
+    "thanks again", "all the best", "thank you", "talk soon", "take care", "sincerely", "regards", "warmly",
+    "thanks", "cheers", "best", "thx",
+];
+
+/// Words a greeting can address instead of a name
+const GROUP_ADDRESSES: [&str; 6] = ["all", "everyone", "folks", "guys", "team", "there"];
+
+/// Words that don't make a phrase distinctive on their own
+const STOPWORDS: [&str; 24] = [
+    "a", "an", "and", "are", "as", "at", "be", "for", "i", "in", "is", "it", "me", "of", "on", "or", "so", "that",
+    "the", "this", "to", "we", "with", "you",
+];
+
+/// What one sent message shows about its writer
+struct MessageStyle {
+    greeting: Option<String>,
+    sign_off: Option<String>,
+    words: usize,
+    /// 0 (casual) to 1 (formal)
+    formality: f32,
+    /// Distinct 3 and 4 word sequences of the message body
+    ngrams: HashSet<String>,
+}
+
+/// A profile learned from sent mail
+pub struct Analysis {
+    pub greeting: Option<String>,
+    pub sign_off: Option<String>,
+    pub typical_words: i32,
+    pub formality: String,
+    pub common_phrases: Vec<String>,
+    pub sample_count: i32,
+}
+
+/// Drops what the writer didn't write: quoted replies, forwarded messages and the
+/// signature block after a `-- ` line
+fn strip_quoted(body: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in body.replace("\r\n", "\n").lines() {
+        let trimmed = line.trim();
+        if trimmed.ends_with("wrote:") {
+            // Gmail wraps long attributions: "On Mon, Jan 8, ... <ann@x.com>\nwrote:"
+            if lines.last().is_some_and(|l| l.trim_start().starts_with("On ")) {
+                lines.pop();
+            }
+            break;
+        }
+        if trimmed == "--" || trimmed.starts_with("-----Original Message") || trimmed.starts_with("---------- Forwarded") {
+            break;
+        }
+        if trimmed.starts_with('>') {
+            continue;
+        }
+        lines.push(line.trim_end().to_string());
+    }
+
+    let start = lines.iter().position(|l| !l.trim().is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|l| !l.trim().is_empty()).map_or(start, |i| i + 1);
+    lines[start..end].to_vec()
+}
+
+/// Text of `line` after the phrase it starts with, if it starts with one of `phrases`
+/// followed by a word boundary
+fn strip_phrase<'a>(line: &'a str, phrases: &[&str]) -> Option<(&'a str, &'a str)> {
+    let lower = line.to_lowercase();
+    phrases.iter().find_map(|p| {
+        let boundary = lower.strip_prefix(p)?.chars().next().is_none_or(|c| !c.is_alphanumeric());
+        (boundary && line.is_char_boundary(p.len())).then(|| line.split_at(p.len()))
+    })
+}
+
+/// The greeting form of a first line, with the name replaced: `Hi Ann,` gives `Hi <name>,`
+fn greeting_of(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.split_whitespace().count() > 6 {
+        return None;
+    }
+    let (opener, rest) = strip_phrase(line, &GREETINGS)?;
+
+    // "Hi Ann, thanks for the note." greets Ann and goes on with the message
+    let (addressee, punctuation) = match rest.find(',') {
+        Some(i) => (rest[..i].trim(), ",".to_string()),
+        None => {
+            let punctuation: String = rest.chars().rev().take_while(|c| ",.!:;-".contains(*c)).collect();
+            (rest.trim_end_matches(|c| ",.!:;-".contains(c)).trim(), punctuation.chars().rev().collect())
+        }
+    };
+    let addressee = if addressee.is_empty() {
+        String::new()
+    } else if GROUP_ADDRESSES.contains(&addressee.to_lowercase().as_str()) {
+        format!(" {}", addressee)
+    } else {
+        " <name>".to_string()
+    };
+    Some(format!("{}{}{}", opener, addressee, punctuation))
+}
+
+/// The sign-off line among the last lines of a message and its index; `Thanks, Ann`
+/// gives `Thanks,`
+fn sign_off_of(lines: &[String]) -> Option<(usize, String)> {
+    let candidates = lines.iter().enumerate().rev().filter(|(_, l)| !l.trim().is_empty()).take(4);
+    for (i, line) in candidates {
+        let line = line.trim();
+        let Some((closer, rest)) = strip_phrase(line, &SIGN_OFFS) else { continue };
+        let rest = rest.trim();
+        // "Thanks for the update." is a sentence, not a sign-off
+        if rest.is_empty() || rest.chars().all(|c| ",.!".contains(c)) {
+            return Some((i, format!("{}{}", closer, rest)));
+        }
+        if rest.strip_prefix(',').is_some_and(|name| name.split_whitespace().count() <= 3) {
+            return Some((i, format!("{},", closer)));
+        }
+    }
+    None
+}
+
+/// Markers of casual and formal writing
+struct FormalityMarkers {
+    casual: Regex,
+    formal: Regex,
+}
+
+impl FormalityMarkers {
+    fn new() -> FormalityMarkers {
+        FormalityMarkers {
+            casual: Regex::new(
+                r"(?i)\b(i'm|you're|we're|they're|it's|that's|there's|what's|don't|doesn't|didn't|can't|won't|isn't|aren't|wasn't|couldn't|wouldn't|shouldn't|i've|we've|you've|i'll|we'll|you'll|i'd|we'd|let's|hey|thx|btw|lol|gonna|wanna|yeah|yep|awesome|cool|cheers)\b|!",
+            )
+            .expect("valid regex"),
+            formal: Regex::new(
+                r"(?i)\b(i am|you are|we are|it is|do not|does not|did not|cannot|will not|is not|are not|i have|we have|i will|we will|i would|we would|dear|sincerely|regards|kindly|please find|further to|do not hesitate|at your earliest convenience)\b",
+            )
+            .expect("valid regex"),
+        }
+    }
+
+    /// 0 (casual) to 1 (formal); 0.5 when there are no markers
+    fn score(&self, text: &str) -> f32 {
+        let text = text.replace('\u{2019}', "'");
+        let casual = self.casual.find_iter(&text).count() as f32;
+        let formal = self.formal.find_iter(&text).count() as f32;
+        (formal + 1.0) / (formal + casual + 2.0)
+    }
+}
+
+/// Word sequences of 3 and 4 words that don't cross a sentence
+fn ngrams(text: &str) -> HashSet<String> {
+    let word_re = Regex::new(r"[a-z][a-z']*").expect("valid regex");
+    let mut grams = HashSet::new();
+    for sentence in text.to_lowercase().replace('\u{2019}', "'").split(['.', '!', '?', '\n']) {
+        let words: Vec<&str> = word_re.find_iter(sentence).map(|m| m.as_str()).collect();
+        for n in [3, 4] {
+            for window in words.windows(n) {
+                if window.iter().all(|w| STOPWORDS.contains(w)) {
+                    continue;
+                }
+                grams.insert(window.join(" "));
+            }
+        }
+    }
+    grams
+}
+
+fn message_style(body: &str, markers: &FormalityMarkers) -> Option<MessageStyle> {
+    let lines = strip_quoted(body);
+    let first = lines.first()?;
+
+    let greeting = greeting_of(first);
+    let sign_off = sign_off_of(&lines);
+    let start = usize::from(greeting.is_some());
+    let end = sign_off.as_ref().map_or(lines.len(), |(i, _)| *i);
+    let text = lines.get(start..end.max(start)).unwrap_or_default().join("\n");
+
+    let words = text.split_whitespace().count();
+    if words == 0 {
+        return None;
+    }
+
+    let all = lines.join("\n");
+    Some(MessageStyle {
+        greeting,
+        sign_off: sign_off.map(|(_, s)| s),
+        words,
+        formality: markers.score(&all),
+        ngrams: ngrams(&text),
+    })
+}
+
+/// The value used by at least a third of the messages, most used first
+fn habit(values: impl Iterator<Item = Option<String>>, total: usize) -> Option<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for value in values.flatten() {
+        *counts.entry(value).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(_, n)| n * 3 >= total)
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(value, _)| value)
+}
+
+/// Phrases found in several messages, longest and most used first, without ones
+/// contained in a phrase already picked
+fn common_phrases(styles: &[MessageStyle]) -> Vec<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for style in styles {
+        for gram in &style.ngrams {
+            *counts.entry(gram.as_str()).or_default() += 1;
+        }
+    }
+
+    let min_messages = (styles.len() / 10).max(2);
+    let mut ranked: Vec<(&str, usize)> = counts.into_iter().filter(|(_, n)| *n >= min_messages).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.len().cmp(&a.0.len())).then_with(|| a.0.cmp(b.0)));
+
+    let mut picked: Vec<String> = Vec::new();
+    for (gram, _) in ranked {
+        if picked.len() == MAX_PHRASES {
+            break;
+        }
+        if picked.iter().any(|p| p.contains(gram) || gram.contains(p.as_str())) {
+            continue;
+        }
+        picked.push(gram.to_string());
+    }
+    picked
+}
+
+/// Learns greeting and sign-off habits, typical length, formality and recurring phrases
+/// from sent message bodies. Fails when fewer than `MIN_SAMPLE` messages have text of
+/// their own.
+pub fn analyze(bodies: &[String]) -> Result<Analysis, String> {
+    let markers = FormalityMarkers::new();
+    let styles: Vec<MessageStyle> = bodies.iter().filter_map(|b| message_style(b, &markers)).collect();
+    if styles.len() < MIN_SAMPLE {
+        return Err(format!(
+            "found {} sent messages with text of their own; at least {} are needed",
+            styles.len(),
+            MIN_SAMPLE
+        ));
+    }
+
+    let mut words: Vec<usize> = styles.iter().map(|s| s.words).collect();
+    words.sort_unstable();
+    let formality = styles.iter().map(|s| s.formality).sum::<f32>() / styles.len() as f32;
+
+    Ok(Analysis {
+        greeting: habit(styles.iter().map(|s| s.greeting.clone()), styles.len()),
+        sign_off: habit(styles.iter().map(|s| s.sign_off.clone()), styles.len()),
+        typical_words: words[words.len() / 2] as i32,
+        formality: match formality {
+            f if f >= 0.6 => "formal",
+            f if f <= 0.4 => "casual",
+            _ => "neutral",
+        }
+        .to_string(),
+        common_phrases: common_phrases(&styles),
+        sample_count: styles.len() as i32,
+    })
+}
+
+/// How a profile is described to the model. The sign-off is left out when the mailbox's
+/// signature closes the reply instead.
+pub fn prompt_section(profile: &StyleProfile, include_sign_off: bool) -> String {
+    let mut lines = Vec::new();
+    if let Some(greeting) = &profile.greeting {
+        lines.push(format!("- Greeting: {} (with the sender's name for <name>)", greeting));
+    }
+    if let Some(sign_off) = profile.sign_off.as_ref().filter(|_| include_sign_off) {
+        lines.push(format!("- Sign-off: {}", sign_off));
+    }
+    if let Some(words) = profile.typical_words {
+        lines.push(format!("- Length: about {} words", words));
+    }
+    if let Some(formality) = &profile.formality {
+        lines.push(format!("- Formality: {}", formality));
+    }
+    if !profile.common_phrases.is_empty() {
+        let phrases: Vec<String> = profile.common_phrases.iter().map(|p| format!("\"{}\"", p)).collect();
+        lines.push(format!("- Phrases they often use, where they fit naturally: {}", phrases.join(", ")));
+    }
+    if let Some(notes) = profile.notes.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        lines.push(format!("- Notes from the writer: {}", notes));
+    }
+    if lines.is_empty() {
+        return String::new();
+    }
+    format!("Writing style of the person replying. Write as they would:\n{}\n\n", lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn greetings_replace_the_name() {
+        assert_eq!(greeting_of("Hi Ann,").as_deref(), Some("Hi <name>,"));
+        assert_eq!(greeting_of("Good morning Mr. Lee!").as_deref(), Some("Good morning <name>!"));
+        assert_eq!(greeting_of("Hey team,").as_deref(), Some("Hey team,"));
+        assert_eq!(greeting_of("Hello,").as_deref(), Some("Hello,"));
+        assert_eq!(greeting_of("Hi Ann, thanks for the note.").as_deref(), Some("Hi <name>,"));
+    }
+
+    #[test]
+    fn other_first_lines_are_not_greetings() {
+        assert_eq!(greeting_of("Highlights from today's call"), None);
+        assert_eq!(greeting_of("Dear all, here is the long list of everything we agreed on today"), None);
+        assert_eq!(greeting_of("Thanks for the update."), None);
+    }
+
+    #[test]
+    fn sign_offs_drop_the_name() {
+        assert_eq!(sign_off_of(&lines("Sounds good.\n\nBest regards,\nAnn Lee")), Some((2, "Best regards,".to_string())));
+        assert_eq!(sign_off_of(&lines("See you then.\nCheers!")), Some((1, "Cheers!".to_string())));
+        assert_eq!(sign_off_of(&lines("See you then.\nThanks, Ann")), Some((1, "Thanks,".to_string())));
+    }
+
+    #[test]
+    fn sentences_are_not_sign_offs() {
+        assert_eq!(sign_off_of(&lines("Thanks for the update.\nI'll check tomorrow.")), None);
+        assert_eq!(sign_off_of(&lines("Bestseller list attached.")), None);
+    }
+
+    #[test]
+    fn quoted_text_forwards_and_signatures_are_stripped() {
+        let body = "\nHi Ann,\n\nSounds good.\n\nOn Mon, Jan 8, 2024 at 10:00 AM Ann Lee <ann@x.com>\nwrote:\n> Can we meet?";
+        assert_eq!(strip_quoted(body), vec!["Hi Ann,", "", "Sounds good."]);
+
+        let body = "Works for me.\n> earlier text\nSee you.\n-- \nAnn Lee | Acme";
+        assert_eq!(strip_quoted(body), vec!["Works for me.", "See you."]);
+
+        let body = "FYI\r\n\r\n---------- Forwarded message ---------\r\nFrom: bob@x.com";
+        assert_eq!(strip_quoted(body), vec!["FYI"]);
+    }
+
+    #[test]
+    fn formality_markers() {
+        let markers = FormalityMarkers::new();
+        assert_eq!(markers.score("See attached."), 0.5);
+        assert!(markers.score("Hey! I'm sure it's fine, don't worry. Cheers") < 0.4);
+        assert!(markers.score("Dear Ms. Lee, please find attached the report. I am available; do not hesitate to call. Kind regards") > 0.6);
+        // curly apostrophes count as well
+        assert!(markers.score("I\u{2019}m sure it\u{2019}s fine") < 0.5);
+    }
+
+    #[test]
+    fn analyze_finds_habits() {
+        let bodies: Vec<String> = ["Ann", "Bob", "Carl", "Dana"]
+            .iter()
+            .map(|name| format!("Hi {},\n\nThanks for reaching out. I'll take a look and get back to you soon!\n\nCheers,\nSam\n\nOn Mon, {} wrote:\n> question", name, name))
+            .collect();
+        let analysis = analyze(&bodies).unwrap();
+        assert_eq!(analysis.greeting.as_deref(), Some("Hi <name>,"));
+        assert_eq!(analysis.sign_off.as_deref(), Some("Cheers,"));
+        assert_eq!(analysis.formality, "casual");
+        assert_eq!(analysis.sample_count, 4);
+        assert!(analysis.common_phrases.iter().any(|p| p.contains("get back to you")));
+    }
+
+    #[test]
+    fn analyze_needs_enough_messages() {
+        let bodies = vec!["Hi,\n\nSure.".to_string(), "> only quoted".to_string()];
+        assert!(analyze(&bodies).is_err());
+    }
+}
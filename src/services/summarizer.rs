@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use crate::db::approvals::content_hash;
+use crate::services::groq_ai;
+
+/// Characters of each message body sent to the model
+const MESSAGE_CHARS: usize = 3000;
+/// Messages of a long thread that are summarized, newest kept
+const MAX_MESSAGES: usize = 20;
+
+/// One message to summarize
+pub struct Message {
+    pub id: i32,
+    pub sender: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Summary {
+    pub summary: String,
+    #[serde(default)]
+    pub key_asks: Vec<String>,
+    #[serde(default)]
+    pub deadlines: Vec<String>,
+    #[serde(default)]
+    pub open_questions: Vec<String>,
+}
+
+/// Identifies the exact messages a summary was written from
+pub fn source_hash(messages: &[Message]) -> String {
+    let source: String = messages
+        .iter()
+        .map(|m| format!("{}\u{1f}{}\u{1f}{}\u{1e}", m.id, m.subject, m.body))
+        .collect();
+    content_hash(&source)
+}
+
+pub async fn summarize(messages: &[Message]) -> Result<Summary, String> {
+    let skip = messages.len().saturating_sub(MAX_MESSAGES);
+    let transcript: String = messages
+        .iter()
+        .skip(skip)
+        .enumerate()
+        .map(|(i, m)| {
+            let body: String = m.body.chars().take(MESSAGE_CHARS).collect();
+            format!("--- Message {} ---\nFrom: {}\nSubject: {}\n\n{}\n\n", i + 1, m.sender, m.subject, body)
+        })
+        .collect();
+
+    let prompt = format!(
+        r#"Summarize this email conversation for someone who has to approve a reply to it.
+
+{}
+Answer with a JSON object only, no other text:
+{{"summary": "two or three sentences",
+ "key_asks": ["what the correspondents ask us to do"],
+ "deadlines": ["dates or time limits mentioned, with what they apply to"],
+ "open_questions": ["questions that have not been answered yet"]}}
+
+Use empty lists when there is nothing to report. Do not invent facts."#,
+        transcript
+    );
+
+    groq_ai::complete_json("You summarize email threads. You answer with JSON only.", &prompt, 600, 0.2).await
+}
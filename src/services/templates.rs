@@ -0,0 +1,250 @@
+use std::collections::{BTreeSet, HashMap};
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::db::approvals::sender_address;
+use crate::services::groq_ai;
+
+/// Variables every template can use, filled in from the email being answered
+pub const BUILTINS: [&str; 6] = ["sender_name", "sender_first_name", "sender_email", "subject", "mailbox_email", "today"];
+
+/// Characters of the email body shown to the model when adapting a template
+const BODY_CHARS: usize = 4000;
+
+/// A custom variable a template declares
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    /// Shown to the person filling in the field
+    pub label: Option<String>,
+    /// Used when no value is given
+    pub default: Option<String>,
+}
+
+fn placeholder() -> Regex {
+    Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").expect("valid regex")
+}
+
+/// Variable names used in `body`, in order of first use
+pub fn variables(body: &str) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    placeholder()
+        .captures_iter(body)
+        .map(|c| c[1].to_string())
+        .filter(|name| seen.insert(name.clone()))
+        .collect()
+}
+
+/// Checks that field names are usable and that the body only uses built-in or declared variables
+pub fn validate(body: &str, fields: &[Field]) -> Result<(), String> {
+    let name_re = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").expect("valid regex");
+    let mut declared = BTreeSet::new();
+
+    for field in fields {
+        if !name_re.is_match(&field.name) {
+            return Err(format!("invalid field name {:?}: use letters, digits and _", field.name));
+        }
+        if BUILTINS.contains(&field.name.as_str()) {
+            return Err(format!("{} is a built-in variable", field.name));
+        }
+        if !declared.insert(field.name.as_str()) {
+            return Err(format!("field {} is declared twice", field.name));
+        }
+    }
+
+    let unknown: Vec<String> = variables(body)
+        .into_iter()
+        .filter(|v| !BUILTINS.contains(&v.as_str()) && !declared.contains(v.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!("unknown variables: {} (declare them in fields)", unknown.join(", ")));
+    }
+    Ok(())
+}
+
+/// Display name of a `From` header: `Ann Lee <ann@x.com>` gives `Ann Lee`, a bare address
+/// gives its local part, e.g. `ann.lee@x.com` gives `Ann Lee`
+fn display_name(sender: &str) -> String {
+    if let Some(start) = sender.find('<') {
+        let name = sender[..start].trim().trim_matches('"').trim();
+        if !name.is_empty() {
+            return name.to_string();
+        }
+    }
+    let address = sender_address(sender);
+    let local = address.split('@').next().unwrap_or("");
+    local
+        .split(['.', '_', '-'])
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Values of the built-in variables for a reply to `sender` about `subject`
+pub fn builtin_values(sender: Option<&str>, subject: Option<&str>, mailbox_email: &str, today: NaiveDate) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    if let Some(sender) = sender.filter(|s| !s.trim().is_empty()) {
+        let name = display_name(sender);
+        values.insert("sender_first_name".to_string(), name.split_whitespace().next().unwrap_or("").to_string());
+        values.insert("sender_name".to_string(), name);
+        values.insert("sender_email".to_string(), sender_address(sender));
+    }
+    if let Some(subject) = subject {
+        values.insert("subject".to_string(), subject.to_string());
+    }
+    values.insert("mailbox_email".to_string(), mailbox_email.to_string());
+    values.insert("today".to_string(), today.format("%B %-d, %Y").to_string());
+    values
+}
+
+#[derive(Serialize)]
+pub struct Rendered {
+    pub content: String,
+    /// Variables without a value; they are left as `{{name}}` in `content`
+    pub missing: Vec<String>,
+}
+
+/// Fills in a template. Given values win over field defaults, which win over built-ins.
+pub fn render(body: &str, fields: &[Field], builtins: &HashMap<String, String>, given: &HashMap<String, String>) -> Rendered {
+    let value_of = |name: &str| -> Option<String> {
+        given
+            .get(name)
+            .cloned()
+            .or_else(|| fields.iter().find(|f| f.name == name).and_then(|f| f.default.clone()))
+            .or_else(|| builtins.get(name).cloned())
+            .filter(|v| !v.is_empty())
+    };
+
+    let mut missing = Vec::new();
+    let content = placeholder()
+        .replace_all(body, |caps: &regex::Captures| match value_of(&caps[1]) {
+            Some(value) => value,
+            None => {
+                if !missing.contains(&caps[1].to_string()) {
+                    missing.push(caps[1].to_string());
+                }
+                caps[0].to_string()
+            }
+        })
+        .into_owned();
+
+    Rendered { content, missing }
+}
+
+/// What the model needs to adapt a filled-in template to an email
+pub struct AdaptPrompt<'a> {
+    pub template: &'a str,
+    pub sender: &'a str,
+    pub subject: &'a str,
+    pub email_body: &'a str,
+    pub tone: &'a str,
+}
+
+/// Has the model tailor a filled-in template to the email: it keeps the template's
+/// substance and wording where it fits and fills any `{{placeholders}}` left from the email
+pub async fn adapt(p: &AdaptPrompt<'_>) -> Result<String, String> {
+    let body: String = p.email_body.chars().take(BODY_CHARS).collect();
+    let prompt = format!(
+        r#"Adapt this reply template to the email below, in a {} tone.
+
+Rules:
+- Keep the template's facts, commitments, links and overall structure; change wording only where
+  the email calls for it
+- Answer the specific points of the email that the template covers; do not add new promises
+- Replace any remaining {{{{placeholder}}}} with the right value from the email. If the email
+  doesn't give it, rephrase the sentence so it isn't needed
+- Output only the reply body, without headers or comments
+
+Template:
+{}
+
+Email:
+From: {}
+Subject: {}
+Body: {}
+
+Adapted reply:"#,
+        p.tone,
+        p.template,
+        p.sender,
+        if p.subject.is_empty() { "No subject" } else { p.subject },
+        body
+    );
+
+    groq_ai::complete(
+        "You are a professional email assistant. You tailor saved reply templates to the email being answered.",
+        &prompt,
+        700,
+        0.4,
+    )
+    .await?
+    .filter(|s| !s.trim().is_empty())
+    .ok_or_else(|| "model returned no reply".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, default: Option<&str>) -> Field {
+        Field { name: name.to_string(), label: None, default: default.map(str::to_string) }
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn variables_in_order_of_first_use() {
+        assert_eq!(variables("{{ b }} {{a}} {{b}} {{ not valid-}} {single}"), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn validate_checks_fields_and_variables() {
+        assert!(validate("Hi {{sender_first_name}}, {{amount}}", &[field("amount", None)]).is_ok());
+        assert!(validate("{{amount}}", &[]).is_err());
+        assert!(validate("", &[field("bad-name", None)]).is_err());
+        assert!(validate("", &[field("today", None)]).is_err());
+        assert!(validate("", &[field("amount", None), field("amount", None)]).is_err());
+    }
+
+    #[test]
+    fn given_values_win_over_defaults_and_builtins() {
+        let fields = [field("amount", Some("$10")), field("sender_name", Some("Friend"))];
+        let builtins = values(&[("sender_name", "Carl Lee"), ("today", "January 8, 2024")]);
+
+        let rendered = render("{{sender_name}}: {{amount}} ({{today}})", &fields, &builtins, &HashMap::new());
+        assert_eq!(rendered.content, "Friend: $10 (January 8, 2024)");
+
+        let rendered = render("{{sender_name}}: {{amount}}", &fields, &builtins, &values(&[("amount", "$20")]));
+        assert_eq!(rendered.content, "Friend: $20");
+    }
+
+    #[test]
+    fn missing_values_are_left_in_place_and_listed_once() {
+        let rendered = render("{{ref}} and {{ ref }}, {{note}}", &[field("ref", None), field("note", None)], &HashMap::new(), &values(&[("note", "")]));
+        assert_eq!(rendered.content, "{{ref}} and {{ ref }}, {{note}}");
+        assert_eq!(rendered.missing, vec!["ref", "note"]);
+    }
+
+    #[test]
+    fn builtins_from_the_sender() {
+        let today = NaiveDate::from_ymd_opt(2024, 1, 8).unwrap();
+        let v = builtin_values(Some("\"Ann Lee\" <Ann@X.com>"), Some("Order"), "me@x.com", today);
+        assert_eq!(v["sender_name"], "Ann Lee");
+        assert_eq!(v["sender_first_name"], "Ann");
+        assert_eq!(v["sender_email"], "ann@x.com");
+        assert_eq!(v["today"], "January 8, 2024");
+
+        let v = builtin_values(Some("carl.de-vries@x.com"), None, "me@x.com", today);
+        assert_eq!(v["sender_name"], "Carl De Vries");
+        assert!(!v.contains_key("subject"));
+    }
+}
@@ -0,0 +1,87 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use crate::config;
+
+const NONCE_LEN: usize = 12;
+
+/// A secret encrypted with a random per-row data key, which is itself
+/// wrapped with the master key identified by `key_id`
+pub struct EncryptedSecret {
+    pub ciphertext: String,
+    pub encrypted_dek: String,
+    pub key_id: String,
+}
+
+/// Encrypts with the active master key (`TOKEN_ENCRYPTION_KEY_ID`)
+pub fn encrypt(plaintext: &str) -> Result<EncryptedSecret, String> {
+    encrypt_with_key(plaintext, &config::token_encryption_key_id())
+}
+
+pub fn encrypt_with_key(plaintext: &str, key_id: &str) -> Result<EncryptedSecret, String> {
+    let master = master_cipher(key_id)?;
+
+    let dek = Aes256Gcm::generate_key(OsRng);
+    let ciphertext = seal(&Aes256Gcm::new(&dek), plaintext.as_bytes())?;
+    let encrypted_dek = seal(&master, dek.as_slice())?;
+
+    Ok(EncryptedSecret {
+        ciphertext,
+        encrypted_dek,
+        key_id: key_id.to_string(),
+    })
+}
+
+pub fn decrypt(ciphertext: &str, encrypted_dek: &str, key_id: &str) -> Result<String, String> {
+    let master = master_cipher(key_id)?;
+
+    let dek = open(&master, encrypted_dek)?;
+    if dek.len() != 32 {
+        return Err("invalid data key length".into());
+    }
+    let plaintext = open(&Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&dek)), ciphertext)?;
+
+    String::from_utf8(plaintext).map_err(|e| format!("decrypted secret is not utf8: {:?}", e))
+}
+
+fn master_cipher(key_id: &str) -> Result<Aes256Gcm, String> {
+    let keys = config::token_encryption_keys();
+    let encoded = keys
+        .get(key_id)
+        .ok_or_else(|| format!("unknown token encryption key id: {}", key_id))?;
+
+    let bytes = STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("token encryption key {} is not base64: {:?}", key_id, e))?;
+    if bytes.len() != 32 {
+        return Err(format!("token encryption key {} must be 32 bytes", key_id));
+    }
+
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)))
+}
+
+/// Returns base64(nonce || ciphertext)
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8]) -> Result<String, String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ct = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|e| format!("encrypt error: {:?}", e))?;
+
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&ct);
+    Ok(STANDARD.encode(out))
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &str) -> Result<Vec<u8>, String> {
+    let bytes = STANDARD
+        .decode(sealed)
+        .map_err(|e| format!("ciphertext is not base64: {:?}", e))?;
+    if bytes.len() <= NONCE_LEN {
+        return Err("ciphertext too short".into());
+    }
+
+    let (nonce, ct) = bytes.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ct)
+        .map_err(|e| format!("decrypt error: {:?}", e))
+}
@@ -0,0 +1,55 @@
+use crate::db;
+use crate::services::embeddings::{self, Provider};
+
+/// Re-embeds emails and drafts whose vectors come from an earlier version of the local
+/// embedding, then drops the old vectors. Local vectors are computed in process, so this
+/// runs in the background at startup; other providers are reindexed through the API.
+pub async fn refresh_local_embeddings() {
+    tokio::spawn(async {
+        match refresh().await {
+            Ok(0) => {}
+            Ok(n) => log::info!("re-embedded {} emails and drafts with the current local model", n),
+            Err(e) => log::error!("refreshing local embeddings failed: {}", e),
+        }
+    });
+}
+
+async fn refresh() -> Result<usize, String> {
+    let provider = Provider::from_env()?;
+    if !matches!(provider, Provider::Local) {
+        return Ok(0);
+    }
+    let model = provider.model();
+
+    let retired = db::embeddings::retired_local(&model)
+        .await
+        .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    let mut refreshed = 0;
+    for (account_id, source_type, source_id) in &retired {
+        let result = match source_type.as_str() {
+            "email" => embeddings::index_email(*account_id, *source_id).await,
+            "draft" => match draft_content(*source_id).await? {
+                Some(content) => embeddings::index(&provider, *account_id, "draft", *source_id, &content).await,
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        };
+        result?;
+        refreshed += 1;
+    }
+
+    db::embeddings::delete_retired_local(&model)
+        .await
+        .map_err(|e| format!("db delete error: {:?}", e))?;
+    Ok(refreshed)
+}
+
+async fn draft_content(draft_id: i32) -> Result<Option<String>, String> {
+    let row = sqlx::query!("SELECT content FROM drafts WHERE id = $1", draft_id)
+        .fetch_optional(db::get_pool())
+        .await
+        .map_err(|e| format!("db fetch error: {:?}", e))?;
+
+    Ok(row.and_then(|r| r.content))
+}
@@ -0,0 +1,14 @@
+use crate::{config, db};
+
+/// `drafly rotate-token-keys`: re-encrypts every stored Google refresh token with the
+/// key named by `TOKEN_ENCRYPTION_KEY_ID`. Add the new key to `TOKEN_ENCRYPTION_KEYS`
+/// and point `TOKEN_ENCRYPTION_KEY_ID` at it first; drop the old key once this finishes.
+pub async fn rotate_token_keys() -> Result<(), String> {
+    let key_id = config::token_encryption_key_id();
+    log::info!("Re-encrypting refresh tokens with key {}", key_id);
+
+    let rewritten = db::mailbox_accounts::reencrypt_all(&key_id).await?;
+
+    println!("[KEY ROTATION] Re-encrypted {} refresh token(s) with key {}", rewritten, key_id);
+    Ok(())
+}
@@ -1 +1,3 @@
-pub mod cron;
\ No newline at end of file
+pub mod cron;
+pub mod key_rotation;
+pub mod embeddings;